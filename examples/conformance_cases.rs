@@ -0,0 +1,385 @@
+// Generates tests/conformance/cases.json:
+//
+//   cargo run --example conformance_cases [OUT]
+//
+// Expected states come from the small reference model below rather than
+// from the interpreter, so a bug in one doesn't hide itself in the other.
+
+use std::fs;
+
+use chip8::conformance::{Case, State};
+
+const OUT: &str = "tests/conformance/cases.json";
+const PROFILES: [&str; 3] = ["modern", "vip", "schip"];
+// Where Processor::new puts the fonts.
+const FONT_BASE: u16 = 0x000;
+const BIG_FONT_BASE: u16 = 0x050;
+const VF: usize = 0xF;
+
+fn main() {
+    let out = std::env::args().nth(1).unwrap_or_else(|| String::from(OUT));
+    let mut cases = Cases::default();
+    flow(&mut cases);
+    skips(&mut cases);
+    loads(&mut cases);
+    alu(&mut cases);
+    memory(&mut cases);
+    draw(&mut cases);
+    keys(&mut cases);
+    timers(&mut cases);
+    schip(&mut cases);
+    faults(&mut cases);
+
+    let lines: Vec<String> = cases
+        .0
+        .iter()
+        .map(|case| serde_json::to_string(case).expect("cases serialize"))
+        .collect();
+    fs::write(&out, format!("[\n{}\n]\n", lines.join(",\n"))).unwrap_or_else(|err| panic!("{}: {}", out, err));
+    println!("saved {} cases to {}", lines.len(), out);
+}
+
+#[derive(Default)]
+struct Cases(Vec<Case>);
+
+impl Cases {
+    fn add(&mut self, name: impl Into<String>, opcode: u16, quirks: &str, initial: State, expected: State) {
+        self.0.push(Case {
+            name: name.into(),
+            opcode: format!("{:04X}", opcode),
+            quirks: (quirks != "modern").then(|| quirks.to_string()),
+            initial,
+            expected,
+            fault: None,
+        });
+    }
+
+    fn add_fault(&mut self, name: &str, opcode: u16, initial: State, expected: State, fault: &str) {
+        self.add(name, opcode, "modern", initial, expected);
+        self.0.last_mut().unwrap().fault = Some(fault.to_string());
+    }
+}
+
+fn regs(values: &[(usize, u8)]) -> State {
+    State {
+        v: values.iter().map(|&(x, value)| (format!("{:X}", x), value)).collect(),
+        ..State::default()
+    }
+}
+
+// `state` after an instruction that moved on to the next one.
+fn next(state: State) -> State {
+    at(state, 0x202)
+}
+
+fn at(state: State, pc: u16) -> State {
+    State { pc: Some(pc), ..state }
+}
+
+fn ram(state: State, addr: u16, bytes: &[u8]) -> State {
+    let mut ram = state.ram.clone();
+    ram.insert(format!("{:#05x}", addr), bytes.to_vec());
+    State { ram, ..state }
+}
+
+fn flow(cases: &mut Cases) {
+    cases.add("00E0 clears the screen", 0x00E0, "modern", State { pixels: Some(vec![[0, 0], [63, 31], [10, 5]]), ..State::default() }, next(State { pixels: Some(vec![]), ..State::default() }));
+    cases.add("00EE returns past the call", 0x00EE, "modern", State { stack: Some(vec![0x300]), pc: Some(0x40A), ..State::default() }, at(State { stack: Some(vec![]), ..State::default() }, 0x302));
+    cases.add("00EE pops only the top", 0x00EE, "modern", State { stack: Some(vec![0x300, 0x456]), ..State::default() }, at(State { stack: Some(vec![0x300]), ..State::default() }, 0x458));
+    let full: Vec<u16> = (0..16).map(|depth| 0x200 + 2 * depth).collect();
+    cases.add("00EE from a full stack", 0x00EE, "modern", State { stack: Some(full.clone()), ..State::default() }, at(State { stack: Some(full[..15].to_vec()), ..State::default() }, 0x220));
+    for nnn in [0x000, 0x200, 0x202, 0xABC, 0xFFE] {
+        cases.add(format!("1nnn jumps to {:03X}", nnn), 0x1000 | nnn, "modern", State::default(), at(State::default(), nnn));
+    }
+    cases.add("2nnn pushes PC", 0x2345, "modern", State::default(), at(State { stack: Some(vec![0x200]), ..State::default() }, 0x345));
+    cases.add("2nnn pushes onto a non-empty stack", 0x2ABC, "modern", State { stack: Some(vec![0x300]), pc: Some(0x410), ..State::default() }, at(State { stack: Some(vec![0x300, 0x410]), ..State::default() }, 0xABC));
+    cases.add("2nnn fills the stack", 0x2400, "modern", State { stack: Some(full[..15].to_vec()), ..State::default() }, at(State { stack: Some(full[..15].iter().copied().chain([0x200]).collect()), ..State::default() }, 0x400));
+    for (profile, offset_reg) in [("modern", 0), ("vip", 0), ("schip", 3)] {
+        for (base, offset) in [(0x300, 0x00), (0x300, 0x10), (0x3F0, 0xFF)] {
+            let initial = regs(&[(0, offset), (3, 0x22)]);
+            let offset = if offset_reg == 0 { offset } else { 0x22 };
+            cases.add(format!("Bnnn {} jumps to {:03X}+V{}", profile, base, offset_reg), 0xB000 | base, profile, initial, at(State::default(), base + offset as u16));
+        }
+    }
+}
+
+fn skips(cases: &mut Cases) {
+    for x in [0x1, VF] {
+        for (value, kk) in [(0x12, 0x12), (0x12, 0x13), (0x00, 0x00), (0xFF, 0x00)] {
+            let initial = regs(&[(x, value)]);
+            let equal = value == kk;
+            let op = (x as u16) << 8 | kk as u16;
+            cases.add(format!("3xkk V{:X}={:02X} kk={:02X}", x, value, kk), 0x3000 | op, "modern", initial.clone(), skip_if(equal));
+            cases.add(format!("4xkk V{:X}={:02X} kk={:02X}", x, value, kk), 0x4000 | op, "modern", initial, skip_if(!equal));
+        }
+    }
+    for (x, y) in [(1, 2), (1, VF), (VF, 1), (4, 4)] {
+        for (vx, vy) in [(0x12, 0x12), (0x12, 0x21), (0x00, 0xFF)] {
+            let initial = regs(&[(x, vx), (y, vy)]);
+            // With x = y both read the same register, so it's always equal.
+            let equal = x == y || vx == vy;
+            let op = (x as u16) << 8 | (y as u16) << 4;
+            let values = regs(&[(x, vx), (y, vy)]).v;
+            let name = |op: &str| format!("{} V{:X}={:02X} V{:X}={:02X}", op, x, values[&format!("{:X}", x)], y, values[&format!("{:X}", y)]);
+            cases.add(name("5xy0"), 0x5000 | op, "modern", initial.clone(), skip_if(equal));
+            cases.add(name("9xy0"), 0x9000 | op, "modern", initial, skip_if(!equal));
+        }
+    }
+}
+
+fn skip_if(skip: bool) -> State {
+    at(State::default(), if skip { 0x204 } else { 0x202 })
+}
+
+fn loads(cases: &mut Cases) {
+    for (x, kk) in [(0x0, 0x00), (0x5, 0xAB), (VF, 0xFF)] {
+        cases.add(format!("6xkk V{:X}={:02X}", x, kk), 0x6000 | (x as u16) << 8 | kk as u16, "modern", regs(&[(x, 0x11)]), next(regs(&[(x, kk)])));
+    }
+    // 7xkk never touches VF, not even on overflow.
+    for (x, value, kk) in [(0x2, 0x01, 0x02), (0x2, 0xFF, 0x02), (0x2, 0x80, 0x80), (VF, 0xFE, 0x03)] {
+        let sum = ((value as u16 + kk as u16) % 256) as u8;
+        let initial = if x == VF { regs(&[(x, value)]) } else { regs(&[(x, value), (VF, 0x55)]) };
+        cases.add(format!("7xkk V{:X}={:02X} + {:02X}", x, value, kk), 0x7000 | (x as u16) << 8 | kk as u16, "modern", initial, next(regs(&[(x, sum)])));
+    }
+    for nnn in [0x000, 0x123, 0xFFF] {
+        cases.add(format!("Annn I={:03X}", nnn), 0xA000 | nnn, "modern", State { i: Some(0x0F0), ..State::default() }, next(State { i: Some(nnn), ..State::default() }));
+    }
+    // Only a zero mask pins down the random number.
+    cases.add("Cxkk with kk=00 is always 0", 0xC500, "modern", regs(&[(5, 0x77)]), next(regs(&[(5, 0)])));
+}
+
+// The 8xyN arithmetic, written out independently of the interpreter. Returns
+// the result for Vx and the flag for VF, if the instruction sets one.
+fn reference(n: u16, vx: u8, vy: u8, profile: &str) -> (u8, Option<u8>) {
+    let (vx, vy) = (vx as i32, vy as i32);
+    let shift_vx = profile != "vip";
+    let reset = if profile == "vip" { Some(0) } else { None };
+    let (result, flag) = match n {
+        0x0 => (vy, None),
+        0x1 => (vx | vy, reset),
+        0x2 => (vx & vy, reset),
+        0x3 => (vx ^ vy, reset),
+        0x4 => (vx + vy, Some((vx + vy > 255) as u8)),
+        0x5 => (vx - vy, Some((vx >= vy) as u8)),
+        0x7 => (vy - vx, Some((vy >= vx) as u8)),
+        0x6 => {
+            let source = if shift_vx { vx } else { vy };
+            (source / 2, Some((source % 2) as u8))
+        }
+        0xE => {
+            let source = if shift_vx { vx } else { vy };
+            (source * 2, Some((source / 128) as u8))
+        }
+        _ => unreachable!(),
+    };
+    (result.rem_euclid(256) as u8, flag)
+}
+
+fn alu(cases: &mut Cases) {
+    let values = [(0x00, 0x00), (0x01, 0x02), (0x0F, 0xF0), (0x80, 0x80), (0xFF, 0x01), (0xFF, 0xFF), (0x05, 0x03), (0x03, 0x05), (0x81, 0x7F)];
+    for n in [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE] {
+        let profiles: &[&str] = if [0x1, 0x2, 0x3, 0x6, 0xE].contains(&n) { &PROFILES } else { &PROFILES[..1] };
+        for &profile in profiles {
+            for (x, y) in [(1, 2), (VF, 1), (1, VF), (3, 3)] {
+                for (a, b) in values {
+                    alu_case(cases, n, profile, x, y, a, b);
+                }
+            }
+        }
+    }
+}
+
+fn alu_case(cases: &mut Cases, n: u16, profile: &str, x: usize, y: usize, a: u8, b: u8) {
+    // VF starts out as something no flag can be, unless it's an operand.
+    let mut v = [0x00; 16];
+    v[VF] = 0x55;
+    v[x] = a;
+    v[y] = b;
+    let initial = regs(&[(VF, v[VF]), (x, v[x]), (y, v[y])]);
+    let (result, flag) = reference(n, v[x], v[y], profile);
+    // The flag is written after the result, so it wins when x is F.
+    let mut after = v;
+    after[x] = result;
+    if let Some(flag) = flag {
+        after[VF] = flag;
+    }
+    let name = format!("8xy{:X} {} V{:X}={:02X} V{:X}={:02X}", n, profile, x, v[x], y, v[y]);
+    let op = 0x8000 | (x as u16) << 8 | (y as u16) << 4 | n;
+    cases.add(name, op, profile, initial, next(regs(&[(x, after[x]), (VF, after[VF])])));
+}
+
+fn memory(cases: &mut Cases) {
+    for (i, value) in [(0x300, 0x01), (0x300, 0xFF), (0xFFF, 0x01), (0xFFFF, 0x01)] {
+        let sum = (i as u32 + value as u32) as u16;
+        cases.add(format!("Fx1E I={:03X} + {:02X}", i, value), 0xF41E, "modern", State { i: Some(i), ..regs(&[(4, value)]) }, next(State { i: Some(sum), ..State::default() }));
+    }
+    for digit in [0x0, 0x7, 0xF, 0x1A] {
+        let glyph = digit & 0xF;
+        cases.add(format!("Fx29 digit {:X}", digit), 0xF229, "modern", regs(&[(2, digit)]), next(State { i: Some(FONT_BASE + 5 * glyph as u16), ..State::default() }));
+        cases.add(format!("Fx30 digit {:X}", digit), 0xF230, "modern", regs(&[(2, digit)]), next(State { i: Some(BIG_FONT_BASE + 10 * glyph as u16), ..State::default() }));
+    }
+    for value in [0, 7, 10, 99, 100, 123, 255] {
+        let digits = [value / 100, value / 10 % 10, value % 10];
+        cases.add(format!("Fx33 {}", value), 0xF333, "modern", State { i: Some(0x300), ..regs(&[(3, value)]) }, next(ram(State::default(), 0x300, &digits)));
+    }
+    // BCD digits past the end of RAM wrap around to the start.
+    let initial = State { i: Some(0xFFF), ..regs(&[(3, 234)]) };
+    cases.add("Fx33 wraps around RAM", 0xF333, "modern", initial, next(ram(ram(State::default(), 0xFFF, &[2]), 0x000, &[3, 4])));
+
+    let values: Vec<u8> = (0..16).map(|x| (x as u8 + 1) * 0x0F).collect();
+    let all: Vec<(usize, u8)> = values.iter().copied().enumerate().collect();
+    for profile in PROFILES {
+        let increment = profile == "vip";
+        for x in [0x0, 0x3, VF] {
+            let count = x + 1;
+            let i_after = |i: u16| if increment { i.wrapping_add(count as u16) } else { i };
+            // Fx55: V0..=Vx go out, the registers after x don't.
+            let initial = State { i: Some(0x300), ..regs(&all) };
+            let expected = State { i: Some(i_after(0x300)), ..ram(State::default(), 0x300, &values[..count]) };
+            cases.add(format!("Fx55 {} x={:X}", profile, x), 0xF055 | (x as u16) << 8, profile, initial, next(expected));
+
+            // Fx65: V0..=Vx come in, the registers after x keep their values.
+            let stored: Vec<u8> = (0..16).map(|byte| 0xA0 + byte as u8).collect();
+            let initial = ram(State { i: Some(0x300), ..regs(&all) }, 0x300, &stored);
+            let loaded: Vec<(usize, u8)> = stored[..count].iter().copied().enumerate().collect();
+            let expected = State { i: Some(i_after(0x300)), ..regs(&loaded) };
+            cases.add(format!("Fx65 {} x={:X}", profile, x), 0xF065 | (x as u16) << 8, profile, initial, next(expected));
+        }
+    }
+    // Both wrap around the end of RAM.
+    let initial = State { i: Some(0xFFE), ..regs(&[(0, 1), (1, 2), (2, 3)]) };
+    cases.add("Fx55 wraps around RAM", 0xF255, "modern", initial, next(ram(ram(State::default(), 0xFFE, &[1, 2]), 0x000, &[3])));
+    let initial = ram(ram(State { i: Some(0xFFF), ..State::default() }, 0xFFF, &[7]), 0x000, &[8]);
+    cases.add("Fx65 wraps around RAM", 0xF165, "modern", initial, next(regs(&[(0, 7), (1, 8)])));
+}
+
+// Pixels lit by XORing `sprite` onto `lit` at (x, y), wrapping or clipping
+// at the edges, and whether any pixel was turned off.
+fn reference_draw(lit: &[[u8; 2]], x: usize, y: usize, sprite: &[u8], clip: bool) -> (Vec<[u8; 2]>, bool) {
+    let mut screen = [[false; 64]; 32];
+    for &[px, py] in lit {
+        screen[py as usize][px as usize] = true;
+    }
+    let (x, y) = (x % 64, y % 32);
+    let mut collision = false;
+    for (row, byte) in sprite.iter().enumerate() {
+        for col in 0..8 {
+            if byte & (0x80 >> col) == 0 || (clip && (x + col >= 64 || y + row >= 32)) {
+                continue;
+            }
+            let pixel = &mut screen[(y + row) % 32][(x + col) % 64];
+            collision |= *pixel;
+            *pixel = !*pixel;
+        }
+    }
+    let mut pixels = Vec::new();
+    for (py, line) in screen.iter().enumerate() {
+        for (px, &on) in line.iter().enumerate() {
+            if on {
+                pixels.push([px as u8, py as u8]);
+            }
+        }
+    }
+    (pixels, collision)
+}
+
+fn draw(cases: &mut Cases) {
+    let sprite = [0xF0, 0x90, 0x81];
+    let layouts: [(&str, usize, usize, Vec<[u8; 2]>); 7] = [
+        ("at the origin", 0, 0, vec![]),
+        ("in the middle", 20, 10, vec![]),
+        ("over lit pixels", 0, 0, vec![[0, 0], [5, 5]]),
+        ("next to lit pixels", 0, 0, vec![[4, 0], [1, 1]]),
+        ("across the right edge", 60, 4, vec![]),
+        ("across the bottom edge", 4, 30, vec![]),
+        ("across the corner", 61, 31, vec![[0, 0]]),
+    ];
+    for profile in PROFILES {
+        let clip = profile != "modern";
+        for (place, x, y, lit) in &layouts {
+            let (pixels, collision) = reference_draw(lit, *x, *y, &sprite, clip);
+            let initial = ram(State { i: Some(0x300), pixels: Some(lit.clone()), ..regs(&[(1, *x as u8), (2, *y as u8), (VF, 0x55)]) }, 0x300, &sprite);
+            let expected = State { pixels: Some(pixels), ..regs(&[(VF, collision as u8)]) };
+            cases.add(format!("Dxyn {} {}", profile, place), 0xD123, profile, initial, next(expected));
+        }
+        // The starting position always wraps, even when clipping.
+        let (pixels, _) = reference_draw(&[], 66, 40, &sprite, clip);
+        let initial = ram(State { i: Some(0x300), ..regs(&[(1, 66), (2, 40)]) }, 0x300, &sprite);
+        cases.add(format!("Dxyn {} wraps the start position", profile), 0xD123, profile, initial, next(State { pixels: Some(pixels), ..regs(&[(VF, 0)]) }));
+    }
+
+    // VF as a coordinate, then overwritten by the collision flag.
+    let (pixels, collision) = reference_draw(&[[8, 3]], 8, 3, &[0x80], false);
+    let initial = ram(State { i: Some(0x300), pixels: Some(vec![[8, 3]]), ..regs(&[(VF, 8), (1, 3)]) }, 0x300, &[0x80]);
+    cases.add("Dxyn with VF as x", 0xDF11, "modern", initial, next(State { pixels: Some(pixels), ..regs(&[(VF, collision as u8)]) }));
+
+    // Fifteen rows, and sprite bytes read past the end of RAM wrap around.
+    let tall: Vec<u8> = (0..15).map(|row| 1 << (row % 8)).collect();
+    let (pixels, _) = reference_draw(&[], 0, 0, &tall, false);
+    let initial = ram(State { i: Some(0x300), ..regs(&[(1, 0), (2, 0)]) }, 0x300, &tall);
+    cases.add("Dxyn 15 rows", 0xD12F, "modern", initial, next(State { pixels: Some(pixels), ..regs(&[(VF, 0)]) }));
+    let (pixels, _) = reference_draw(&[], 0, 0, &[0xC0, 0x30], false);
+    let initial = ram(ram(State { i: Some(0xFFF), ..regs(&[(1, 0), (2, 0)]) }, 0xFFF, &[0xC0]), 0x000, &[0x30]);
+    cases.add("Dxyn wraps around RAM", 0xD122, "modern", initial, next(State { pixels: Some(pixels), ..regs(&[(VF, 0)]) }));
+}
+
+fn keys(cases: &mut Cases) {
+    for (value, held) in [(0x5, vec![0x5]), (0x5, vec![0x4, 0x6]), (0x0, vec![]), (0xF, vec![0xF, 0x0]), (0x15, vec![0x5])] {
+        let pressed = held.contains(&(value & 0xF));
+        let initial = State { keys: Some(held.clone()), ..regs(&[(3, value)]) };
+        cases.add(format!("Ex9E V3={:02X} held {:?}", value, held), 0xE39E, "modern", initial.clone(), skip_if(pressed));
+        cases.add(format!("ExA1 V3={:02X} held {:?}", value, held), 0xE3A1, "modern", initial, skip_if(!pressed));
+    }
+
+    // Fx0A waits for a key to go down and then up again.
+    let waiting = |key: Option<u8>, held: Vec<u8>| State { keys: Some(held), awaited_key: Some(key), ..regs(&[(6, 0x77)]) };
+    cases.add("Fx0A with no key held keeps waiting", 0xF60A, "modern", waiting(None, vec![]), at(State::default(), 0x200));
+    cases.add("Fx0A notes the first key held", 0xF60A, "modern", waiting(None, vec![0x7, 0xA]), at(State { awaited_key: Some(Some(0x7)), ..State::default() }, 0x200));
+    cases.add("Fx0A waits while the key is held", 0xF60A, "modern", waiting(Some(0x7), vec![0x7]), at(State::default(), 0x200));
+    cases.add("Fx0A ignores other keys", 0xF60A, "modern", waiting(Some(0x7), vec![0x7, 0x2]), at(State::default(), 0x200));
+    cases.add("Fx0A stores the released key", 0xF60A, "modern", waiting(Some(0x7), vec![]), next(State { awaited_key: Some(None), ..regs(&[(6, 0x7)]) }));
+    cases.add("Fx0A into VF", 0xFF0A, "modern", waiting(Some(0xC), vec![0x1]), next(State { awaited_key: Some(None), ..regs(&[(VF, 0xC)]) }));
+}
+
+fn timers(cases: &mut Cases) {
+    for value in [0x00, 0x01, 0xFF] {
+        cases.add(format!("Fx07 delay={:02X}", value), 0xFA07, "modern", State { delay_timer: Some(value), ..regs(&[(0xA, 0x33)]) }, next(regs(&[(0xA, value)])));
+        cases.add(format!("Fx15 delay={:02X}", value), 0xFA15, "modern", State { delay_timer: Some(0x33), ..regs(&[(0xA, value)]) }, next(State { delay_timer: Some(value), ..State::default() }));
+        cases.add(format!("Fx18 sound={:02X}", value), 0xFA18, "modern", State { sound_timer: Some(0x33), ..regs(&[(0xA, value)]) }, next(State { sound_timer: Some(value), ..State::default() }));
+    }
+}
+
+// SUPER-CHIP instructions. Apart from the resolution switches they are
+// decoded but don't do anything yet. 5xyn is decoded as 5xy0 whatever n is,
+// and V1 and V2 differ here.
+fn schip(cases: &mut Cases) {
+    let busy = State { i: Some(0x300), pixels: Some(vec![[1, 1], [40, 20]]), ..regs(&[(0, 1), (1, 2), (VF, 3)]) };
+    for op in [0x00C3, 0x00FB, 0x00FC, 0x00FD, 0xD120, 0xF175, 0xF185, 0x5121] {
+        cases.add(format!("{:04X} does nothing", op), op, "modern", busy.clone(), next(State::default()));
+    }
+    let cleared = |width, height| next(State { screen: Some([width, height]), pixels: Some(vec![]), ..State::default() });
+    let hires = State { screen: Some([128, 64]), pixels: Some(vec![[1, 1], [100, 60]]), ..busy.clone() };
+    cases.add("00FE switches to 64x32 and clears the screen", 0x00FE, "modern", hires.clone(), cleared(64, 32));
+    cases.add("00FE in 64x32 clears the screen", 0x00FE, "modern", busy.clone(), cleared(64, 32));
+    cases.add("00FF switches to 128x64 and clears the screen", 0x00FF, "modern", busy, cleared(128, 64));
+    cases.add("00FF in 128x64 clears the screen", 0x00FF, "modern", hires, cleared(128, 64));
+}
+
+// Instructions that used to panic. Crashes the fuzz targets find get
+// minimised into cases here. The processor now stops on them with PC left on
+// the instruction.
+fn faults(cases: &mut Cases) {
+    let full: Vec<u16> = (0..16).map(|depth| 0x300 + 2 * depth).collect();
+    cases.add_fault("00EE on an empty stack", 0x00EE, State { stack: Some(vec![]), ..State::default() }, State::default(), "stack underflow");
+    cases.add_fault("2nnn on a full stack", 0x2400, State { stack: Some(full), ..State::default() }, State::default(), "stack overflow");
+    cases.add_fault("Bnnn past the end of RAM", 0xBFFF, regs(&[(0, 0xFF)]), State::default(), "PC out of range at 0x10FE");
+    cases.add_fault("Bnnn to the last byte of RAM", 0xBFFF, regs(&[(0, 0x00)]), State::default(), "PC out of range at 0xFFF");
+    cases.add_fault("1nnn to the last byte of RAM", 0x1FFF, State::default(), State::default(), "PC out of range at 0xFFF");
+    cases.add_fault("3xkk skipping past the end of RAM", 0x3000, State { pc: Some(0xFFC), ..State::default() }, State::default(), "PC out of range at 0x1000");
+    // The instruction itself still runs, only moving on from it faults.
+    cases.add_fault("6xkk at the end of RAM", 0x6042, State { pc: Some(0xFFE), ..State::default() }, regs(&[(0, 0x42)]), "PC out of range at 0x1000");
+    // Opcodes that don't mean anything at all.
+    for op in [0x0123, 0x8128, 0x9121, 0xE1FF, 0xF1FF] {
+        cases.add_fault(&format!("{:04X} is unknown", op), op, State::default(), State::default(), &format!("unknown opcode {:04X}", op));
+    }
+}
@@ -0,0 +1,64 @@
+// Times sprite drawing on the packed framebuffer against one bool per pixel,
+// the way Dxyn used to draw, copying the whole screen after every sprite:
+//
+//   cargo run --release --example framebuffer_bench [SPRITES]
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use chip8::framebuffer::Framebuffer;
+use chip8::{HEIGHT, WIDTH};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const SPRITES: usize = 1_000_000;
+
+fn main() {
+    let count = std::env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(SPRITES);
+    let mut rng = StdRng::seed_from_u64(34);
+    let sprites: Vec<(usize, usize, Vec<u8>)> = (0..1024)
+        .map(|_| {
+            let rows = rng.gen_range(1..16);
+            (rng.gen_range(0..WIDTH), rng.gen_range(0..HEIGHT), (0..rows).map(|_| rng.gen()).collect())
+        })
+        .collect();
+
+    let mut packed = Framebuffer::new(WIDTH, HEIGHT);
+    let packed_time = time(|| {
+        for (x, y, sprite) in sprites.iter().cycle().take(count) {
+            black_box(packed.draw_sprite(*x, *y, sprite, false));
+        }
+    });
+
+    let mut cycle_buffer = vec![false; WIDTH * HEIGHT];
+    let mut pixels = vec![false; WIDTH * HEIGHT];
+    let bools_time = time(|| {
+        for (x, y, sprite) in sprites.iter().cycle().take(count) {
+            let mut collision = false;
+            for (row, byte) in sprite.iter().enumerate() {
+                for bit in 0..8 {
+                    if byte >> (7 - bit) & 1 == 1 {
+                        let pixel = &mut cycle_buffer[(y + row) % HEIGHT * WIDTH + (x + bit) % WIDTH];
+                        collision |= *pixel;
+                        *pixel = !*pixel;
+                    }
+                }
+            }
+            cycle_buffer.clone_into(&mut pixels);
+            black_box((collision, &pixels));
+        }
+    });
+
+    assert!(packed.pixels().eq(cycle_buffer.iter().copied()));
+    let rate = |elapsed: Duration| count as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    println!("{} sprites", count);
+    println!("one bool per pixel: {:>14.0} sprites/s", rate(bools_time));
+    println!("packed rows:        {:>14.0} sprites/s", rate(packed_time));
+    println!("speed-up:           {:>14.2}x", rate(packed_time) / rate(bools_time));
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
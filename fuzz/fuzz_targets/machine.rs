@@ -0,0 +1,42 @@
+#![no_main]
+
+// Arbitrary ROMs and key presses run through the whole machine for a few
+// frames, with the quirk profile and Cxkk seed picked by the fuzzer too.
+
+use arbitrary::Arbitrary;
+use chip8::chip_machine::{CHIPMachine, Status};
+use chip8::quirks::PROFILES;
+use chip8::{HEIGHT, WIDTH};
+use libfuzzer_sys::fuzz_target;
+
+// Enough to get through loops and subroutines without slowing the fuzzer down.
+const MAX_FRAMES: u8 = 16;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    rom: Vec<u8>,
+    // (frame, key, pressed)
+    keys: Vec<(u8, u8, bool)>,
+    profile: u8,
+    seed: u64,
+    frames: u8,
+}
+
+fuzz_target!(|input: Input| {
+    let mut chip8 = CHIPMachine::new(WIDTH, HEIGHT);
+    chip8.set_quirks(PROFILES[input.profile as usize % PROFILES.len()].1);
+    chip8.seed(input.seed);
+    chip8.load_bytes(input.rom);
+    for frame in 0..input.frames.min(MAX_FRAMES) {
+        for &(_, key, pressed) in input.keys.iter().filter(|(at, ..)| *at == frame) {
+            chip8.set_key(key, pressed);
+        }
+        chip8.run_frame();
+        if let Err(err) = chip8.cpu().check_invariants() {
+            panic!("frame {}: {}", frame, err);
+        }
+        if chip8.status() != Status::Running {
+            break;
+        }
+    }
+});
@@ -0,0 +1,64 @@
+#![no_main]
+
+// Arbitrary opcodes run one after another on an arbitrary processor state.
+// The state only has to keep the invariants `check_invariants` describes,
+// since no instruction can break them.
+
+use arbitrary::Arbitrary;
+use chip8::processor::{Processor, LAST_PC};
+use chip8::quirks::Quirks;
+use chip8::{HEIGHT, WIDTH};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    keypad: [bool; 16],
+    awaited_key: Option<u8>,
+    // Written from I on, for Dxyn, Fx65 and friends to read.
+    ram: Vec<u8>,
+    quirks: [bool; 5],
+    seed: u64,
+    opcodes: Vec<u16>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut cpu = Processor::new(WIDTH, HEIGHT);
+    let in_ram = |addr: u16| addr % (LAST_PC as u16 + 1);
+    cpu.v = input.v;
+    cpu.i = input.i;
+    cpu.pc = in_ram(input.pc);
+    for (entry, &addr) in cpu.stack.iter_mut().zip(&input.stack) {
+        *entry = in_ram(addr);
+    }
+    cpu.sp = input.stack.len().min(cpu.stack.len()) as u16;
+    cpu.delay_timer = input.delay_timer;
+    cpu.sound_timer = input.sound_timer;
+    cpu.keypad = input.keypad;
+    cpu.awaited_key = input.awaited_key.map(|key| key & 0xF);
+    for (offset, &byte) in input.ram.iter().enumerate() {
+        cpu.write_ram((input.i as usize + offset) % cpu.ram.len(), byte);
+    }
+    let [vf_reset, memory_increment, shift_vx, jump_vx, clip] = input.quirks;
+    cpu.quirks = Quirks {
+        vf_reset,
+        memory_increment,
+        shift_vx,
+        jump_vx,
+        clip,
+    };
+    cpu.seed(input.seed);
+    cpu.check_invariants().expect("the input makes a valid state");
+
+    for op in input.opcodes {
+        cpu.tick(op);
+        if let Err(err) = cpu.check_invariants() {
+            panic!("{:04X}: {}", op, err);
+        }
+    }
+});
@@ -0,0 +1,54 @@
+#[cfg(feature = "aot")]
+use crate::hash::fnv1a;
+use crate::processor::Processor;
+
+// A basic block translated to Rust ahead of time by `chip8 recompile`.
+// Only generated code builds these, so without the `aot` feature they go unused.
+#[cfg_attr(not(feature = "aot"), allow(dead_code))]
+#[derive(Debug)]
+pub struct Block {
+    pub start: u16,
+    // The block's instructions as they were in the ROM. It only runs while
+    // RAM still holds exactly these bytes.
+    pub code: &'static [u8],
+    pub instructions: usize,
+    // Runs every instruction in the block and leaves PC on the next one.
+    pub run: fn(&mut Processor),
+}
+
+#[cfg_attr(not(feature = "aot"), allow(dead_code))]
+#[derive(Debug)]
+pub struct Program {
+    pub rom_hash: u64,
+    // Sorted by start address.
+    pub blocks: &'static [Block],
+}
+
+impl Program {
+    // The block starting at PC, unless its code has been overwritten since the ROM was loaded.
+    pub fn block(&self, cpu: &Processor) -> Option<&'static Block> {
+        let index = self.blocks.binary_search_by_key(&cpu.pc, |block| block.start).ok()?;
+        let block = &self.blocks[index];
+        let start = block.start as usize;
+        (cpu.ram[start..start + block.code.len()] == *block.code).then_some(block)
+    }
+}
+
+// Built with `CHIP8_AOT_MODULE=<module.rs> cargo build --features aot`.
+#[cfg(feature = "aot")]
+mod generated {
+    #![allow(clippy::all)]
+    include!(env!("CHIP8_AOT_MODULE"));
+}
+
+// The recompiled program built into this binary, if it was made from `rom`.
+#[cfg(feature = "aot")]
+pub fn program_for(rom: &[u8]) -> Option<&'static Program> {
+    let program: &'static Program = &generated::PROGRAM;
+    (program.rom_hash == fnv1a(rom)).then_some(program)
+}
+
+#[cfg(not(feature = "aot"))]
+pub fn program_for(_rom: &[u8]) -> Option<&'static Program> {
+    None
+}
@@ -0,0 +1,211 @@
+use std::{
+    fmt::Write,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::Mutex,
+    thread,
+};
+
+use serde::Deserialize;
+
+use crate::chip_machine::{CHIPMachine, Status};
+use crate::framebuffer::Framebuffer;
+use crate::headless;
+use crate::options::Options;
+use crate::processor::Fault;
+use crate::script::InputScript;
+
+// Example manifest:
+//
+//     frames = 600
+//
+//     [[machine]]
+//     name = "opcodes"
+//     rom = "roms/test_opcode.ch8"
+//     seed = 1
+//     input = "opcodes.keys"
+//     frames = 120
+//
+// Paths are relative to the manifest. `name` defaults to the ROM's file name
+// and `frames` to the top level value.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Manifest {
+    frames: Option<usize>,
+    #[serde(rename = "machine")]
+    machines: Vec<MachineEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MachineEntry {
+    name: Option<String>,
+    rom: String,
+    seed: Option<u64>,
+    input: Option<String>,
+    frames: Option<usize>,
+}
+
+pub struct Job {
+    pub name: String,
+    pub chip8: CHIPMachine,
+    pub input: InputScript,
+    pub frames: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    // Ran every frame it was given.
+    Finished,
+    // Jumped to itself at this address.
+    Halted(u16),
+    // Stopped on an instruction that couldn't run at this address.
+    Faulted(u16, Fault),
+    // The emulator itself panicked.
+    Panicked(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub name: String,
+    pub frames: usize,
+    pub stop: Stop,
+    pub pc: u16,
+    pub i: u16,
+    pub sp: u16,
+    pub v: [u8; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub display: Framebuffer,
+}
+
+// Runs every job to completion on up to `threads` threads and returns the
+// outcomes in the same order.
+pub fn run(jobs: Vec<Job>, threads: usize) -> Vec<Outcome> {
+    let count = jobs.len();
+    let queue = Mutex::new(jobs.into_iter().enumerate());
+    let results = Mutex::new(Vec::with_capacity(count));
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, count.max(1)) {
+            scope.spawn(|| loop {
+                // The guard has to be gone before the job runs.
+                let next = queue.lock().unwrap().next();
+                let Some((index, job)) = next else { break };
+                let outcome = run_job(job);
+                results.lock().unwrap().push((index, outcome));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+fn run_job(mut job: Job) -> Outcome {
+    let ran = panic::catch_unwind(AssertUnwindSafe(|| {
+        headless::run_frames(&mut job.chip8, job.frames, &job.input);
+        match job.chip8.status() {
+            Status::Halted(addr) => Stop::Halted(addr),
+            Status::Faulted(fault) => Stop::Faulted(job.chip8.cpu().pc, fault),
+            Status::Running => Stop::Finished,
+        }
+    }));
+    let stop = ran.unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Stop::Panicked(message)
+    });
+    let cpu = job.chip8.cpu();
+    Outcome {
+        name: job.name,
+        frames: job.chip8.frame_count as usize,
+        stop,
+        pc: cpu.pc,
+        i: cpu.i,
+        sp: cpu.sp,
+        v: cpu.v,
+        delay_timer: cpu.delay_timer,
+        sound_timer: cpu.sound_timer,
+        display: cpu.display.clone(),
+    }
+}
+
+// Loads the manifest into jobs, using `options` for everything it doesn't set.
+pub fn load(path: &str, options: &Options) -> Result<Vec<Job>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let manifest: Manifest = toml::from_str(&text).map_err(|err| format!("{}: {}", path, err))?;
+    let base = Path::new(path).parent().unwrap_or(Path::new("."));
+    let relative = |file: &str| base.join(file).to_string_lossy().into_owned();
+    manifest
+        .machines
+        .iter()
+        .map(|entry| {
+            let rom = relative(&entry.rom);
+            if !Path::new(&rom).is_file() {
+                return Err(format!("{}: no such ROM", rom));
+            }
+            let options = Options {
+                rom: rom.clone(),
+                seed: entry.seed.or(options.seed),
+                ..options.clone()
+            };
+            let name = entry.name.clone().unwrap_or_else(|| {
+                let file = Path::new(&rom).file_name().unwrap_or_default();
+                file.to_string_lossy().into_owned()
+            });
+            let input = match &entry.input {
+                Some(file) => InputScript::load(&relative(file))?,
+                None => InputScript::default(),
+            };
+            Ok(Job {
+                name,
+                chip8: options.machine()?,
+                input,
+                frames: entry.frames.or(manifest.frames).unwrap_or(options.frames),
+            })
+        })
+        .collect()
+}
+
+// A line per machine, then its registers and final screen.
+pub fn report(outcomes: &[Outcome]) -> String {
+    let mut out = String::new();
+    let width = outcomes.iter().map(|o| o.name.len()).max().unwrap_or(0);
+    for o in outcomes {
+        let stop = match &o.stop {
+            Stop::Finished => String::from("finished"),
+            Stop::Halted(addr) => format!("halted at {:#05X}", addr),
+            Stop::Faulted(addr, fault) => format!("faulted at {:#05X}: {}", addr, fault),
+            Stop::Panicked(message) => format!("panicked: {}", message),
+        };
+        let _ = writeln!(out, "{:width$}  {:>6} frames  screen {:016x}  {}", o.name, o.frames, o.display.hash(), stop);
+    }
+    for o in outcomes {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "== {}", o.name);
+        let _ = writeln!(
+            out,
+            "PC {:#05X}  I {:#05X}  SP {:X}  DT {:02X}  ST {:02X}",
+            o.pc, o.i, o.sp, o.delay_timer, o.sound_timer
+        );
+        let v: Vec<String> = o.v.iter().enumerate().map(|(x, v)| format!("V{:X} {:02X}", x, v)).collect();
+        let _ = writeln!(out, "{}", v.join("  "));
+        let _ = write!(out, "{}", o.display);
+    }
+    out
+}
+
+// `chip8 batch MANIFEST`: runs every machine in the manifest in parallel and
+// returns the report.
+pub fn run_manifest(options: &Options) -> Result<String, String> {
+    let path = options.file.as_deref().ok_or("batch needs a manifest file")?;
+    let jobs = load(path, options)?;
+    let threads = options
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    Ok(report(&run(jobs, threads)))
+}
@@ -0,0 +1,54 @@
+use std::time::{Duration, Instant};
+
+use crate::options::Options;
+use crate::processor::Processor;
+
+// `chip8 bench`: runs the ROM headless through the plain interpreter
+// (`tick`, which fetches and decodes every instruction) and through the
+// decode cache (`run`, a block at a time), and reports the speed of both.
+pub fn run(options: &Options) -> Result<String, String> {
+    let chip8 = options.machine()?;
+    let instructions = (options.frames * chip8.cycles_per_frame()) as u64;
+
+    let mut interpreted = chip8.cpu().clone();
+    let interpreted_time = time(|| {
+        for _ in 0..instructions {
+            let op = interpreted.get_instruction().unwrap_or_default();
+            interpreted.tick(op);
+        }
+    });
+
+    let mut cached = chip8.cpu().clone();
+    let cached_time = time(|| {
+        let mut left = instructions as usize;
+        while left > 0 {
+            match cached.run(left) {
+                // Faulted, nothing more will run.
+                0 => break,
+                ran => left -= ran,
+            }
+        }
+    });
+
+    if !same_state(&interpreted, &cached) {
+        return Err(String::from("interpreter and decode cache ended in different states"));
+    }
+    let rate = |elapsed: Duration| instructions as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    Ok(format!(
+        "{} instructions\ninterpreter:  {:>14.0} instructions/s\ndecode cache: {:>14.0} instructions/s\nspeed-up:     {:>14.2}x",
+        instructions,
+        rate(interpreted_time),
+        rate(cached_time),
+        rate(cached_time) / rate(interpreted_time)
+    ))
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn same_state(a: &Processor, b: &Processor) -> bool {
+    a.pc == b.pc && a.i == b.i && a.sp == b.sp && a.v == b.v && a.ram == b.ram && a.display == b.display
+}
@@ -0,0 +1,235 @@
+use std::fmt;
+
+use crate::disasm::{classify, Class};
+use crate::expr::{Expr, Message};
+use crate::processor::Processor;
+
+// Where a breakpoint fires. All but `Collision` are checked before the
+// instruction at PC runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Place {
+    Addr(u16),
+    // An instruction matching a pattern like `Fx0A`, kept as given with the
+    // wildcards in lower case.
+    Opcode { value: u16, mask: u16, text: [u8; 4] },
+    Class(Class),
+    // Just after a `Dxyn` drew over a lit pixel.
+    Collision,
+}
+
+impl Place {
+    // `ADDR`, `op PATTERN`, `class NAME` or `collision`, and how many words
+    // that took. `resolve` turns an address or label into an address.
+    pub fn parse(words: &[&str], resolve: impl Fn(&str) -> Result<u16, String>) -> Result<(Self, usize), String> {
+        match words {
+            [] => Err(String::from("missing breakpoint location")),
+            ["collision", ..] => Ok((Place::Collision, 1)),
+            ["op", pattern, ..] => Ok((Place::opcode(pattern)?, 2)),
+            ["class", name, ..] => match Class::from_name(name) {
+                Some(class) => Ok((Place::Class(class), 2)),
+                None => {
+                    let names: Vec<&str> = Class::ALL.iter().map(|class| class.name()).collect();
+                    Err(format!("unknown class '{}', expected one of: {}", name, names.join(", ")))
+                }
+            },
+            ["op"] => Err(String::from("missing pattern after 'op'")),
+            ["class"] => Err(String::from("missing class after 'class'")),
+            [addr, ..] => Ok((Place::Addr(resolve(addr)?), 1)),
+        }
+    }
+
+    // Four hex digits, any of them replaced by x, y, n or k to match anything.
+    fn opcode(pattern: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid opcode pattern '{}', expected e.g. Fx0A or Dxyn", pattern);
+        let chars: Vec<char> = pattern.chars().collect();
+        if chars.len() != 4 {
+            return Err(invalid());
+        }
+        let (mut value, mut mask, mut text) = (0, 0, [0; 4]);
+        for (at, &c) in chars.iter().enumerate() {
+            let shift = 12 - 4 * at;
+            match c.to_digit(16) {
+                Some(digit) => {
+                    value |= (digit as u16) << shift;
+                    mask |= 0xF << shift;
+                    text[at] = c.to_ascii_uppercase() as u8;
+                }
+                None if "xynkXYNK".contains(c) => text[at] = c.to_ascii_lowercase() as u8,
+                None => return Err(invalid()),
+            }
+        }
+        Ok(Place::Opcode { value, mask, text })
+    }
+
+    // With the machine stopped before its next instruction, `ran` being the
+    // one that just ran.
+    fn reached(&self, cpu: &Processor, ran: Option<u16>) -> bool {
+        let op = cpu.get_instruction();
+        match *self {
+            Place::Addr(addr) => cpu.pc == addr,
+            Place::Opcode { value, mask, .. } => op.is_some_and(|op| op & mask == value),
+            Place::Class(class) => op.is_some_and(|op| classify(op) == class),
+            // VF is the number of colliding rows in SCHIP's 16x16 mode.
+            Place::Collision => ran.is_some_and(|op| op >> 12 == 0xD) && cpu.v[0xF] != 0,
+        }
+    }
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Place::Addr(addr) => write!(f, "{:#05X}", addr),
+            Place::Opcode { text, .. } => write!(f, "op {}", String::from_utf8_lossy(text)),
+            Place::Class(class) => write!(f, "class {}", class.name()),
+            Place::Collision => write!(f, "collision"),
+        }
+    }
+}
+
+// A breakpoint, or with `log` a log point that prints and carries on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub place: Place,
+    // Only fires when this is true.
+    pub condition: Option<Expr>,
+    pub log: Option<Message>,
+    // How often the place has been reached, `hit_count` in expressions.
+    pub hits: u64,
+}
+
+impl Breakpoint {
+    pub fn new(place: Place) -> Self {
+        Self {
+            place,
+            condition: None,
+            log: None,
+            hits: 0,
+        }
+    }
+
+    // `PLACE [if EXPR]`.
+    pub fn parse(words: &[&str], resolve: impl Fn(&str) -> Result<u16, String>) -> Result<Self, String> {
+        let (place, used) = Place::parse(words, resolve)?;
+        let mut breakpoint = Breakpoint::new(place);
+        match &words[used..] {
+            [] => (),
+            ["if", condition @ ..] if !condition.is_empty() => breakpoint.condition = Some(Expr::parse(&condition.join(" "))?),
+            ["if"] => return Err(String::from("missing condition after 'if'")),
+            [word, ..] => return Err(format!("expected 'if' after the location, found '{}'", word)),
+        }
+        Ok(breakpoint)
+    }
+
+    // `PLACE MESSAGE`, a log point.
+    pub fn parse_log(words: &[&str], resolve: impl Fn(&str) -> Result<u16, String>) -> Result<Self, String> {
+        let (place, used) = Place::parse(words, resolve)?;
+        let message = &words[used..];
+        if message.is_empty() {
+            return Err(String::from("missing log message"));
+        }
+        let mut breakpoint = Breakpoint::new(place);
+        breakpoint.log = Some(Message::parse(&message.join(" "))?);
+        Ok(breakpoint)
+    }
+
+    // Everything but the place, e.g. ` if v3 == 1, log "V3 {v3}"`.
+    pub fn details(&self) -> String {
+        let mut out = String::new();
+        if let Some(condition) = &self.condition {
+            out += &format!(" if {}", condition);
+        }
+        if let Some(message) = &self.log {
+            out += &format!(", log \"{}\"", message);
+        }
+        out
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.place, self.details())
+    }
+}
+
+// Breakpoints and log points, at most one per place.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Breakpoints(Vec<Breakpoint>);
+
+impl Breakpoints {
+    // Replaces any breakpoint at the same place, which is returned.
+    pub fn insert(&mut self, breakpoint: Breakpoint) -> Option<Breakpoint> {
+        let replaced = self.remove(breakpoint.place);
+        self.0.push(breakpoint);
+        replaced
+    }
+
+    pub fn remove(&mut self, place: Place) -> Option<Breakpoint> {
+        let index = self.0.iter().position(|breakpoint| breakpoint.place == place)?;
+        Some(self.0.remove(index))
+    }
+
+    pub fn get_mut(&mut self, place: Place) -> Option<&mut Breakpoint> {
+        self.0.iter_mut().find(|breakpoint| breakpoint.place == place)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.0.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Breakpoints {
+    type Item = &'a mut Breakpoint;
+    type IntoIter = std::slice::IterMut<'a, Breakpoint>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
+// A hex address, with or without 0x.
+pub fn parse_addr(text: &str) -> Result<u16, String> {
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", text))
+}
+
+// What the breakpoints asked for after an instruction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Check {
+    // The first breakpoint to stop at.
+    pub stop: Option<Place>,
+    // Log point output, and conditions that couldn't be evaluated.
+    pub logs: Vec<String>,
+}
+
+// Counts a hit for every breakpoint reached once `ran` has run and sees
+// whether it fires. Conditions that fail to evaluate stop with the error
+// logged, rather than being skipped silently.
+pub fn check<'a>(breakpoints: impl IntoIterator<Item = &'a mut Breakpoint>, cpu: &Processor, ran: Option<u16>) -> Check {
+    let mut check = Check::default();
+    for breakpoint in breakpoints {
+        if !breakpoint.place.reached(cpu, ran) {
+            continue;
+        }
+        breakpoint.hits += 1;
+        let fires = match &breakpoint.condition {
+            Some(condition) => condition.eval(cpu, breakpoint.hits).map(|value| value != 0),
+            None => Ok(true),
+        };
+        let logged = match (&breakpoint.log, &fires) {
+            (Some(message), Ok(true)) => Some(message.eval(cpu, breakpoint.hits)),
+            _ => None,
+        };
+        match (fires, logged) {
+            (Ok(false), _) => (),
+            (Ok(true), None) => {
+                check.stop.get_or_insert(breakpoint.place);
+            }
+            (Ok(true), Some(Ok(text))) => check.logs.push(text),
+            (Err(err), _) | (_, Some(Err(err))) => {
+                check.logs.push(format!("Error in breakpoint at {}: {}", breakpoint.place, err));
+                check.stop.get_or_insert(breakpoint.place);
+            }
+        }
+    }
+    check
+}
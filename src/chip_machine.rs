@@ -1,17 +1,54 @@
 use winit::event::VirtualKeyCode;
 
-use crate::processor::Processor;
-use std::{time::{ Duration, Instant }, fs::{self, File}, io::Read};
+use crate::aot::{self, Program};
+use crate::font::Font;
+use crate::hash::fnv1a;
+use crate::keypad;
+use crate::palette::Palette;
+use crate::phosphor::{Persistence, Phosphor};
+use crate::upscale::Filter;
+use crate::processor::{Fault, Processor};
+use crate::quirks::Quirks;
+use crate::trace::Tracer;
+use std::{time::Duration, fs, io, path::Path};
 
-#[derive(Clone, Debug)]
+// Timers and the display run at 60 Hz.
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    // Stuck on a `1nnn` jumping to itself at this address, nothing but the
+    // timers can change any more.
+    Halted(u16),
+    // Stopped on an instruction that couldn't run, at PC.
+    Faulted(Fault),
+}
+
+#[derive(Debug)]
 pub struct CHIPMachine {
     cpu: Processor,
     // Should always be the same size as `cells`. When updating, we read from
     // `cells` and write to `scratch_cells`, then swap. Otherwise it's not in
     // use, and `cells` should be updated directly.
     pub cycle_duration: Duration,
-    pub start_time: Instant,
     pub running: bool,
+    palettes: Vec<Palette>,
+    palette_index: usize,
+    phosphor: Phosphor,
+    filter: Filter,
+    // Native resolution RGBA frame, only used when a filter upscales it.
+    frame: Vec<u8>,
+    rom_path: Option<String>,
+    rom_hash: u64,
+    // Recompiled blocks for the loaded ROM, when this binary was built with them.
+    aot: Option<&'static Program>,
+    frame_cycles: usize,
+    status: Status,
+    pub frame_count: u64,
+    pub cycle_count: u64,
+    redraw: bool,
+    tracer: Option<Tracer>,
 }
 
 impl CHIPMachine {
@@ -19,50 +56,328 @@ impl CHIPMachine {
         assert!(width != 0 && height != 0);
         let size = width.checked_mul(height).expect("too big");
         Self {
-            cpu: Processor::new(size),
-            start_time: Instant::now(),
+            cpu: Processor::new(width, height),
             cycle_duration: Duration::from_micros(200),
             running: false,
+            palettes: Palette::builtin(),
+            palette_index: 0,
+            phosphor: Phosphor::new(Persistence::Off, size),
+            filter: Filter::None,
+            frame: vec![0; 4 * size],
+            rom_path: None,
+            rom_hash: fnv1a(&[]),
+            aot: None,
+            frame_cycles: 0,
+            status: Status::Running,
+            frame_count: 0,
+            cycle_count: 0,
+            redraw: true,
+            tracer: None,
         }
     }
 
-    pub fn cycle(&mut self) {
-        let op = match self.cpu.get_instruction() {
-            Some(op) => op,
-            None => 0,
+    // Runs a recompiled block when one starts at PC and ends within this
+    // frame, otherwise cached instructions up to the next jump or the end of
+    // the frame. Returns true when it completed a 60 Hz frame, after which the
+    // timers have been ticked.
+    pub fn cycle(&mut self) -> bool {
+        // Traces and watchpoints see every instruction, one at a time.
+        if self.tracer.is_some() || !self.cpu.watches.is_empty() {
+            return self.step();
+        }
+        let left = self.cycles_per_frame() - self.frame_cycles;
+        let block = self.aot.and_then(|program| program.block(&self.cpu)).filter(|block| block.instructions <= left);
+        let instructions = match (self.skip_idle(left), block) {
+            (Some(skipped), _) => skipped,
+            (None, Some(block)) => {
+                (block.run)(&mut self.cpu);
+                block.instructions
+            }
+            (None, None) => self.cpu.run(left),
         };
-        self.cpu.tick(op);
+        self.count(instructions)
     }
 
-    pub fn reset_start_time(&mut self) {
-        self.start_time = Instant::now();
+    // Runs exactly one instruction, without skipping idle loops or running
+    // recompiled blocks, so a debugger sees every PC. Returns true when it
+    // completed a 60 Hz frame.
+    pub fn step(&mut self) -> bool {
+        match self.tracer.as_mut() {
+            Some(tracer) => tracer.step(&mut self.cpu, self.cycle_count),
+            None => self.cpu.step(),
+        }
+        self.status = match self.cpu.fault {
+            Some(fault) => Status::Faulted(fault),
+            None => Status::Running,
+        };
+        self.count(1)
     }
 
-    pub fn load_rom(&mut self, file_path: String) {
-        let mut f = File::open(&file_path).expect("no file found");
-        let metadata = fs::metadata(&file_path).expect("unable to read metadata");
-        let mut buffer: Vec<u8> = vec![0; metadata.len() as usize];
-        f.read(&mut buffer).expect("buffer overflow");
+    fn count(&mut self, instructions: usize) -> bool {
+        self.cycle_count += instructions as u64;
+        self.frame_cycles += instructions;
+        if self.frame_cycles < self.cycles_per_frame() {
+            return false;
+        }
+        self.frame_cycles = 0;
+        self.frame_count += 1;
+        self.cpu.tick_timers();
+        true
+    }
+
+    // Fast-forwards through loops that can't do anything new before the next
+    // timer tick and returns how many instructions that stood for. The state
+    // afterwards is what running them one by one would have left.
+    fn skip_idle(&mut self, left: usize) -> Option<usize> {
+        let pc = self.cpu.pc;
+        self.status = Status::Running;
+        if let Some(fault) = self.cpu.fault {
+            self.status = Status::Faulted(fault);
+            return Some(left);
+        }
+        let op = |offset: u16| self.cpu.instruction_at(pc.wrapping_add(offset));
+        let first = op(0)?;
+        if first == 0x1000 | pc {
+            self.status = Status::Halted(pc);
+            return Some(left);
+        }
+
+        // Fx07, then 3xkk or 4xkk on the same Vx, then 1nnn back to the Fx07:
+        // polling the delay timer, which only changes at the end of the frame.
+        let (test, back) = (op(2)?, op(4)?);
+        let x = (first >> 8 & 0xF) as usize;
+        if first & 0xF0FF != 0xF007 || back != 0x1000 | pc || (test >> 8 & 0xF) as usize != x || left < 3 {
+            return None;
+        }
+        let timer = self.cpu.delay_timer;
+        let leaves = match test >> 12 {
+            0x3 => timer == test as u8,
+            0x4 => timer != test as u8,
+            _ => return None,
+        };
+        if leaves {
+            return None;
+        }
+        // Every pass through the loop leaves the same state behind, whatever
+        // is left over runs normally.
+        self.cpu.v[x] = timer;
+        Some(left - left % 3)
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn cycles_per_frame(&self) -> usize {
+        (FRAME_DURATION.as_nanos() / self.cycle_duration.as_nanos()).max(1) as usize
+    }
 
+    // Runs instructions up to the end of the current 60 Hz frame.
+    pub fn run_frame(&mut self) {
+        while !self.cycle() {}
+    }
+
+    pub fn load_rom(&mut self, file_path: String) -> io::Result<()> {
+        let buffer = fs::read(&file_path)?;
+        self.load_bytes(buffer);
+        self.rom_path = Some(file_path);
+        Ok(())
+    }
+
+    // Loads a ROM that isn't in a file. Anything past the end of RAM is dropped.
+    pub fn load_bytes(&mut self, buffer: Vec<u8>) {
+        self.rom_hash = fnv1a(&buffer);
+        self.aot = aot::program_for(&buffer);
         self.cpu.load(buffer);
         self.running = true;
     }
 
-    pub fn draw(&self, screen: &mut [u8]) {
-        debug_assert_eq!(screen.len(), 4 * self.cpu.pixels.len());
-        for (c, pix) in self.cpu.pixels.iter().zip(screen.chunks_exact_mut(4)) {
-            let color = if *c {
-                //#2a9d8f
-                [0x2a, 0x9d, 0x8f, 0xFF]
-            } else {
-                // #264653
-                [0x26, 0x46, 0x53, 0xFF]
-            };
-            pix.copy_from_slice(&color);
+    // Whether recompiled blocks are used for the loaded ROM.
+    pub fn aot(&self) -> bool {
+        self.aot.is_some()
+    }
+
+    // Runs everything through the interpreter, even with recompiled blocks built in.
+    // The debuggers need this, as a recompiled block would run past breakpoints.
+    pub fn disable_aot(&mut self) {
+        self.aot = None;
+    }
+
+    // File name of the loaded ROM without its extension.
+    pub fn rom_name(&self) -> &str {
+        self.rom_path
+            .as_deref()
+            .and_then(|path| Path::new(path).file_stem())
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("chip8")
+    }
+
+    // FNV-1a of the ROM as loaded, to tell builds of the same ROM apart.
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    // The screen size, which 00FE and 00FF switch between 64x32 and 128x64.
+    pub fn size(&self) -> (usize, usize) {
+        (self.cpu.display.width(), self.cpu.display.height())
+    }
+
+    pub fn load_font(&mut self, font: &Font) {
+        assert!(font.fits(), "font must fit below 0x200");
+        self.cpu.load_font(font);
+    }
+
+    // Replaces the selectable palettes and makes `active` the current one,
+    // or the first palette when no name is given.
+    pub fn set_palettes(&mut self, palettes: Vec<Palette>, active: Option<&str>) -> Result<(), String> {
+        assert!(!palettes.is_empty());
+        let index = match active {
+            Some(name) => palettes
+                .iter()
+                .position(|p| p.name == name)
+                .ok_or_else(|| format!("unknown palette '{}'", name))?,
+            None => 0,
+        };
+        self.palettes = palettes;
+        self.palette_index = index;
+        self.redraw = true;
+        Ok(())
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palettes[self.palette_index]
+    }
+
+    pub fn cycle_palette(&mut self) -> &Palette {
+        self.palette_index = (self.palette_index + 1) % self.palettes.len();
+        self.redraw = true;
+        self.palette()
+    }
+
+    pub fn set_persistence(&mut self, mode: Persistence) {
+        self.phosphor.set_mode(mode);
+        self.redraw = true;
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.cpu.quirks
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.redraw = true;
+    }
+
+    // Size of the frame `draw` expects, after upscaling.
+    pub fn output_size(&self) -> (usize, usize) {
+        let scale = self.filter.scale();
+        let (width, height) = self.size();
+        (width * scale, height * scale)
+    }
+
+    // Renders into `screen` and returns true, or returns false without
+    // touching it when it would look the same as after the last call.
+    pub fn draw(&mut self, screen: &mut [u8]) -> bool {
+        let (width, height) = self.output_size();
+        debug_assert_eq!(screen.len(), 4 * width * height);
+        let (width, height) = self.size();
+        if self.frame.len() != 4 * width * height {
+            self.frame = vec![0; 4 * width * height];
+            self.phosphor.resize(width * height);
+        }
+        let dirty = self.cpu.display.take_dirty();
+        if !(dirty || self.redraw || self.phosphor.is_fading()) {
+            return false;
+        }
+        self.redraw = false;
+        let palette = &self.palettes[self.palette_index];
+        if self.filter == Filter::None {
+            self.phosphor.apply(&self.cpu.display, palette, screen, self.frame_count);
+        } else {
+            self.phosphor.apply(&self.cpu.display, palette, &mut self.frame, self.frame_count);
+            self.filter.apply(&self.frame, width, height, screen);
+        }
+        true
+    }
+
+    // The current screen as palette indices (see `Palette::color`), scaled
+    // up `scale` times with nearest neighbour.
+    pub fn indexed_snapshot(&self, scale: usize) -> Vec<u8> {
+        assert!(scale > 0);
+        let (width, height) = self.size();
+        let out_width = width * scale;
+        let mut out = vec![0; out_width * height * scale];
+        for (row, line) in out.chunks_exact_mut(out_width).enumerate() {
+            let y = row / scale;
+            for (col, index) in line.iter_mut().enumerate() {
+                *index = self.cpu.display.get(col / scale, y) as u8;
+            }
+        }
+        out
+    }
+
+    // The current screen in the active palette, without persistence or
+    // filters, scaled up `scale` times with nearest neighbour.
+    pub fn snapshot(&self, scale: usize) -> Vec<u8> {
+        let palette = self.palette();
+        self.indexed_snapshot(scale)
+            .into_iter()
+            .flat_map(|index| palette.color(index))
+            .collect()
+    }
+
+    pub fn process_key(&mut self, key: VirtualKeyCode, pressed: bool) {
+        if let Some(hex) = keypad::from_virtual_key(key) {
+            self.set_key(hex, pressed);
         }
     }
 
-    pub fn process_key(&self, _: VirtualKeyCode) {
+    // Makes Cxkk repeatable.
+    pub fn seed(&mut self, seed: u64) {
+        self.cpu.seed(seed);
+    }
+
+    pub fn set_key(&mut self, hex: u8, pressed: bool) {
+        self.cpu.keypad[hex as usize & 0xF] = pressed;
+    }
+
+    pub fn cpu(&self) -> &Processor {
+        &self.cpu
+    }
+
+    // For debuggers. RAM has to be written through `Processor::write_ram`.
+    pub fn cpu_mut(&mut self) -> &mut Processor {
+        &mut self.cpu
+    }
+
+    // Moves PC, for debuggers. A faulted machine carries on from there.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.cpu.pc = pc;
+        self.cpu.fault = None;
+        self.status = Status::Running;
+    }
+
+    // Records every instruction from now on. Idle loops are no longer
+    // skipped and recompiled blocks no longer run while tracing.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    // Stops tracing and hands the tracer back.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.cpu.display.get(x, y)
     }
 
 }
\ No newline at end of file
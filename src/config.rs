@@ -0,0 +1,53 @@
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+use crate::palette::Palette;
+
+pub const DEFAULT_CONFIG: &str = "./chip8.toml";
+
+// Example `chip8.toml`:
+//
+//     palette = "amber"
+//
+//     [[palettes]]
+//     name = "gameboy"
+//     colors = ["#9bbc0f", "#0f380f", "#306230", "#8bac0f"]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub palette: Option<String>,
+    pub palettes: Vec<PaletteConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PaletteConfig {
+    pub name: String,
+    pub colors: Vec<String>,
+}
+
+impl Config {
+    // A missing default config is fine, a missing explicit one is an error.
+    pub fn load(file_path: Option<&str>) -> Result<Self, String> {
+        let path = file_path.unwrap_or(DEFAULT_CONFIG);
+        if file_path.is_none() && !Path::new(path).exists() {
+            return Ok(Config::default());
+        }
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        toml::from_str(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    // Built-in palettes followed by the user's. A user palette with the same
+    // name as a built-in one replaces it in place.
+    pub fn palettes(&self) -> Result<Vec<Palette>, String> {
+        let mut palettes = Palette::builtin();
+        for entry in &self.palettes {
+            let palette = Palette::from_hex(&entry.name, &entry.colors)?;
+            match palettes.iter_mut().find(|p| p.name == palette.name) {
+                Some(existing) => *existing = palette,
+                None => palettes.push(palette),
+            }
+        }
+        Ok(palettes)
+    }
+}
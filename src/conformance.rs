@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::framebuffer::Framebuffer;
+use crate::options::Options;
+use crate::processor::Processor;
+use crate::quirks::Quirks;
+use crate::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+
+// One instruction run on a known machine state. Case files hold a JSON array
+// of these, e.g.
+//
+//   {"name": "8xy4 carry", "opcode": "8124",
+//    "initial": {"v": {"1": 255, "2": 1}},
+//    "expected": {"v": {"1": 0, "F": 1}, "pc": 514}}
+//
+// `initial` is applied on top of a freshly reset processor, `expected` lists
+// only what the instruction changes. Everything it leaves out must be the
+// same afterwards as in `initial`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Case {
+    pub name: String,
+    // In hex, without a prefix.
+    pub opcode: String,
+    // Quirk profile name, "modern" when left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<String>,
+    #[serde(default)]
+    pub initial: State,
+    #[serde(default)]
+    pub expected: State,
+    // The fault the instruction raises, as `Fault` displays it, e.g.
+    // "stack overflow". PC stays on the instruction when it faults.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault: Option<String>,
+}
+
+// Part of the machine state. Fields that are left out are not touched.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct State {
+    // Registers by hex digit, e.g. {"0": 1, "F": 0}.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub v: BTreeMap<String, u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub i: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pc: Option<u16>,
+    // Return addresses from the bottom up, so its length is SP.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack: Option<Vec<u16>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay_timer: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sound_timer: Option<u8>,
+    // Keys held down, every other key is up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keys: Option<Vec<u8>>,
+    // Key Fx0A waits to be released, `null` for none.
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub awaited_key: Option<Option<u8>>,
+    // Bytes by start address, e.g. {"0x300": [1, 2, 3]}.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub ram: BTreeMap<String, Vec<u8>>,
+    // Screen size as [width, height], 00FE and 00FF switch it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screen: Option<[usize; 2]>,
+    // Every lit pixel as [x, y], the rest of the screen is dark.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixels: Option<Vec<[u8; 2]>>,
+}
+
+// Tells an explicit `null` apart from a missing field.
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<u8>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+impl Case {
+    // Runs the case and returns everything that came out different, one
+    // line each.
+    pub fn run(&self) -> Result<(), String> {
+        let opcode = u16::from_str_radix(&self.opcode, 16).map_err(|err| format!("opcode '{}': {}", self.opcode, err))?;
+        let mut cpu = Processor::new(WIDTH, HEIGHT);
+        cpu.quirks = Quirks::from_name(self.quirks.as_deref().unwrap_or("modern"))?;
+        // Cxkk draws the same numbers every run.
+        cpu.seed(0);
+        self.initial.apply(&mut cpu)?;
+        cpu.check_invariants().map_err(|err| format!("initial state: {}", err))?;
+        let mut expected = cpu.clone();
+        self.expected.apply(&mut expected)?;
+
+        // The decode cache has its own copy of the common instructions.
+        let mut cached = cpu.clone();
+        cpu.tick(opcode);
+        cached.step_decoded(opcode);
+        let mut mismatches = compare(&expected, &cpu);
+        let fault = cpu.fault.map(|fault| fault.to_string());
+        if fault != self.fault {
+            mismatches.push(format!("fault: expected {:?}, got {:?}", self.fault, fault));
+        }
+        for mismatch in compare(&expected, &cached) {
+            mismatches.push(format!("decode cache: {}", mismatch));
+        }
+        if cached.fault != cpu.fault {
+            mismatches.push(format!("decode cache: fault {:?}, interpreter {:?}", cached.fault, cpu.fault));
+        }
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatches.join("\n"))
+        }
+    }
+}
+
+impl State {
+    pub fn apply(&self, cpu: &mut Processor) -> Result<(), String> {
+        for (register, &value) in &self.v {
+            let index = usize::from_str_radix(register, 16)
+                .ok()
+                .filter(|&index| index < cpu.v.len())
+                .ok_or_else(|| format!("no register V{}", register))?;
+            cpu.v[index] = value;
+        }
+        cpu.i = self.i.unwrap_or(cpu.i);
+        cpu.pc = self.pc.unwrap_or(cpu.pc);
+        if let Some(stack) = &self.stack {
+            if stack.len() > cpu.stack.len() {
+                return Err(format!("stack holds at most {} addresses", cpu.stack.len()));
+            }
+            cpu.stack[..stack.len()].copy_from_slice(stack);
+            cpu.sp = stack.len() as u16;
+        }
+        cpu.delay_timer = self.delay_timer.unwrap_or(cpu.delay_timer);
+        cpu.sound_timer = self.sound_timer.unwrap_or(cpu.sound_timer);
+        if let Some(keys) = &self.keys {
+            cpu.keypad = [false; 16];
+            for &key in keys {
+                *cpu.keypad.get_mut(key as usize).ok_or_else(|| format!("no key {:X}", key))? = true;
+            }
+        }
+        cpu.awaited_key = self.awaited_key.unwrap_or(cpu.awaited_key);
+        for (addr, bytes) in &self.ram {
+            let start = parse_addr(addr)?;
+            if start + bytes.len() > cpu.ram.len() {
+                return Err(format!("{} bytes at {} run past the end of RAM", bytes.len(), addr));
+            }
+            for (offset, &byte) in bytes.iter().enumerate() {
+                cpu.write_ram(start + offset, byte);
+            }
+        }
+        if let Some([width, height]) = self.screen {
+            if !matches!((width, height), (WIDTH, HEIGHT) | (HIRES_WIDTH, HIRES_HEIGHT)) {
+                return Err(format!("no {}x{} screen", width, height));
+            }
+            cpu.display = Framebuffer::new(width, height);
+        }
+        if let Some(pixels) = &self.pixels {
+            cpu.display.clear();
+            for &[x, y] in pixels {
+                let (x, y) = (x as usize, y as usize);
+                if x >= cpu.display.width() || y >= cpu.display.height() || cpu.display.get(x, y) {
+                    return Err(format!("pixel [{}, {}] is off screen or listed twice", x, y));
+                }
+                cpu.display.draw_sprite(x, y, &[0x80], true);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_addr(addr: &str) -> Result<usize, String> {
+    let digits = addr.strip_prefix("0x").unwrap_or(addr);
+    usize::from_str_radix(digits, 16).map_err(|err| format!("address '{}': {}", addr, err))
+}
+
+// Everything observable that differs between the two processors.
+fn compare(expected: &Processor, actual: &Processor) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    let mut check = |what: String, expected: String, actual: String| {
+        if expected != actual {
+            mismatches.push(format!("{}: expected {}, got {}", what, expected, actual));
+        }
+    };
+    for (index, (want, got)) in expected.v.iter().zip(&actual.v).enumerate() {
+        check(format!("V{:X}", index), format!("{:#04x}", want), format!("{:#04x}", got));
+    }
+    check("I".into(), format!("{:#06x}", expected.i), format!("{:#06x}", actual.i));
+    check("PC".into(), format!("{:#06x}", expected.pc), format!("{:#06x}", actual.pc));
+    check("stack".into(), live_stack(expected), live_stack(actual));
+    check("delay timer".into(), expected.delay_timer.to_string(), actual.delay_timer.to_string());
+    check("sound timer".into(), expected.sound_timer.to_string(), actual.sound_timer.to_string());
+    check("keys".into(), format!("{:?}", expected.keypad), format!("{:?}", actual.keypad));
+    check("awaited key".into(), format!("{:?}", expected.awaited_key), format!("{:?}", actual.awaited_key));
+    for (addr, (want, got)) in expected.ram.iter().zip(actual.ram.iter()).enumerate() {
+        check(format!("RAM {:#05x}", addr), format!("{:#04x}", want), format!("{:#04x}", got));
+    }
+    let size = |cpu: &Processor| format!("{}x{}", cpu.display.width(), cpu.display.height());
+    check("screen size".into(), size(expected), size(actual));
+    check("screen".into(), lit_pixels(expected), lit_pixels(actual));
+    mismatches
+}
+
+fn live_stack(cpu: &Processor) -> String {
+    format!("{:x?}", cpu.stack.get(..cpu.sp as usize).unwrap_or(&cpu.stack))
+}
+
+fn lit_pixels(cpu: &Processor) -> String {
+    let width = cpu.display.width();
+    let lit: Vec<[usize; 2]> = cpu
+        .display
+        .pixels()
+        .enumerate()
+        .filter(|&(_, lit)| lit)
+        .map(|(index, _)| [index % width, index / width])
+        .collect();
+    format!("{:?}", lit)
+}
+
+pub fn load(path: &str) -> Result<Vec<Case>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    serde_json::from_str(&text).map_err(|err| format!("{}: {}", path, err))
+}
+
+// `chip8 conformance FILE`: runs every case in the file and reports the
+// ones that failed. Returns whether they all passed, and the report.
+pub fn run(options: &Options) -> Result<(bool, String), String> {
+    let path = options.file.as_deref().ok_or("conformance needs a case file")?;
+    let cases = load(path)?;
+    let mut report = String::new();
+    let mut failed = 0;
+    for case in &cases {
+        if let Err(mismatches) = case.run() {
+            failed += 1;
+            report += &format!("FAIL {} ({})\n", case.name, case.opcode);
+            for line in mismatches.lines() {
+                report += &format!("  {}\n", line);
+            }
+        }
+    }
+    report += &format!("{} of {} cases passed\n", cases.len() - failed, cases.len());
+    Ok((failed == 0, report))
+}
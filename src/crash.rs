@@ -0,0 +1,77 @@
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::chip_machine::{CHIPMachine, Status};
+use crate::debugger::{hex_dump, registers};
+use crate::disasm::disassemble;
+use crate::screenshot;
+
+// Bytes shown on either side of PC and I.
+const CONTEXT: usize = 32;
+
+// Everything there is to know about why the machine faulted, as text. The
+// trace section needs a ring tracer, see `--crash-ring`.
+pub fn report(chip8: &CHIPMachine) -> String {
+    let cpu = chip8.cpu();
+    let fault = match chip8.status() {
+        Status::Faulted(fault) => fault.to_string(),
+        status => format!("none ({:?})", status),
+    };
+    let mut out = String::new();
+    let _ = writeln!(out, "CHIP-8 crash report");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "Fault   {}", fault);
+    let _ = writeln!(out, "At      {}", instruction(chip8, cpu.pc));
+    let _ = writeln!(out, "ROM     {} (fnv1a {:016x})", chip8.rom_name(), chip8.rom_hash());
+    let _ = writeln!(out, "Quirks  {}", chip8.quirks().name());
+    let _ = writeln!(out, "Frame   {}, instruction {}", chip8.frame_count, chip8.cycle_count);
+
+    let _ = writeln!(out, "\nRegisters\n{}", registers(chip8));
+
+    // Each stack entry is the address of a call, innermost last.
+    let _ = writeln!(out, "\nCall stack");
+    let _ = writeln!(out, "#0  {}", instruction(chip8, cpu.pc));
+    for (depth, &call) in cpu.stack[..cpu.sp as usize].iter().rev().enumerate() {
+        let _ = writeln!(out, "#{}  {}", depth + 1, instruction(chip8, call));
+    }
+
+    let traced: Vec<String> = chip8.tracer().into_iter().flat_map(|tracer| tracer.entries()).map(|entry| entry.to_string()).collect();
+    if traced.is_empty() {
+        let _ = writeln!(out, "\nNo instructions traced, run with --crash-ring N to keep the last N");
+    } else {
+        let _ = writeln!(out, "\nLast {} instructions\n{}", traced.len(), traced.join("\n"));
+    }
+
+    let _ = writeln!(out, "\nRAM around PC\n{}", around(chip8, cpu.pc));
+    let _ = writeln!(out, "\nRAM around I\n{}", around(chip8, cpu.i));
+    out
+}
+
+// Saves the report and a PNG of the screen as `<rom>-crash-<timestamp>.txt`
+// and `.png` inside `dir`, returning the path of the report.
+pub fn save(chip8: &CHIPMachine, dir: &Path, scale: usize) -> io::Result<PathBuf> {
+    let name = format!("{}-crash", chip8.rom_name());
+    let path = dir.join(screenshot::file_name(&name, "txt"));
+    let png = path.with_extension("png");
+    screenshot::save_as(chip8, &png, scale)?;
+    let screen = format!("\nScreen  {}\n", png.file_name().unwrap_or_default().to_string_lossy());
+    fs::write(&path, report(chip8) + &screen)?;
+    Ok(path)
+}
+
+// `0x20C  00EE  RET`, or dashes past the end of RAM.
+fn instruction(chip8: &CHIPMachine, addr: u16) -> String {
+    match chip8.cpu().instruction_at(addr) {
+        Some(op) => format!("{:#05X}  {:04X}  {}", addr, op, disassemble(op)),
+        None => format!("{:#05X}  ----", addr),
+    }
+}
+
+// Rows around `addr`, with `[` in front of it.
+fn around(chip8: &CHIPMachine, addr: u16) -> String {
+    let start = (addr as usize).saturating_sub(CONTEXT) & !0xF;
+    hex_dump(&chip8.cpu().ram, start, 2 * CONTEXT + 16, addr)
+}
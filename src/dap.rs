@@ -0,0 +1,667 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Instant;
+
+use log::{error, info};
+use serde_json::{json, Value};
+
+use crate::breakpoint::{self, parse_addr, Breakpoint, Breakpoints, Place};
+use crate::chip_machine::{CHIPMachine, Status, FRAME_DURATION};
+use crate::debugger::read_labels;
+use crate::expr::{Expr, Message};
+use crate::options::Options;
+use crate::quirks::Quirks;
+use crate::screenshot;
+
+// The machine is the only thread.
+const THREAD: u64 = 1;
+// Variable references for the three scopes.
+const REGISTERS: u64 = 1;
+const MEMORY: u64 = 2;
+const SCREEN: u64 = 3;
+// Bytes per memory variable.
+const ROW: usize = 16;
+const SCREEN_SCALE: usize = 4;
+// Larger messages are refused rather than allocated. Real ones are a few KiB.
+const MAX_MESSAGE: usize = 1 << 20;
+
+// Which source line each instruction came from, read from lines of
+// `ADDR FILE:LINE`, e.g. `0x202 game.8o:14`. Files are relative to the map
+// and `#` starts a comment. Addresses without an entry belong to the closest
+// entry below them.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<PathBuf>,
+    lines: BTreeMap<u16, (usize, u32)>,
+}
+
+impl SourceMap {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        let mut map = SourceMap::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let entry = line.split_once(char::is_whitespace).and_then(|(addr, location)| {
+                let (file, line) = location.trim().rsplit_once(':')?;
+                let addr = addr.strip_prefix("0x").or_else(|| addr.strip_prefix("0X")).unwrap_or(addr);
+                Some((u16::from_str_radix(addr, 16).ok()?, file, line.parse().ok()?))
+            });
+            let Some((addr, file, line)) = entry else {
+                return Err(format!("{}:{}: expected ADDR FILE:LINE", path, number + 1));
+            };
+            let file = canonical(&dir.join(file));
+            let index = match map.files.iter().position(|known| *known == file) {
+                Some(index) => index,
+                None => {
+                    map.files.push(file);
+                    map.files.len() - 1
+                }
+            };
+            map.lines.insert(addr, (index, line));
+        }
+        Ok(map)
+    }
+
+    // The file and line `addr` belongs to.
+    pub fn line(&self, addr: u16) -> Option<(&Path, u32)> {
+        let (index, line) = self.location(addr)?;
+        Some((&self.files[index], line))
+    }
+
+    // Where to break for `line` of `file`: the first instruction on it, or on
+    // the next line that has any. Returns the address and the line it is on.
+    pub fn address(&self, file: &Path, line: u32) -> Option<(u16, u32)> {
+        let index = self.files.iter().position(|known| *known == canonical(file))?;
+        self.lines
+            .iter()
+            .filter(|(_, &(at, at_line))| at == index && at_line >= line)
+            .min_by_key(|(&addr, &(_, at_line))| (at_line, addr))
+            .map(|(&addr, &(_, at_line))| (addr, at_line))
+    }
+
+    fn location(&self, addr: u16) -> Option<(usize, u32)> {
+        self.lines.range(..=addr).next_back().map(|(_, &location)| location)
+    }
+}
+
+// Paths from the map and from the editor are compared after resolving them,
+// as far as the file system allows.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// `chip8 dap`: serves one editor on stdio, or on `--port` when given.
+pub fn run(options: &Options) -> Result<(), String> {
+    match options.port {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|err| format!("port {}: {}", port, err))?;
+            let addr = listener.local_addr().map_err(|err| err.to_string())?;
+            println!("waiting for the editor on {}", addr);
+            let (stream, peer) = listener.accept().map_err(|err| err.to_string())?;
+            info!("editor connected from {}", peer);
+            stream.set_nodelay(true).map_err(|err| err.to_string())?;
+            let input = stream.try_clone().map_err(|err| err.to_string())?;
+            serve(input, stream, options)
+        }
+        None => serve(io::stdin(), io::stdout(), options),
+    }
+    .map_err(|err| err.to_string())
+}
+
+// Speaks the protocol on `input` and `output` until the editor disconnects.
+// `defaults` supplies everything `launch` doesn't set, e.g. the font.
+pub fn serve(input: impl Read + Send + 'static, output: impl Write, defaults: &Options) -> io::Result<()> {
+    let messages = read_messages(input);
+    let mut session = Session {
+        output,
+        seq: 0,
+        defaults: defaults.clone(),
+        chip8: None,
+        map: SourceMap::default(),
+        labels: BTreeMap::new(),
+        breakpoints: BTreeMap::new(),
+        functions: Breakpoints::default(),
+        mode: Mode::Paused,
+        stop_on_entry: false,
+        pending: None,
+        screen: String::new(),
+    };
+    let mut next_frame = Instant::now();
+    loop {
+        let message = if session.mode == Mode::Paused {
+            next_frame = Instant::now();
+            messages.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            messages.recv_timeout(next_frame.saturating_duration_since(Instant::now()))
+        };
+        match message {
+            Ok(message) => {
+                if !session.handle(&message)? {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                session.run_frame()?;
+                // Don't try to catch up after a long stall.
+                next_frame += FRAME_DURATION;
+                let now = Instant::now();
+                if next_frame + 4 * FRAME_DURATION < now {
+                    next_frame = now;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(())
+}
+
+// Requests arrive on their own thread so they can interrupt a running machine.
+fn read_messages(input: impl Read + Send + 'static) -> Receiver<Value> {
+    let (sender, messages) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        loop {
+            match read_message(&mut input) {
+                Ok(Some(message)) => {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    error!("dap: {}", err);
+                    break;
+                }
+            }
+        }
+    });
+    messages
+}
+
+// One `Content-Length` framed JSON message, or `None` at the end of input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "missing Content-Length"))?;
+    if length > MAX_MESSAGE {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("message of {} bytes is too long", length)));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Paused,
+    Running,
+    // Until PC reaches the start of a different line than `from`, or any new
+    // instruction with `instruction`. With `over`, only at the same call depth
+    // or shallower.
+    Line {
+        from: Option<(usize, u32)>,
+        instruction: bool,
+        sp: u16,
+        over: bool,
+    },
+    // Until the subroutine that was running at depth `sp` returns.
+    Out { sp: u16 },
+}
+
+// A `stopped` event about to be sent.
+struct Stop {
+    reason: &'static str,
+    text: Option<String>,
+}
+
+impl Stop {
+    fn new(reason: &'static str) -> Self {
+        Self { reason, text: None }
+    }
+}
+
+struct Session<W: Write> {
+    output: W,
+    seq: u64,
+    defaults: Options,
+    // Set by `launch`.
+    chip8: Option<CHIPMachine>,
+    map: SourceMap,
+    labels: BTreeMap<u16, String>,
+    // Breakpoints by source file, each `setBreakpoints` replaces a file's.
+    breakpoints: BTreeMap<PathBuf, Breakpoints>,
+    // Function breakpoints, named by label, address or one of the places
+    // the debugger prompt takes, e.g. `op Fx0A` or `collision`.
+    functions: Breakpoints,
+    mode: Mode,
+    stop_on_entry: bool,
+    // Sent after the response to the request that caused it.
+    pending: Option<Stop>,
+    // The screen when the machine last stopped, as a PNG data URI.
+    screen: String,
+}
+
+impl<W: Write> Session<W> {
+    // Returns false once the editor has disconnected.
+    fn handle(&mut self, message: &Value) -> io::Result<bool> {
+        if message["type"] != "request" {
+            return Ok(true);
+        }
+        let command = message["command"].as_str().unwrap_or_default();
+        let args = &message["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsSteppingGranularity": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsHitConditionalBreakpoints": true,
+                "supportsLogPoints": true,
+                "supportsFunctionBreakpoints": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(args),
+            "evaluate" => self.machine().and_then(|chip8| {
+                let expression = args["expression"].as_str().unwrap_or_default();
+                let value = Expr::parse(expression)?.eval(chip8.cpu(), 0)?;
+                Ok(json!({"result": Expr::describe(value), "variablesReference": 0}))
+            }),
+            "configurationDone" => self.launched().map(|()| {
+                match self.stop_on_entry {
+                    true => self.pending = Some(Stop::new("entry")),
+                    false => self.mode = Mode::Running,
+                }
+                Value::Null
+            }),
+            "threads" => Ok(json!({"threads": [{"id": THREAD, "name": "chip8"}]})),
+            "stackTrace" => self.stack_trace(args),
+            "scopes" => Ok(json!({"scopes": [
+                {"name": "Registers", "variablesReference": REGISTERS, "expensive": false},
+                {"name": "Memory", "variablesReference": MEMORY, "expensive": true},
+                {"name": "Screen", "variablesReference": SCREEN, "expensive": false},
+            ]})),
+            "variables" => self.variables(args),
+            "continue" => self.launched().map(|()| {
+                self.mode = Mode::Running;
+                json!({"allThreadsContinued": true})
+            }),
+            "next" | "stepIn" => self.step(args, command == "next"),
+            "stepOut" => self.machine().map(|chip8| chip8.cpu().sp).and_then(|sp| match sp {
+                0 => Err(String::from("not in a subroutine")),
+                sp => {
+                    self.mode = Mode::Out { sp };
+                    Ok(Value::Null)
+                }
+            }),
+            "pause" => self.launched().map(|()| {
+                self.pending = Some(Stop::new("pause"));
+                Value::Null
+            }),
+            "disconnect" | "terminate" => {
+                self.respond(message, Ok(Value::Null))?;
+                return Ok(false);
+            }
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+        let launched = command == "launch" && result.is_ok();
+        self.respond(message, result)?;
+        // Configuration can only start once breakpoints can be mapped.
+        if launched {
+            self.event("initialized", Value::Null)?;
+        }
+        if let Some(stop) = self.pending.take() {
+            self.stop(stop)?;
+        }
+        Ok(true)
+    }
+
+    fn machine(&self) -> Result<&CHIPMachine, String> {
+        self.chip8.as_ref().ok_or_else(|| String::from("no program launched"))
+    }
+
+    fn launched(&self) -> Result<(), String> {
+        self.machine().map(|_| ())
+    }
+
+    // Arguments: `program`, and optionally `sourceMap`, `labels`, `quirks`,
+    // `seed` and `stopOnEntry`.
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"].as_str().ok_or("launch needs a program")?;
+        fs::metadata(program).map_err(|err| format!("{}: {}", program, err))?;
+        let mut options = Options {
+            rom: program.to_string(),
+            ..self.defaults.clone()
+        };
+        if let Some(name) = args["quirks"].as_str() {
+            options.quirks = Quirks::from_name(name)?;
+        }
+        if let Some(seed) = args["seed"].as_u64() {
+            options.seed = Some(seed);
+        }
+        let mut chip8 = options.machine()?;
+        chip8.disable_aot();
+        if let Some(path) = args["sourceMap"].as_str() {
+            self.map = SourceMap::load(path)?;
+        }
+        if let Some(path) = args["labels"].as_str() {
+            self.labels = read_labels(path)?.into_iter().map(|(name, addr)| (addr, name)).collect();
+        }
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.chip8 = Some(chip8);
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["source"]["path"].as_str().ok_or("breakpoints need a source path")?;
+        let requested = args["breakpoints"].as_array().map(Vec::as_slice).unwrap_or_default();
+        let mut set = Breakpoints::default();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as u32;
+            match self.map.address(Path::new(path), line) {
+                Some((addr, line)) => match conditions(&mut set, Place::Addr(addr), breakpoint) {
+                    Ok(()) => breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("{:#05X}", addr),
+                    })),
+                    Err(message) => breakpoints.push(json!({"verified": false, "line": line, "message": message})),
+                },
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no code on or after this line",
+                })),
+            }
+        }
+        self.breakpoints.insert(canonical(Path::new(path)), set);
+        Ok(json!({"breakpoints": breakpoints}))
+    }
+
+    // Replaces all function breakpoints.
+    fn set_function_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let requested = args["breakpoints"].as_array().map(Vec::as_slice).unwrap_or_default();
+        let resolve = |text: &str| match self.labels.iter().find(|(_, name)| *name == text) {
+            Some((&addr, _)) => Ok(addr),
+            None => parse_addr(text).map_err(|_| format!("no label or address '{}'", text)),
+        };
+        let mut set = Breakpoints::default();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let words: Vec<&str> = breakpoint["name"].as_str().unwrap_or_default().split_whitespace().collect();
+            let place = Place::parse(&words, resolve).and_then(|(place, used)| match words.get(used) {
+                Some(word) => Err(format!("unexpected '{}'", word)),
+                None => Ok(place),
+            });
+            match place.and_then(|place| conditions(&mut set, place, breakpoint)) {
+                Ok(()) => breakpoints.push(json!({"verified": true})),
+                Err(message) => breakpoints.push(json!({"verified": false, "message": message})),
+            }
+        }
+        self.functions = set;
+        Ok(json!({"breakpoints": breakpoints}))
+    }
+
+    fn step(&mut self, args: &Value, over: bool) -> Result<Value, String> {
+        let cpu = self.machine()?.cpu();
+        // Without a map there are no lines to step by.
+        let instruction = args["granularity"] == "instruction" || self.map.lines.is_empty();
+        self.mode = Mode::Line {
+            from: self.map.location(cpu.pc),
+            instruction,
+            sp: cpu.sp,
+            over,
+        };
+        Ok(Value::Null)
+    }
+
+    // The current instruction, then the call of every subroutine on the
+    // stack. Stack entries are the addresses of the calls.
+    fn stack_trace(&self, args: &Value) -> Result<Value, String> {
+        let cpu = self.machine()?.cpu();
+        let frames: Vec<Value> = std::iter::once(cpu.pc)
+            .chain(cpu.stack[..cpu.sp as usize].iter().rev().copied())
+            .enumerate()
+            .map(|(id, addr)| self.frame(id, addr))
+            .collect();
+        let total = frames.len();
+        let start = args["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = args["levels"].as_u64().filter(|&levels| levels > 0).unwrap_or(total as u64) as usize;
+        let frames: Vec<Value> = frames.into_iter().skip(start).take(levels).collect();
+        Ok(json!({"stackFrames": frames, "totalFrames": total}))
+    }
+
+    fn frame(&self, id: usize, addr: u16) -> Value {
+        // Named after the closest label at or below it.
+        let name = match self.labels.range(..=addr).next_back() {
+            Some((_, name)) => name.clone(),
+            None => format!("{:#05X}", addr),
+        };
+        let mut frame = json!({
+            "id": id,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("{:#05X}", addr),
+        });
+        if let Some((path, line)) = self.map.line(addr) {
+            let file = path.file_name().unwrap_or_default().to_string_lossy();
+            frame["source"] = json!({"name": file, "path": path});
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+        }
+        frame
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        let cpu = self.machine()?.cpu();
+        let variable = |name: String, value: String| json!({"name": name, "value": value, "variablesReference": 0});
+        let variables: Vec<Value> = match args["variablesReference"].as_u64() {
+            Some(REGISTERS) => {
+                let v = cpu.v.iter().enumerate().map(|(x, v)| variable(format!("V{:X}", x), format!("{:#04X}", v)));
+                let others = [
+                    ("I", format!("{:#05X}", cpu.i)),
+                    ("PC", format!("{:#05X}", cpu.pc)),
+                    ("SP", cpu.sp.to_string()),
+                    ("DT", format!("{:#04X}", cpu.delay_timer)),
+                    ("ST", format!("{:#04X}", cpu.sound_timer)),
+                ];
+                v.chain(others.into_iter().map(|(name, value)| variable(name.to_string(), value))).collect()
+            }
+            Some(MEMORY) => cpu
+                .ram
+                .chunks(ROW)
+                .enumerate()
+                .map(|(row, bytes)| {
+                    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                    variable(format!("{:#05X}", row * ROW), bytes.join(" "))
+                })
+                .collect(),
+            Some(SCREEN) => vec![json!({
+                "name": "screen",
+                "value": self.screen,
+                "type": "image/png",
+                "variablesReference": 0,
+                "presentationHint": {"kind": "data"},
+            })],
+            _ => return Err(String::from("unknown variables reference")),
+        };
+        Ok(json!({"variables": variables}))
+    }
+
+    // Runs the rest of the current frame, stopping early when something
+    // tells it to.
+    fn run_frame(&mut self) -> io::Result<()> {
+        while let Some(chip8) = self.chip8.as_mut() {
+            let ran = chip8.cpu().get_instruction();
+            let frame_done = chip8.step();
+            // Checked apart so a stop can say which kind of breakpoint it was.
+            let lines = breakpoint::check(self.breakpoints.values_mut().flatten(), chip8.cpu(), ran);
+            let functions = breakpoint::check(&mut self.functions, chip8.cpu(), ran);
+            for text in lines.logs.into_iter().chain(functions.logs) {
+                self.event("output", json!({"category": "console", "output": format!("{}\n", text)}))?;
+            }
+            let breakpoint = match (lines.stop, functions.stop) {
+                (Some(_), _) => Some(Stop::new("breakpoint")),
+                (None, Some(place)) => Some(Stop {
+                    reason: "function breakpoint",
+                    text: Some(place.to_string()),
+                }),
+                (None, None) => None,
+            };
+            if let Some(stop) = self.stop_reason(breakpoint) {
+                return self.stop(stop);
+            }
+            if frame_done {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // `breakpoint` is the stop the breakpoints asked for, if any.
+    fn stop_reason(&self, breakpoint: Option<Stop>) -> Option<Stop> {
+        let chip8 = self.chip8.as_ref()?;
+        let cpu = chip8.cpu();
+        if let Status::Faulted(fault) = chip8.status() {
+            return Some(Stop {
+                reason: "exception",
+                text: Some(fault.to_string()),
+            });
+        }
+        if breakpoint.is_some() {
+            return breakpoint;
+        }
+        let done = match self.mode {
+            Mode::Line {
+                from,
+                instruction,
+                sp,
+                over,
+            } => {
+                let new_line = self.map.lines.contains_key(&cpu.pc) && self.map.location(cpu.pc) != from;
+                (instruction || new_line) && (!over || cpu.sp <= sp)
+            }
+            Mode::Out { sp } => cpu.sp < sp,
+            Mode::Running | Mode::Paused => false,
+        };
+        done.then(|| Stop::new("step"))
+    }
+
+    // Pauses, keeping the screen for the `Screen` scope, and tells the editor.
+    fn stop(&mut self, stop: Stop) -> io::Result<()> {
+        self.mode = Mode::Paused;
+        if let Some(chip8) = &self.chip8 {
+            let (width, height) = chip8.size();
+            let mut png = Vec::new();
+            screenshot::encode_png(
+                &mut png,
+                width * SCREEN_SCALE,
+                height * SCREEN_SCALE,
+                &chip8.snapshot(SCREEN_SCALE),
+            )?;
+            self.screen = format!("data:image/png;base64,{}", base64(&png));
+        }
+        let mut body = json!({"reason": stop.reason, "threadId": THREAD, "allThreadsStopped": true});
+        if let Some(text) = stop.text {
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body)
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => (),
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({"type": "event", "event": event});
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        // In one write, so a TCP connection sends it as one segment.
+        let framed = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        self.output.write_all(framed.as_bytes())?;
+        self.output.flush()
+    }
+}
+
+// Adds a breakpoint at `place` with the `condition`, `hitCondition` and
+// `logMessage` of `request`. A hit condition is appended to `hit_count`, so
+// `> 5` or `% 2 == 0`, and a bare number N means `>= N`.
+fn conditions(set: &mut Breakpoints, place: Place, request: &Value) -> Result<(), String> {
+    let mut parts = Vec::new();
+    if let Some(condition) = request["condition"].as_str().filter(|text| !text.trim().is_empty()) {
+        parts.push(format!("({})", condition));
+    }
+    if let Some(hits) = request["hitCondition"].as_str().map(str::trim).filter(|text| !text.is_empty()) {
+        match hits.starts_with(|c: char| c.is_ascii_digit()) {
+            true => parts.push(format!("hit_count >= ({})", hits)),
+            false => parts.push(format!("(hit_count {})", hits)),
+        }
+    }
+    let mut breakpoint = Breakpoint::new(place);
+    if !parts.is_empty() {
+        breakpoint.condition = Some(Expr::parse(&parts.join(" && "))?);
+    }
+    if let Some(message) = request["logMessage"].as_str() {
+        breakpoint.log = Some(Message::parse(message)?);
+    }
+    set.insert(breakpoint);
+    Ok(())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (k, &byte)| n | (byte as u32) << (16 - 8 * k));
+        for k in 0..4 {
+            match k <= chunk.len() {
+                true => out.push(DIGITS[(n >> (18 - 6 * k) & 63) as usize] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
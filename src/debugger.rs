@@ -0,0 +1,553 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+
+use crate::breakpoint::{self, parse_addr, Breakpoint, Breakpoints, Place};
+use crate::chip_machine::{CHIPMachine, Status};
+use crate::emulation::Input;
+use crate::expr::Expr;
+use crate::watch::{Action, Watchpoint};
+
+const PROMPT: &str = "(chip8) ";
+const HELP: &str = "\
+break WHERE [if EXPR]
+                     stop there, only when EXPR isn't 0 (b). WHERE is an
+                     address or label, op PATTERN for instructions like
+                     Fx0A, class NAME for e.g. draw, or collision for just
+                     after a sprite hit lit pixels
+log WHERE TEXT       print TEXT there instead of stopping, with {EXPR}
+                     replaced by its value
+condition WHERE [EXPR]
+                     change or remove the condition of a breakpoint
+delete WHERE         remove a breakpoint or log point (d)
+breakpoints          list breakpoints and log points
+print EXPR           e.g. v3 == 0x10 && i > 0x300, ram[i + 1] or hit_count (p)
+label NAME [ADDR]    name an address, PC by default
+labels               list labels
+step [N]             run N instructions, into calls (s)
+next [N]             run N instructions, over calls (n)
+continue             run until a breakpoint (c)
+watch TARGET [read|write|access|change] [from N] [to N] [log]
+                     stop or log when V0-VF, I, DT, ST, an address or a range
+                     like 300-30F is read, written or changed (w)
+unwatch ID           remove a watchpoint
+watches              list watchpoints
+finish               run until the current subroutine returns
+pause                stop running
+regs                 V0-VF, I, PC, SP and the timers
+i | stack | timers   just those
+mem [ADDR [LEN]]     hex dump, by default around I (x)
+set REG VALUE        set V0-VF, I, PC, DT or ST, setting PC clears a fault
+poke ADDR BYTE...    write bytes to RAM
+screen               the display as ASCII art
+quit                 stop the emulator (q)
+Numbers are hex, with or without 0x, except in expressions, where they are
+decimal unless they start with 0x. An empty line repeats the last command.";
+
+// What the machine does between commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Paused,
+    // Until a breakpoint or fault.
+    Running,
+    // Until PC is back at `pc` with the stack no deeper than `sp`, e.g. after
+    // stepping over a call.
+    Until { pc: u16, sp: u16 },
+    // Until the stack is shallower than `sp`.
+    Finish { sp: u16 },
+}
+
+// gdb-like commands on a machine. Lives wherever the machine does, which is
+// the emulation thread when the window is open, and only runs instructions
+// one by one so it never misses a breakpoint.
+#[derive(Clone, Debug)]
+pub struct Debugger {
+    breakpoints: Breakpoints,
+    labels: BTreeMap<String, u16>,
+    mode: Mode,
+    // Hits of log watchpoints and log points, printed with the next output.
+    logged: Vec<String>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self {
+            breakpoints: Breakpoints::default(),
+            labels: BTreeMap::new(),
+            mode: Mode::Paused,
+            logged: Vec::new(),
+        }
+    }
+}
+
+impl Debugger {
+    pub fn load_labels(&mut self, path: &str) -> Result<(), String> {
+        self.labels.extend(read_labels(path)?);
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    // Runs the rest of the current frame unless paused, stopping early at a
+    // breakpoint, a watchpoint, a fault or the end of a `next` or `finish`.
+    // Returns what to tell the user when it stopped or logged something.
+    pub fn run_frame(&mut self, chip8: &mut CHIPMachine) -> Option<String> {
+        if self.is_paused() {
+            return None;
+        }
+        loop {
+            let ran = chip8.cpu().get_instruction();
+            let frame_done = chip8.step();
+            if let Some(reason) = self.stop_reason(chip8, ran) {
+                self.mode = Mode::Paused;
+                return Some(self.output(format!("{}\n{}", reason, self.location(chip8))));
+            }
+            if frame_done {
+                return (!self.logged.is_empty()).then(|| self.output(String::new()).trim_end().to_string());
+            }
+        }
+    }
+
+    // `ran` is the instruction that just ran.
+    fn stop_reason(&mut self, chip8: &mut CHIPMachine, ran: Option<u16>) -> Option<String> {
+        let watched = self.watch_hits(chip8);
+        let breakpoint = self.check_breakpoints(chip8, ran);
+        let cpu = chip8.cpu();
+        if let Status::Faulted(fault) = chip8.status() {
+            return Some(format!("Fault: {}", fault));
+        }
+        if watched.is_some() {
+            return watched;
+        }
+        let done = match self.mode {
+            Mode::Until { pc, sp } => cpu.pc == pc && cpu.sp <= sp,
+            Mode::Finish { sp } => cpu.sp < sp,
+            Mode::Running | Mode::Paused => false,
+        };
+        if let Some(place) = breakpoint {
+            Some(format!("Breakpoint at {}", self.describe_place(place)))
+        } else if done {
+            Some(String::from("Stopped"))
+        } else {
+            None
+        }
+    }
+
+    // Keeps the hits of log watchpoints for the next output and returns the
+    // first hit of one that breaks.
+    fn watch_hits(&mut self, chip8: &mut CHIPMachine) -> Option<String> {
+        let mut stop = None;
+        for hit in chip8.cpu_mut().watches.take_hits() {
+            match hit.action {
+                Action::Log => self.logged.push(hit.to_string()),
+                Action::Break => stop = stop.or(Some(hit.to_string())),
+            }
+        }
+        stop
+    }
+
+    // Keeps what log points printed for the next output and returns where to
+    // stop, if anywhere.
+    fn check_breakpoints(&mut self, chip8: &CHIPMachine, ran: Option<u16>) -> Option<Place> {
+        let check = breakpoint::check(&mut self.breakpoints, chip8.cpu(), ran);
+        self.logged.extend(check.logs);
+        check.stop
+    }
+
+    // `text` after anything logged since the last output.
+    fn output(&mut self, text: String) -> String {
+        let mut lines = std::mem::take(&mut self.logged);
+        lines.push(text);
+        lines.join("\n")
+    }
+
+    // Runs one command and returns its output.
+    pub fn command(&mut self, chip8: &mut CHIPMachine, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else {
+            return String::new();
+        };
+        let runs = matches!(name, "step" | "s" | "next" | "n" | "continue" | "c" | "finish");
+        if runs && !self.is_paused() {
+            return String::from("Error: already running, pause first");
+        }
+        let result = match name {
+            "help" | "h" => Ok(String::from(HELP)),
+            "break" | "b" => match args {
+                [] => Ok(Breakpoint::new(Place::Addr(chip8.cpu().pc))),
+                _ => Breakpoint::parse(args, |text| self.resolve(Some(text), chip8)),
+            }
+            .map(|breakpoint| self.add_breakpoint(breakpoint)),
+            "log" => Breakpoint::parse_log(args, |text| self.resolve(Some(text), chip8)).map(|breakpoint| self.add_breakpoint(breakpoint)),
+            "condition" => self.condition(chip8, args),
+            "delete" | "d" => self.place(chip8, args).and_then(|(place, _)| match self.breakpoints.remove(place) {
+                Some(breakpoint) => Ok(format!("Deleted breakpoint at {}", self.describe_breakpoint(&breakpoint))),
+                None => Err(format!("no breakpoint at {}", self.describe_place(place))),
+            }),
+            "breakpoints" => Ok(self
+                .breakpoints
+                .iter()
+                .map(|breakpoint| match breakpoint.hits {
+                    0 => self.describe_breakpoint(breakpoint),
+                    hits => format!("{}  ({} hits)", self.describe_breakpoint(breakpoint), hits),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "print" | "p" => Expr::parse(&args.join(" "))
+                .and_then(|expr| expr.eval(chip8.cpu(), 0))
+                .map(Expr::describe),
+            "watch" | "w" => self.watch(chip8, args),
+            "unwatch" => match args.first().map(|id| id.parse()) {
+                Some(Ok(id)) => match chip8.cpu_mut().watches.remove(id) {
+                    Some(watchpoint) => Ok(format!("Deleted watchpoint {}: {}", id, watchpoint)),
+                    None => Err(format!("no watchpoint {}", id)),
+                },
+                _ => Err(String::from("usage: unwatch ID")),
+            },
+            "watches" => Ok(chip8
+                .cpu()
+                .watches
+                .iter()
+                .map(|(id, watchpoint)| format!("{}  {}", id, watchpoint))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "label" => match args {
+                [_] => Ok(chip8.cpu().pc),
+                [_, addr] => self.resolve(Some(*addr), chip8),
+                _ => Err(String::from("usage: label NAME [ADDR]")),
+            }
+            .map(|addr| {
+                self.labels.insert(args[0].to_string(), addr);
+                format!("{} = {:#05X}", args[0], addr)
+            }),
+            "labels" => Ok(self
+                .labels
+                .iter()
+                .map(|(name, addr)| format!("{:#05X}  {}", addr, name))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "step" | "s" => self.count(args).map(|count| self.step(chip8, count, false)),
+            "next" | "n" => self.count(args).map(|count| self.step(chip8, count, true)),
+            "continue" | "c" => {
+                self.mode = Mode::Running;
+                Ok(String::from("Continuing"))
+            }
+            "finish" => match chip8.cpu().sp {
+                0 => Err(String::from("not in a subroutine")),
+                sp => {
+                    self.mode = Mode::Finish { sp };
+                    Ok(String::from("Running until the subroutine returns"))
+                }
+            },
+            "pause" => {
+                self.mode = Mode::Paused;
+                Ok(self.location(chip8))
+            }
+            "regs" | "registers" => Ok(registers(chip8)),
+            "i" => Ok(format!("I {:#05X}", chip8.cpu().i)),
+            "stack" => Ok(self.stack(chip8)),
+            "timers" => Ok(format!("DT {:02X}  ST {:02X}", chip8.cpu().delay_timer, chip8.cpu().sound_timer)),
+            "mem" | "x" => self.memory(chip8, args),
+            "set" => set(chip8, args),
+            "poke" => self.poke(chip8, args),
+            "screen" => Ok(chip8.cpu().display.to_string().trim_end().to_string()),
+            _ => Err(format!("unknown command '{}', try help", name)),
+        };
+        let text = result.unwrap_or_else(|err| format!("Error: {}", err));
+        self.output(text)
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> String {
+        let text = format!("Breakpoint at {}", self.describe_breakpoint(&breakpoint));
+        self.breakpoints.insert(breakpoint);
+        text
+    }
+
+    fn condition(&mut self, chip8: &CHIPMachine, args: &[&str]) -> Result<String, String> {
+        let (place, used) = self.place(chip8, args)?;
+        let condition = match &args[used..] {
+            [] => None,
+            words => Some(Expr::parse(&words.join(" "))?),
+        };
+        let Some(breakpoint) = self.breakpoints.get_mut(place) else {
+            return Err(format!("no breakpoint at {}", self.describe_place(place)));
+        };
+        breakpoint.condition = condition;
+        let breakpoint = breakpoint.clone();
+        Ok(format!("Breakpoint at {}", self.describe_breakpoint(&breakpoint)))
+    }
+
+    // A breakpoint location and how many words it took, PC when none is given.
+    fn place(&self, chip8: &CHIPMachine, args: &[&str]) -> Result<(Place, usize), String> {
+        match args {
+            [] => Ok((Place::Addr(chip8.cpu().pc), 0)),
+            _ => Place::parse(args, |text| self.resolve(Some(text), chip8)),
+        }
+    }
+
+    // Labels work for addresses, but not inside ranges.
+    fn watch(&self, chip8: &mut CHIPMachine, args: &[&str]) -> Result<String, String> {
+        let mut words = args.to_vec();
+        let resolved = args.first().and_then(|target| self.labels.get(*target)).map(|addr| format!("{:X}", addr));
+        if let Some(addr) = &resolved {
+            words[0] = addr;
+        }
+        let watchpoint = Watchpoint::parse(&words)?;
+        let text = watchpoint.to_string();
+        let id = chip8.cpu_mut().watches.add(watchpoint);
+        Ok(format!("Watchpoint {}: {}", id, text))
+    }
+
+    fn count(&self, args: &[&str]) -> Result<usize, String> {
+        match args.first() {
+            Some(text) => text.parse().map_err(|_| format!("invalid count '{}'", text)),
+            None => Ok(1),
+        }
+    }
+
+    // With `over`, a 2nnn call runs until it returns. That can take many
+    // frames, so it keeps running in the background like `continue`.
+    fn step(&mut self, chip8: &mut CHIPMachine, count: usize, over: bool) -> String {
+        for _ in 0..count {
+            let cpu = chip8.cpu();
+            let ran = cpu.get_instruction();
+            let calls = ran.is_some_and(|op| op >> 12 == 0x2);
+            if over && calls {
+                self.mode = Mode::Until { pc: cpu.pc + 2, sp: cpu.sp };
+                chip8.step();
+                if let Some(reason) = self.stop_reason(chip8, ran) {
+                    self.mode = Mode::Paused;
+                    return format!("{}\n{}", reason, self.location(chip8));
+                }
+                return String::from("Running until the call returns");
+            }
+            chip8.step();
+            let watched = self.watch_hits(chip8);
+            let breakpoint = self.check_breakpoints(chip8, ran);
+            if let Status::Faulted(fault) = chip8.status() {
+                return format!("Fault: {}\n{}", fault, self.location(chip8));
+            }
+            if let Some(hit) = watched {
+                return format!("{}\n{}", hit, self.location(chip8));
+            }
+            if breakpoint.is_some() {
+                break;
+            }
+        }
+        self.location(chip8)
+    }
+
+    fn stack(&self, chip8: &CHIPMachine) -> String {
+        let cpu = chip8.cpu();
+        if cpu.sp == 0 {
+            return String::from("Stack is empty");
+        }
+        // Each entry is the address of the call, the return goes just past it.
+        cpu.stack[..cpu.sp as usize]
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(depth, &call)| format!("#{}  called from {}", depth, self.describe(call)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn memory(&self, chip8: &CHIPMachine, args: &[&str]) -> Result<String, String> {
+        let cpu = chip8.cpu();
+        let (start, len) = match args {
+            [] => ((cpu.i as usize).saturating_sub(16) & !0xF, 48),
+            [addr] => (self.resolve(Some(*addr), chip8)? as usize, 16),
+            [addr, len, ..] => (self.resolve(Some(*addr), chip8)? as usize, parse_number(len)? as usize),
+        };
+        if start >= cpu.ram.len() {
+            return Err(format!("{:#05X} is past the end of RAM", start));
+        }
+        // `[` marks I.
+        Ok(hex_dump(&cpu.ram, start, len, cpu.i))
+    }
+
+    fn poke(&self, chip8: &mut CHIPMachine, args: &[&str]) -> Result<String, String> {
+        let [addr, bytes @ ..] = args else {
+            return Err(String::from("usage: poke ADDR BYTE..."));
+        };
+        let start = self.resolve(Some(*addr), chip8)? as usize;
+        let bytes: Vec<u8> = bytes.iter().map(|byte| parse_byte(byte)).collect::<Result<_, _>>()?;
+        if bytes.is_empty() || start + bytes.len() > chip8.cpu().ram.len() {
+            return Err(String::from("poke needs bytes that fit in RAM"));
+        }
+        for (offset, &byte) in bytes.iter().enumerate() {
+            chip8.cpu_mut().write_ram(start + offset, byte);
+        }
+        Ok(format!("Wrote {} bytes at {:#05X}", bytes.len(), start))
+    }
+
+    // An address from a label or a hex number, PC when none is given.
+    fn resolve(&self, arg: Option<&str>, chip8: &CHIPMachine) -> Result<u16, String> {
+        match arg {
+            None => Ok(chip8.cpu().pc),
+            Some(text) => match self.labels.get(text) {
+                Some(&addr) => Ok(addr),
+                None => parse_addr(text).map_err(|_| format!("no label or address '{}'", text)),
+            },
+        }
+    }
+
+    // `0x2A4 <draw+4>`, using the closest label at or below `addr`.
+    fn describe(&self, addr: u16) -> String {
+        let label = self
+            .labels
+            .iter()
+            .filter(|(_, &at)| at <= addr)
+            .max_by_key(|(_, &at)| at)
+            .map(|(name, &at)| match addr - at {
+                0 => format!(" <{}>", name),
+                offset => format!(" <{}+{}>", name, offset),
+            });
+        format!("{:#05X}{}", addr, label.unwrap_or_default())
+    }
+
+    fn describe_place(&self, place: Place) -> String {
+        match place {
+            Place::Addr(addr) => self.describe(addr),
+            place => place.to_string(),
+        }
+    }
+
+    fn describe_breakpoint(&self, breakpoint: &Breakpoint) -> String {
+        format!("{}{}", self.describe_place(breakpoint.place), breakpoint.details())
+    }
+
+    // Where PC is and the instruction there.
+    fn location(&self, chip8: &CHIPMachine) -> String {
+        let cpu = chip8.cpu();
+        let op = cpu.get_instruction().map_or(String::from("----"), |op| format!("{:04X}", op));
+        format!("{}  {}", self.describe(cpu.pc), op)
+    }
+}
+
+// Reads labels from lines of `NAME ADDR`. `#` starts a comment.
+pub fn read_labels(path: &str) -> Result<BTreeMap<String, u16>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let mut labels = BTreeMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, addr) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("{}:{}: expected NAME ADDR", path, number + 1))?;
+        let addr = parse_number(addr.trim()).map_err(|err| format!("{}:{}: {}", path, number + 1, err))?;
+        labels.insert(name.to_string(), addr);
+    }
+    Ok(labels)
+}
+
+// Rows of 16 bytes from `start`, cut off at the end of RAM, with `[` in
+// front of the byte at `mark`.
+pub fn hex_dump(ram: &[u8], start: usize, len: usize, mark: u16) -> String {
+    let end = (start + len).min(ram.len());
+    let mut out = String::new();
+    for row in (start..end).step_by(16) {
+        let _ = write!(out, "{:03X}:", row);
+        for (addr, byte) in ram[row..(row + 16).min(end)].iter().enumerate() {
+            let gap = if row + addr == mark as usize { '[' } else { ' ' };
+            let _ = write!(out, "{}{:02X}", gap, byte);
+        }
+        out.push('\n');
+    }
+    out.trim_end().to_string()
+}
+
+pub fn registers(chip8: &CHIPMachine) -> String {
+    let cpu = chip8.cpu();
+    let v: Vec<String> = cpu.v.iter().enumerate().map(|(x, v)| format!("V{:X} {:02X}", x, v)).collect();
+    format!(
+        "{}\n{}\nI {:#05X}  PC {:#05X}  SP {:X}  DT {:02X}  ST {:02X}",
+        v[..8].join("  "),
+        v[8..].join("  "),
+        cpu.i,
+        cpu.pc,
+        cpu.sp,
+        cpu.delay_timer,
+        cpu.sound_timer
+    )
+}
+
+fn set(chip8: &mut CHIPMachine, args: &[&str]) -> Result<String, String> {
+    let [register, value] = args else {
+        return Err(String::from("usage: set REG VALUE"));
+    };
+    let value = parse_number(value)?;
+    let byte = || u8::try_from(value).map_err(|_| format!("{:#X} doesn't fit in a byte", value));
+    let register = register.to_ascii_lowercase();
+    if register == "pc" {
+        if value as usize > crate::processor::LAST_PC {
+            return Err(format!("PC {:#X} is past the end of RAM", value));
+        }
+        chip8.set_pc(value);
+        return Ok(format!("PC = {:#X}", value));
+    }
+    let cpu = chip8.cpu_mut();
+    match register.as_str() {
+        "i" => cpu.i = value,
+        "dt" => cpu.delay_timer = byte()?,
+        "st" => cpu.sound_timer = byte()?,
+        _ => {
+            let x = register
+                .strip_prefix('v')
+                .and_then(|x| usize::from_str_radix(x, 16).ok())
+                .filter(|&x| x < 16)
+                .ok_or_else(|| format!("unknown register '{}'", register))?;
+            cpu.v[x] = byte()?;
+        }
+    }
+    Ok(format!("{} = {:#X}", register.to_uppercase(), value))
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid number '{}'", text))
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+    parse_number(text).and_then(|value| u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", text)))
+}
+
+// The prompt, on stdin and stdout. Commands go to the emulation thread as
+// `Input::Debug` and everything it sends back on `output` is printed, also
+// when it arrives between commands, e.g. a breakpoint hit while continuing.
+// Returns once the emulation thread has stopped after `quit` or end of input.
+pub fn repl(input: Sender<Input>, output: Receiver<String>) {
+    let printer = thread::spawn(move || {
+        print!("{}", PROMPT);
+        let _ = io::stdout().flush();
+        for text in output {
+            if !text.is_empty() {
+                println!("\r{}", text);
+            }
+            print!("{}", PROMPT);
+            let _ = io::stdout().flush();
+        }
+        println!();
+    });
+    let mut last = String::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let line = match line.trim() {
+            "" => last.clone(),
+            line => line.to_string(),
+        };
+        if matches!(line.as_str(), "quit" | "q") || input.send(Input::Debug(line.clone())).is_err() {
+            break;
+        }
+        last = line;
+    }
+    let _ = input.send(Input::Quit);
+    let _ = printer.join();
+}
@@ -0,0 +1,305 @@
+use crate::font::SMALL_GLYPH_SZ;
+use crate::processor::{split_nibbles, Fault, Processor, ProcessorAction, RAM_SZ};
+
+// An instruction with its operands pulled out, named after Cowgod's
+// mnemonics. Registers are kept as indices below 16. Only the common
+// instructions get a variant, the rest run through `Processor::execute`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decoded {
+    Cls,
+    Ret,
+    Jp(u16),
+    Call(u16),
+    SeImm(u8, u8),
+    SneImm(u8, u8),
+    Se(u8, u8),
+    LdImm(u8, u8),
+    AddImm(u8, u8),
+    Ld(u8, u8),
+    Or(u8, u8),
+    And(u8, u8),
+    Xor(u8, u8),
+    Add(u8, u8),
+    Sub(u8, u8),
+    Shr(u8, u8),
+    Subn(u8, u8),
+    Shl(u8, u8),
+    Sne(u8, u8),
+    LdI(u16),
+    // nnn and the register Bnnn adds with `quirks.jump_vx`.
+    JpV(u16, u8),
+    Drw(u8, u8, u8),
+    Skp(u8),
+    Sknp(u8),
+    LdVxDt(u8),
+    LdDt(u8),
+    LdSt(u8),
+    AddI(u8),
+    LdF(u8),
+    Other(u16),
+}
+
+impl Decoded {
+    // Whether PC might go anywhere but the next instruction or the one after.
+    // `Processor::run` stops after these.
+    pub fn ends_block(&self) -> bool {
+        matches!(self, Decoded::Ret | Decoded::Jp(_) | Decoded::Call(_) | Decoded::JpV(..) | Decoded::Other(_))
+    }
+}
+
+// One decoded instruction per RAM address, filled in the first time an
+// address is executed. Anything that writes RAM must call `invalidate`.
+#[derive(Clone, Debug)]
+pub struct DecodeCache {
+    entries: Box<[Option<Decoded>; RAM_SZ]>,
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        Self {
+            entries: Box::new([None; RAM_SZ]),
+        }
+    }
+}
+
+impl DecodeCache {
+    // `pc` must be a RAM address, `ram` all of RAM.
+    #[inline(always)]
+    pub fn get_or_decode(&mut self, ram: &[u8; RAM_SZ], pc: u16) -> Decoded {
+        let pc = pc as usize % RAM_SZ;
+        match self.entries[pc] {
+            Some(decoded) => decoded,
+            None => self.decode_into(ram, pc),
+        }
+    }
+
+    #[cold]
+    fn decode_into(&mut self, ram: &[u8; RAM_SZ], pc: usize) -> Decoded {
+        // Like `Processor::get_instruction`, running off the end of RAM reads 0x0000.
+        let op = match ram.get(pc + 1) {
+            Some(&low) => u16::from_be_bytes([ram[pc], low]),
+            None => 0,
+        };
+        let decoded = decode(op);
+        self.entries[pc] = Some(decoded);
+        decoded
+    }
+
+    // `addr` is the low byte of the instruction before it and the high byte of its own.
+    pub fn invalidate(&mut self, addr: usize) {
+        self.entries[addr] = None;
+        if addr > 0 {
+            self.entries[addr - 1] = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+}
+
+// Everything `step` caches gets a variant except Dxy0, whatever `execute`
+// makes of it, and the instructions that write RAM, so they invalidate it.
+pub fn decode(op: u16) -> Decoded {
+    let nibbles = split_nibbles(op);
+    let (x, y, n) = (nibbles.1, nibbles.2, nibbles.3);
+    let (nnn, kk) = (op & 0x0FFF, op as u8);
+    match nibbles {
+        (0x0, 0x0, 0xE, 0x0) => Decoded::Cls,
+        (0x0, 0x0, 0xE, 0xE) => Decoded::Ret,
+        (0x1, ..) => Decoded::Jp(nnn),
+        (0x2, ..) => Decoded::Call(nnn),
+        (0x3, ..) => Decoded::SeImm(x, kk),
+        (0x4, ..) => Decoded::SneImm(x, kk),
+        (0x5, ..) => Decoded::Se(x, y),
+        (0x6, ..) => Decoded::LdImm(x, kk),
+        (0x7, ..) => Decoded::AddImm(x, kk),
+        (0x8, _, _, 0x0) => Decoded::Ld(x, y),
+        (0x8, _, _, 0x1) => Decoded::Or(x, y),
+        (0x8, _, _, 0x2) => Decoded::And(x, y),
+        (0x8, _, _, 0x3) => Decoded::Xor(x, y),
+        (0x8, _, _, 0x4) => Decoded::Add(x, y),
+        (0x8, _, _, 0x5) => Decoded::Sub(x, y),
+        (0x8, _, _, 0x6) => Decoded::Shr(x, y),
+        (0x8, _, _, 0x7) => Decoded::Subn(x, y),
+        (0x8, _, _, 0xE) => Decoded::Shl(x, y),
+        (0x9, _, _, 0x0) => Decoded::Sne(x, y),
+        (0xA, ..) => Decoded::LdI(nnn),
+        (0xB, ..) => Decoded::JpV(nnn, x),
+        (0xD, _, _, 0x0) => Decoded::Other(op),
+        (0xD, ..) => Decoded::Drw(x, y, n),
+        (0xE, _, 0x9, 0xE) => Decoded::Skp(x),
+        (0xE, _, 0xA, 0x1) => Decoded::Sknp(x),
+        (0xF, _, 0x0, 0x7) => Decoded::LdVxDt(x),
+        (0xF, _, 0x1, 0x5) => Decoded::LdDt(x),
+        (0xF, _, 0x1, 0x8) => Decoded::LdSt(x),
+        (0xF, _, 0x1, 0xE) => Decoded::AddI(x),
+        (0xF, _, 0x2, 0x9) => Decoded::LdF(x),
+        _ => Decoded::Other(op),
+    }
+}
+
+// Runs a decoded instruction at `pc`, doing exactly what `Processor::execute`
+// does for its opcode except checking watchpoints, so `Processor::run` only
+// comes here while there are none. Returns where PC goes next, which is left
+// for the caller to check and set. A fault is set here and keeps PC on the
+// instruction; only instructions that end a block can fault.
+#[inline(always)]
+pub fn run(cpu: &mut Processor, decoded: Decoded, pc: u16) -> usize {
+    let v = |reg: u8| reg as usize & 0xF;
+    let (next, skip) = (pc as usize + 2, pc as usize + 4);
+    let skip_if = |skip_it: bool| if skip_it { skip } else { next };
+    match decoded {
+        Decoded::Cls => {
+            cpu.display.clear();
+            next
+        }
+        Decoded::Ret => {
+            if cpu.sp == 0 {
+                cpu.fault = Some(Fault::StackUnderflow);
+                return pc as usize;
+            }
+            cpu.sp -= 1;
+            cpu.stack[cpu.sp as usize & 0xF] as usize + 2
+        }
+        Decoded::Jp(nnn) => nnn as usize,
+        Decoded::Call(nnn) => {
+            if cpu.sp as usize == cpu.stack.len() {
+                cpu.fault = Some(Fault::StackOverflow);
+                return pc as usize;
+            }
+            cpu.stack[cpu.sp as usize & 0xF] = pc;
+            cpu.sp += 1;
+            nnn as usize
+        }
+        Decoded::SeImm(x, kk) => skip_if(cpu.v[v(x)] == kk),
+        Decoded::SneImm(x, kk) => skip_if(cpu.v[v(x)] != kk),
+        Decoded::Se(x, y) => skip_if(cpu.v[v(x)] == cpu.v[v(y)]),
+        Decoded::LdImm(x, kk) => {
+            cpu.v[v(x)] = kk;
+            next
+        }
+        Decoded::AddImm(x, kk) => {
+            cpu.v[v(x)] = cpu.v[v(x)].wrapping_add(kk);
+            next
+        }
+        Decoded::Ld(x, y) => {
+            cpu.v[v(x)] = cpu.v[v(y)];
+            next
+        }
+        Decoded::Or(x, y) => {
+            logic(cpu, v(x), v(y), |vx, vy| vx | vy);
+            next
+        }
+        Decoded::And(x, y) => {
+            logic(cpu, v(x), v(y), |vx, vy| vx & vy);
+            next
+        }
+        Decoded::Xor(x, y) => {
+            logic(cpu, v(x), v(y), |vx, vy| vx ^ vy);
+            next
+        }
+        // The flag is written last, so it wins when x is F.
+        Decoded::Add(x, y) => {
+            let (result, carry) = cpu.v[v(x)].overflowing_add(cpu.v[v(y)]);
+            cpu.v[v(x)] = result;
+            cpu.v[0xF] = carry as u8;
+            next
+        }
+        Decoded::Sub(x, y) => {
+            let (result, borrow) = cpu.v[v(x)].overflowing_sub(cpu.v[v(y)]);
+            cpu.v[v(x)] = result;
+            cpu.v[0xF] = !borrow as u8;
+            next
+        }
+        Decoded::Shr(x, y) => {
+            let value = cpu.v[if cpu.quirks.shift_vx { v(x) } else { v(y) }];
+            cpu.v[v(x)] = value >> 1;
+            cpu.v[0xF] = value & 1;
+            next
+        }
+        Decoded::Subn(x, y) => {
+            let (result, borrow) = cpu.v[v(y)].overflowing_sub(cpu.v[v(x)]);
+            cpu.v[v(x)] = result;
+            cpu.v[0xF] = !borrow as u8;
+            next
+        }
+        Decoded::Shl(x, y) => {
+            let value = cpu.v[if cpu.quirks.shift_vx { v(x) } else { v(y) }];
+            cpu.v[v(x)] = value << 1;
+            cpu.v[0xF] = value >> 7;
+            next
+        }
+        Decoded::Sne(x, y) => skip_if(cpu.v[v(x)] != cpu.v[v(y)]),
+        Decoded::LdI(nnn) => {
+            cpu.i = nnn;
+            next
+        }
+        Decoded::JpV(nnn, x) => {
+            let offset = cpu.v[if cpu.quirks.jump_vx { v(x) } else { 0 }];
+            nnn as usize + offset as usize
+        }
+        Decoded::Drw(x, y, n) => {
+            let collision = draw(cpu, cpu.v[v(x)] as usize, cpu.v[v(y)] as usize, n as usize);
+            cpu.v[0xF] = collision as u8;
+            next
+        }
+        Decoded::Skp(x) => skip_if(cpu.keypad[v(cpu.v[v(x)])]),
+        Decoded::Sknp(x) => skip_if(!cpu.keypad[v(cpu.v[v(x)])]),
+        Decoded::LdVxDt(x) => {
+            cpu.v[v(x)] = cpu.delay_timer;
+            next
+        }
+        Decoded::LdDt(x) => {
+            cpu.delay_timer = cpu.v[v(x)];
+            next
+        }
+        Decoded::LdSt(x) => {
+            cpu.sound_timer = cpu.v[v(x)];
+            next
+        }
+        Decoded::AddI(x) => {
+            cpu.i = cpu.i.wrapping_add(cpu.v[v(x)] as u16);
+            next
+        }
+        Decoded::LdF(x) => {
+            cpu.i = cpu.font_base + v(cpu.v[v(x)]) as u16 * SMALL_GLYPH_SZ as u16;
+            next
+        }
+        Decoded::Other(op) => {
+            cpu.pc = pc;
+            match cpu.execute(op) {
+                ProcessorAction::NextInstruction => next,
+                ProcessorAction::SkipInstruction => skip,
+                ProcessorAction::JumpInstruction(j) => j as usize,
+                ProcessorAction::RepeatInstruction => pc as usize,
+                ProcessorAction::Fault(fault) => {
+                    cpu.fault = Some(fault);
+                    pc as usize
+                }
+            }
+        }
+    }
+}
+
+// 8xy1 to 8xy3. With `quirks.vf_reset` VF is reset after the result is stored.
+#[inline(always)]
+fn logic(cpu: &mut Processor, x: usize, y: usize, f: fn(u8, u8) -> u8) {
+    cpu.v[x] = f(cpu.v[x], cpu.v[y]);
+    if cpu.quirks.vf_reset {
+        cpu.v[0xF] = 0;
+    }
+}
+
+// Draws straight from RAM unless the sprite wraps around its end.
+fn draw(cpu: &mut Processor, x: usize, y: usize, n: usize) -> bool {
+    let i = cpu.i as usize;
+    if i + n <= RAM_SZ {
+        return cpu.display.draw_sprite(x, y, &cpu.ram[i..i + n], cpu.quirks.clip);
+    }
+    let mut sprite = [0; 15];
+    for (row, byte) in sprite.iter_mut().enumerate().take(n) {
+        *byte = cpu.ram[(i + row) % RAM_SZ];
+    }
+    cpu.display.draw_sprite(x, y, &sprite[..n], cpu.quirks.clip)
+}
@@ -0,0 +1,126 @@
+use crate::processor::split_nibbles;
+
+// What an instruction does, for filtering traces. Every opcode belongs to
+// exactly one class.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Class {
+    // Jumps, calls and returns.
+    Flow,
+    // Conditional skips on registers.
+    Skip,
+    // Loads of constants, I and the font addresses.
+    Load,
+    // Arithmetic and logic, including random numbers.
+    Alu,
+    // Reads and writes of RAM at I.
+    Memory,
+    // Anything that changes the screen.
+    Draw,
+    // Key tests and waits.
+    Key,
+    Timer,
+    // Opcodes the interpreter faults on.
+    Unknown,
+}
+
+impl Class {
+    pub const ALL: [Class; 9] = [
+        Class::Flow,
+        Class::Skip,
+        Class::Load,
+        Class::Alu,
+        Class::Memory,
+        Class::Draw,
+        Class::Key,
+        Class::Timer,
+        Class::Unknown,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Class::Flow => "flow",
+            Class::Skip => "skip",
+            Class::Load => "load",
+            Class::Alu => "alu",
+            Class::Memory => "memory",
+            Class::Draw => "draw",
+            Class::Key => "key",
+            Class::Timer => "timer",
+            Class::Unknown => "unknown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Class::ALL.into_iter().find(|class| class.name() == name)
+    }
+}
+
+// Cowgod's mnemonics, e.g. `DRW V0, V1, 5`. Opcodes the interpreter faults
+// on come out as `DW 0x0123`.
+pub fn disassemble(op: u16) -> String {
+    let nibbles = split_nibbles(op);
+    let (x, y, n) = (nibbles.1, nibbles.2, nibbles.3);
+    let nnn = op & 0xFFF;
+    let kk = op as u8;
+    match nibbles {
+        (0x0, 0x0, 0xF, 0xF) => String::from("HIGH"),
+        (0x0, 0x0, 0xE, 0x0) => String::from("CLS"),
+        (0x0, 0x0, 0xE, 0xE) => String::from("RET"),
+        (0x0, 0x0, 0xC, _) => format!("SCD {}", n),
+        (0x0, 0x0, 0xF, 0xB) => String::from("SCR"),
+        (0x0, 0x0, 0xF, 0xC) => String::from("SCL"),
+        (0x0, 0x0, 0xF, 0xD) => String::from("EXIT"),
+        (0x0, 0x0, 0xF, 0xE) => String::from("LOW"),
+        (0x1, ..) => format!("JP {:#05X}", nnn),
+        (0x2, ..) => format!("CALL {:#05X}", nnn),
+        (0x3, ..) => format!("SE V{:X}, {:#04X}", x, kk),
+        (0x4, ..) => format!("SNE V{:X}, {:#04X}", x, kk),
+        (0x5, ..) => format!("SE V{:X}, V{:X}", x, y),
+        (0x6, ..) => format!("LD V{:X}, {:#04X}", x, kk),
+        (0x7, ..) => format!("ADD V{:X}, {:#04X}", x, kk),
+        (0x8, _, _, 0x0) => format!("LD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x1) => format!("OR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x2) => format!("AND V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x3) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x4) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x5) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x6) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x9, _, _, 0x0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, ..) => format!("LD I, {:#05X}", nnn),
+        (0xB, ..) => format!("JP V0, {:#05X}", nnn),
+        (0xC, ..) => format!("RND V{:X}, {:#04X}", x, kk),
+        (0xD, ..) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0xE, _, 0x9, 0xE) => format!("SKP V{:X}", x),
+        (0xE, _, 0xA, 0x1) => format!("SKNP V{:X}", x),
+        (0xF, _, 0x0, 0x7) => format!("LD V{:X}, DT", x),
+        (0xF, _, 0x0, 0xA) => format!("LD V{:X}, K", x),
+        (0xF, _, 0x1, 0x5) => format!("LD DT, V{:X}", x),
+        (0xF, _, 0x1, 0x8) => format!("LD ST, V{:X}", x),
+        (0xF, _, 0x1, 0xE) => format!("ADD I, V{:X}", x),
+        (0xF, _, 0x2, 0x9) => format!("LD F, V{:X}", x),
+        (0xF, _, 0x3, 0x0) => format!("LD HF, V{:X}", x),
+        (0xF, _, 0x3, 0x3) => format!("LD B, V{:X}", x),
+        (0xF, _, 0x5, 0x5) => format!("LD [I], V{:X}", x),
+        (0xF, _, 0x6, 0x5) => format!("LD V{:X}, [I]", x),
+        (0xF, _, 0x7, 0x5) => format!("LD R, V{:X}", x),
+        (0xF, _, 0x8, 0x5) => format!("LD V{:X}, R", x),
+        _ => format!("DW {:#06X}", op),
+    }
+}
+
+pub fn classify(op: u16) -> Class {
+    match split_nibbles(op) {
+        (0x0, 0x0, 0xE, 0xE) | (0x0, 0x0, 0xF, 0xD) | (0x1 | 0x2 | 0xB, ..) => Class::Flow,
+        (0x0, 0x0, 0xE, 0x0) | (0x0, 0x0, 0xC, _) | (0x0, 0x0, 0xF, 0xB | 0xC | 0xE | 0xF) | (0xD, ..) => Class::Draw,
+        (0x3..=0x5, ..) | (0x9, _, _, 0x0) => Class::Skip,
+        (0x6 | 0xA, ..) | (0x8, _, _, 0x0) | (0xF, _, 0x2, 0x9) | (0xF, _, 0x3, 0x0) => Class::Load,
+        (0xF, _, 0x7 | 0x8, 0x5) => Class::Load,
+        (0x7 | 0xC, ..) | (0x8, _, _, 0x1..=0x7 | 0xE) | (0xF, _, 0x1, 0xE) => Class::Alu,
+        (0xF, _, 0x3, 0x3) | (0xF, _, 0x5 | 0x6, 0x5) => Class::Memory,
+        (0xE, _, 0x9, 0xE) | (0xE, _, 0xA, 0x1) | (0xF, _, 0x0, 0xA) => Class::Key,
+        (0xF, _, 0x0, 0x7) | (0xF, _, 0x1, 0x5 | 0x8) => Class::Timer,
+        _ => Class::Unknown,
+    }
+}
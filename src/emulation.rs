@@ -0,0 +1,285 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use log::{error, info};
+use triple_buffer::triple_buffer;
+use winit::event::VirtualKeyCode;
+
+use crate::chip_machine::{CHIPMachine, Status, FRAME_DURATION};
+use crate::crash;
+use crate::debugger::Debugger;
+use crate::recorder::Recorder;
+use crate::screenshot;
+
+// Sent from the window to the emulation thread.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Key(VirtualKeyCode, bool),
+    CyclePalette,
+    Screenshot,
+    ToggleRecording,
+    // A debugger command, only handled when spawned with a debugger.
+    Debug(String),
+    Quit,
+}
+
+// A finished frame, ready to be copied to the screen.
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    pub rgba: Vec<u8>,
+    // Changes when the ROM switches resolution.
+    pub width: usize,
+    pub height: usize,
+    // Total instructions executed when the frame was finished.
+    pub instructions: u64,
+}
+
+// Where F10 and F12 save their files, and crash reports go.
+#[derive(Clone, Debug)]
+pub struct Captures {
+    pub dir: PathBuf,
+    pub screenshot_scale: usize,
+    pub record_scale: usize,
+    pub crash_reports: bool,
+}
+
+// The machine running on its own thread at 60 Hz, so window moves and
+// resizes don't stall it. Frames come back through a triple buffer, which
+// never blocks either side and always hands the window the latest one.
+pub struct Emulation {
+    input: Sender<Input>,
+    frames: triple_buffer::Output<Frame>,
+    // Messages for the on-screen display, e.g. "Screenshot saved".
+    messages: Receiver<String>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Emulation {
+    // Starts running `chip8`. `wake` is called from the emulation thread after
+    // each new frame; with `every_frame` that is every 60 Hz frame, otherwise
+    // only the frames where the picture changed.
+    pub fn spawn(chip8: CHIPMachine, captures: Captures, every_frame: bool, wake: impl Fn() + Send + 'static) -> Self {
+        Self::start(chip8, None, captures, every_frame, wake)
+    }
+
+    // Like `spawn`, with `debugger` in control of the machine. It starts out
+    // paused. Replies to `Input::Debug` and breakpoint hits come out of the
+    // returned receiver, which disconnects when the thread stops.
+    pub fn spawn_debug(
+        chip8: CHIPMachine,
+        debugger: Debugger,
+        captures: Captures,
+        every_frame: bool,
+        wake: impl Fn() + Send + 'static,
+    ) -> (Self, Receiver<String>) {
+        let (output, replies) = mpsc::channel();
+        let emulation = Self::start(chip8, Some((debugger, output)), captures, every_frame, wake);
+        (emulation, replies)
+    }
+
+    fn start(
+        chip8: CHIPMachine,
+        debug: Option<(Debugger, Sender<String>)>,
+        captures: Captures,
+        every_frame: bool,
+        wake: impl Fn() + Send + 'static,
+    ) -> Self {
+        let (width, height) = chip8.output_size();
+        let (frames_in, frames) = triple_buffer(&Frame {
+            rgba: vec![0; 4 * width * height],
+            width,
+            height,
+            instructions: 0,
+        });
+        let (input, inputs) = mpsc::channel();
+        let (messages_in, messages) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let mut emulator = Emulator {
+                chip8,
+                captures,
+                frames: frames_in,
+                messages: messages_in,
+                recorder: None,
+                crashed: false,
+                rgba: vec![0; 4 * width * height],
+                debug,
+            };
+            emulator.run(inputs, every_frame, wake);
+        });
+        Self {
+            input,
+            frames,
+            messages,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn send(&self, input: Input) {
+        // The thread only goes away after `stop`.
+        let _ = self.input.send(input);
+    }
+
+    // For sending from another thread, e.g. the debugger prompt.
+    pub fn sender(&self) -> Sender<Input> {
+        self.input.clone()
+    }
+
+    // The most recent frame.
+    pub fn frame(&mut self) -> &Frame {
+        self.frames.read()
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = String> + '_ {
+        self.messages.try_iter()
+    }
+
+    // Stops the thread, finishing any recording first.
+    pub fn stop(&mut self) {
+        self.send(Input::Quit);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("emulation thread panicked");
+            }
+        }
+    }
+}
+
+struct Emulator {
+    chip8: CHIPMachine,
+    captures: Captures,
+    frames: triple_buffer::Input<Frame>,
+    messages: Sender<String>,
+    recorder: Option<Recorder>,
+    // Whether the current fault has been reported already.
+    crashed: bool,
+    // The machine only redraws what changed, so it keeps its own copy of the
+    // picture rather than drawing into whichever buffer is free.
+    rgba: Vec<u8>,
+    // The debugger and where its output goes.
+    debug: Option<(Debugger, Sender<String>)>,
+}
+
+impl Emulator {
+    fn run(&mut self, inputs: Receiver<Input>, every_frame: bool, wake: impl Fn()) {
+        let mut next_frame = Instant::now() + FRAME_DURATION;
+        loop {
+            match inputs.recv_timeout(next_frame.saturating_duration_since(Instant::now())) {
+                Ok(Input::Quit) | Err(RecvTimeoutError::Disconnected) => break,
+                Ok(input) => self.handle(input),
+                Err(RecvTimeoutError::Timeout) => {
+                    match self.debug.as_mut() {
+                        Some((debugger, output)) => {
+                            if let Some(stopped) = debugger.run_frame(&mut self.chip8) {
+                                let _ = output.send(stopped);
+                            }
+                        }
+                        None => self.chip8.run_frame(),
+                    }
+                    self.check_fault();
+                    self.record();
+                    let (width, height) = self.chip8.output_size();
+                    if self.rgba.len() != 4 * width * height {
+                        self.rgba = vec![0; 4 * width * height];
+                    }
+                    if self.chip8.draw(&mut self.rgba) || every_frame {
+                        let frame = self.frames.input_buffer_mut();
+                        frame.rgba.clone_from(&self.rgba);
+                        (frame.width, frame.height) = (width, height);
+                        frame.instructions = self.chip8.cycle_count;
+                        self.frames.publish();
+                        wake();
+                    }
+
+                    // Don't try to catch up after a long stall, e.g. a suspended process.
+                    next_frame += FRAME_DURATION;
+                    let now = Instant::now();
+                    if next_frame + 4 * FRAME_DURATION < now {
+                        next_frame = now;
+                    }
+                }
+            }
+        }
+        if let Some(active) = self.recorder.take() {
+            if let Err(err) = active.finish() {
+                error!("unable to save recording: {}", err);
+            }
+        }
+    }
+
+    fn handle(&mut self, input: Input) {
+        match input {
+            Input::Key(key, pressed) => self.chip8.process_key(key, pressed),
+            Input::CyclePalette => {
+                let name = self.chip8.cycle_palette().name.clone();
+                info!("palette: {}", name);
+                self.notify(format!("Palette {}", name));
+            }
+            Input::Screenshot => {
+                match screenshot::save(&self.chip8, &self.captures.dir, self.captures.screenshot_scale) {
+                    Ok(path) => {
+                        info!("saved screenshot {}", path.display());
+                        self.notify("Screenshot saved");
+                    }
+                    Err(err) => error!("unable to save screenshot: {}", err),
+                }
+            }
+            Input::ToggleRecording => match self.recorder.take() {
+                Some(active) => match active.finish() {
+                    Ok(path) => {
+                        info!("saved recording {}", path.display());
+                        self.notify("Recording saved");
+                    }
+                    Err(err) => error!("unable to save recording: {}", err),
+                },
+                None => match Recorder::start(&self.chip8, &self.captures.dir, self.captures.record_scale) {
+                    Ok(started) => {
+                        info!("recording to {}", started.path().display());
+                        self.notify("Recording");
+                        self.recorder = Some(started);
+                    }
+                    Err(err) => error!("unable to start recording: {}", err),
+                },
+            },
+            Input::Debug(line) => {
+                if let Some((debugger, output)) = self.debug.as_mut() {
+                    let _ = output.send(debugger.command(&mut self.chip8, &line));
+                }
+            }
+            Input::Quit => (),
+        }
+    }
+
+    // Writes a crash report the first frame the machine is found faulted.
+    fn check_fault(&mut self) {
+        let faulted = matches!(self.chip8.status(), Status::Faulted(_));
+        if faulted && !self.crashed && self.captures.crash_reports {
+            match crash::save(&self.chip8, &self.captures.dir, self.captures.screenshot_scale) {
+                Ok(path) => {
+                    error!("ROM faulted, saved crash report {}", path.display());
+                    self.notify("Crashed, report saved");
+                }
+                Err(err) => error!("unable to save crash report: {}", err),
+            }
+        }
+        self.crashed = faulted;
+    }
+
+    fn record(&mut self) {
+        if let Some(active) = self.recorder.as_mut() {
+            if let Err(err) = active.push(&self.chip8) {
+                error!("recording stopped: {}", err);
+                if let Some(Err(err)) = self.recorder.take().map(Recorder::finish) {
+                    error!("unable to save recording: {}", err);
+                }
+            }
+        }
+    }
+
+    fn notify(&self, text: impl Into<String>) {
+        let _ = self.messages.send(text.into());
+    }
+}
@@ -0,0 +1,367 @@
+use std::fmt;
+
+use crate::processor::Processor;
+
+// Binary operators from loosest to tightest binding, as in Rust, so
+// `opcode & 0xF000 == 0xD000` masks before it compares.
+const LEVELS: [&[&str]; 9] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<=", ">=", "<", ">"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+// How deep expressions may nest, counting every operator on the way down.
+// Parsing and evaluating both recurse, so this keeps text from a remote
+// debugger from overflowing the stack.
+const MAX_DEPTH: usize = 64;
+// Longest first, so `<=` isn't read as `<` then `=`.
+const SYMBOLS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "|", "^", "&", "+", "-", "*", "/", "%", "!", "~", "(", ")",
+    "[", "]",
+];
+
+// Machine state an expression can name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Var {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+    // The instruction at PC, about to run.
+    Opcode,
+    // How often the breakpoint has been reached, this time included.
+    HitCount,
+}
+
+impl Var {
+    fn from_name(name: &str) -> Option<Self> {
+        let var = match name.to_ascii_lowercase().as_str() {
+            "i" => Var::I,
+            "pc" => Var::Pc,
+            "sp" => Var::Sp,
+            "dt" => Var::Dt,
+            "st" => Var::St,
+            "opcode" => Var::Opcode,
+            "hit_count" => Var::HitCount,
+            name => {
+                let x = name.strip_prefix('v').filter(|x| x.len() == 1)?;
+                Var::V(usize::from_str_radix(x, 16).ok()?)
+            }
+        };
+        Some(var)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Var(Var),
+    // `ram[ADDR]`, a byte of RAM.
+    Ram(Box<Node>),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+// An expression over machine state, e.g. `v3 == 0x10 && i > 0x300`. Numbers
+// are decimal unless they start with 0x, comparisons and `&&`, `||` and `!`
+// give 1 or 0, and anything but 0 is true.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expr {
+    text: String,
+    node: Node,
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            at: 0,
+            nesting: 0,
+        };
+        let (node, _) = parser.binary(0)?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {} in '{}'", describe(token), text));
+        }
+        Ok(Self {
+            text: text.trim().to_string(),
+            node,
+        })
+    }
+
+    // The value with the machine as it is, `hit_count` being how often the
+    // breakpoint it belongs to has been reached.
+    pub fn eval(&self, cpu: &Processor, hit_count: u64) -> Result<i64, String> {
+        eval(&self.node, cpu, hit_count)
+    }
+
+    // A value the way `print` shows it, e.g. `0x10 (16)`.
+    pub fn describe(value: i64) -> String {
+        format!("{} ({})", format_value(value), value)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+// Values are shown in hex, like everything else in the debuggers.
+pub fn format_value(value: i64) -> String {
+    match value {
+        value if value < 0 => format!("-{:#X}", value.unsigned_abs()),
+        value => format!("{:#X}", value),
+    }
+}
+
+// Text with expressions in braces, e.g. `V3 is {v3}`, for log points.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    text: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Expr(Expr),
+}
+
+impl Message {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..].find('}').ok_or_else(|| format!("missing '}}' in '{}'", text))? + open;
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            parts.push(Part::Expr(Expr::parse(&rest[open + 1..close])?));
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self {
+            text: text.to_string(),
+            parts,
+        })
+    }
+
+    pub fn eval(&self, cpu: &Processor, hit_count: u64) -> Result<String, String> {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Expr(expr) => out.push_str(&format_value(expr.eval(cpu, hit_count)?)),
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            let word = &rest[..len];
+            let token = if c.is_ascii_digit() {
+                let number = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+                    Some(digits) => i64::from_str_radix(digits, 16),
+                    None => word.parse(),
+                };
+                Token::Number(number.map_err(|_| format!("invalid number '{}'", word))?)
+            } else {
+                Token::Name(word.to_string())
+            };
+            tokens.push(token);
+            len
+        } else {
+            let symbol = SYMBOLS
+                .into_iter()
+                .find(|symbol| rest.starts_with(symbol))
+                .ok_or_else(|| format!("unexpected '{}' in '{}'", c, text))?;
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(number) => format!("'{}'", number),
+        Token::Name(name) => format!("'{}'", name),
+        Token::Symbol(symbol) => format!("'{}'", symbol),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    at: usize,
+    // Calls to `unary` under way.
+    nesting: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.at += 1;
+        self.tokens.get(self.at - 1)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(found)) if *found == symbol => Ok(()),
+            Some(token) => Err(format!("expected '{}', found {}", symbol, describe(token))),
+            None => Err(format!("expected '{}'", symbol)),
+        }
+    }
+
+    // Operators of `level` and tighter, left to right. Returns the node and
+    // how deep it is.
+    fn binary(&mut self, level: usize) -> Result<(Node, usize), String> {
+        let Some(symbols) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let (mut left, mut depth) = self.binary(level + 1)?;
+        while let Some(&Token::Symbol(symbol)) = self.peek() {
+            if !symbols.contains(&symbol) {
+                break;
+            }
+            self.at += 1;
+            let (right, right_depth) = self.binary(level + 1)?;
+            depth = deeper(depth.max(right_depth))?;
+            left = Node::Binary(symbol, Box::new(left), Box::new(right));
+        }
+        Ok((left, depth))
+    }
+
+    // Counts how deep the parser has recursed, which brackets and prefix
+    // operators do before the depth of what they contain is known.
+    fn unary(&mut self) -> Result<(Node, usize), String> {
+        self.nesting = deeper(self.nesting)?;
+        let parsed = self.operand();
+        self.nesting -= 1;
+        parsed
+    }
+
+    fn operand(&mut self) -> Result<(Node, usize), String> {
+        match self.next().cloned() {
+            Some(Token::Symbol(symbol @ ("!" | "-" | "~"))) => {
+                let (operand, depth) = self.unary()?;
+                Ok((Node::Unary(symbol, Box::new(operand)), deeper(depth)?))
+            }
+            Some(Token::Symbol("(")) => {
+                let parsed = self.binary(0)?;
+                self.expect(")")?;
+                Ok(parsed)
+            }
+            Some(Token::Number(number)) => Ok((Node::Number(number), 1)),
+            Some(Token::Name(name)) if name.eq_ignore_ascii_case("ram") => {
+                self.expect("[")?;
+                let (addr, depth) = self.binary(0)?;
+                self.expect("]")?;
+                Ok((Node::Ram(Box::new(addr)), deeper(depth)?))
+            }
+            Some(Token::Name(name)) => match Var::from_name(&name) {
+                Some(var) => Ok((Node::Var(var), 1)),
+                None => Err(format!("unknown name '{}'", name)),
+            },
+            Some(token) => Err(format!("unexpected {}", describe(&token))),
+            None => Err(String::from("unexpected end of expression")),
+        }
+    }
+}
+
+// One level deeper than `depth`, unless that is too deep.
+fn deeper(depth: usize) -> Result<usize, String> {
+    match depth < MAX_DEPTH {
+        true => Ok(depth + 1),
+        false => Err(String::from("expression nested too deeply")),
+    }
+}
+
+fn eval(node: &Node, cpu: &Processor, hit_count: u64) -> Result<i64, String> {
+    let value = |node: &Node| eval(node, cpu, hit_count);
+    let value = match node {
+        Node::Number(number) => *number,
+        Node::Var(var) => match *var {
+            Var::V(x) => cpu.v[x] as i64,
+            Var::I => cpu.i as i64,
+            Var::Pc => cpu.pc as i64,
+            Var::Sp => cpu.sp as i64,
+            Var::Dt => cpu.delay_timer as i64,
+            Var::St => cpu.sound_timer as i64,
+            Var::Opcode => cpu.get_instruction().unwrap_or_default() as i64,
+            Var::HitCount => hit_count as i64,
+        },
+        Node::Ram(addr) => {
+            let addr = value(addr)?;
+            let byte = usize::try_from(addr).ok().and_then(|addr| cpu.ram.get(addr));
+            *byte.ok_or_else(|| format!("{} is outside RAM", format_value(addr)))? as i64
+        }
+        Node::Unary(symbol, operand) => {
+            let operand = value(operand)?;
+            match *symbol {
+                "!" => (operand == 0) as i64,
+                "-" => operand.wrapping_neg(),
+                _ => !operand,
+            }
+        }
+        // Only evaluated as far as needed.
+        Node::Binary("&&", left, right) => (value(left)? != 0 && value(right)? != 0) as i64,
+        Node::Binary("||", left, right) => (value(left)? != 0 || value(right)? != 0) as i64,
+        Node::Binary(symbol, left, right) => {
+            let (left, right) = (value(left)?, value(right)?);
+            match *symbol {
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => return Err(String::from("division by zero")),
+                // `i64::MIN / -1` overflows, and wraps like the other operators.
+                "/" => left.wrapping_div(right),
+                _ => left.wrapping_rem(right),
+            }
+        }
+    };
+    Ok(value)
+}
@@ -0,0 +1,236 @@
+use std::{fs, io};
+
+// Small glyphs are 5 bytes tall, big (SUPER-CHIP) glyphs are 10 bytes tall.
+pub const SMALL_GLYPH_SZ: usize = 5;
+pub const BIG_GLYPH_SZ: usize = 10;
+pub const SMALL_FONT_SZ: usize = 16 * SMALL_GLYPH_SZ;
+pub const BIG_FONT_SZ: usize = 16 * BIG_GLYPH_SZ;
+
+// Fonts live in the interpreter area, which ends where programs are loaded.
+const INTERPRETER_END: usize = 0x200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FontSet {
+    CosmacVip,
+    Dream6800,
+    Eti660,
+    FishNChips,
+    Octo,
+}
+
+impl FontSet {
+    pub const ALL: [FontSet; 5] = [
+        FontSet::CosmacVip,
+        FontSet::Dream6800,
+        FontSet::Eti660,
+        FontSet::FishNChips,
+        FontSet::Octo,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FontSet::CosmacVip => "vip",
+            FontSet::Dream6800 => "dream6800",
+            FontSet::Eti660 => "eti660",
+            FontSet::FishNChips => "fishnchips",
+            FontSet::Octo => "octo",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        FontSet::ALL.into_iter().find(|set| set.name() == name.to_lowercase())
+    }
+
+    pub fn glyphs(&self) -> &'static [u8; SMALL_FONT_SZ] {
+        match self {
+            FontSet::CosmacVip => &VIP_FONT,
+            FontSet::Dream6800 => &DREAM6800_FONT,
+            FontSet::Eti660 => &ETI660_FONT,
+            FontSet::FishNChips => &FISHNCHIPS_FONT,
+            FontSet::Octo => &OCTO_FONT,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Font {
+    pub small: [u8; SMALL_FONT_SZ],
+    pub big: [u8; BIG_FONT_SZ],
+    // Address of the first small glyph. The big glyphs follow directly after.
+    pub base: u16,
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font::builtin(FontSet::Octo)
+    }
+}
+
+impl Font {
+    pub fn builtin(set: FontSet) -> Self {
+        Self {
+            small: *set.glyphs(),
+            big: SCHIP_BIG_FONT,
+            base: 0,
+        }
+    }
+
+    // Loads a raw font file: 80 bytes of small glyphs, optionally followed by
+    // 160 bytes of big glyphs. Without big glyphs the SUPER-CHIP font is kept.
+    pub fn from_file(file_path: &str) -> io::Result<Self> {
+        let data = fs::read(file_path)?;
+        Font::from_bytes(&data).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "font file must be {} or {} bytes, got {}",
+                    SMALL_FONT_SZ,
+                    SMALL_FONT_SZ + BIG_FONT_SZ,
+                    data.len()
+                ),
+            )
+        })
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let mut font = Font::default();
+        match data.len() {
+            SMALL_FONT_SZ => font.small.copy_from_slice(data),
+            n if n == SMALL_FONT_SZ + BIG_FONT_SZ => {
+                font.small.copy_from_slice(&data[..SMALL_FONT_SZ]);
+                font.big.copy_from_slice(&data[SMALL_FONT_SZ..]);
+            }
+            _ => return None,
+        }
+        Some(font)
+    }
+
+    pub fn with_base(mut self, base: u16) -> Self {
+        self.base = base;
+        self
+    }
+
+    pub fn big_base(&self) -> u16 {
+        self.base + SMALL_FONT_SZ as u16
+    }
+
+    // Both fonts must sit below 0x200 so they never overlap the program.
+    pub fn fits(&self) -> bool {
+        self.base as usize + SMALL_FONT_SZ + BIG_FONT_SZ <= INTERPRETER_END
+    }
+}
+
+const VIP_FONT: [u8; SMALL_FONT_SZ] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const DREAM6800_FONT: [u8; SMALL_FONT_SZ] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const ETI660_FONT: [u8; SMALL_FONT_SZ] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const FISHNCHIPS_FONT: [u8; SMALL_FONT_SZ] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+    0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+    0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+    0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+    0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+    0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+    0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+    0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+    0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+    0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+    0x60, 0x80, 0x80, 0x80, 0x60, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const OCTO_FONT: [u8; SMALL_FONT_SZ] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// SUPER-CHIP 1.1 only shipped big digits 0-9, A-F are taken from Octo.
+const SCHIP_BIG_FONT: [u8; BIG_FONT_SZ] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
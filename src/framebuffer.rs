@@ -0,0 +1,117 @@
+use std::fmt;
+
+use crate::hash::fnv1a;
+
+// Monochrome display packed one row per `u128`, so screens up to the 128x64
+// SUPER-CHIP resolution fit. Pixel x of a row is bit `width - 1 - x`.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    mask: u128,
+    rows: Vec<u128>,
+    // Set whenever the contents change, cleared by `take_dirty`.
+    dirty: bool,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!((8..=128).contains(&width) && height > 0);
+        Self {
+            width,
+            height,
+            mask: u128::MAX >> (128 - width),
+            rows: vec![0; height],
+            dirty: true,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.rows[y] >> (self.width - 1 - x) & 1 == 1
+    }
+
+    // Every pixel in row-major order.
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        self.rows
+            .iter()
+            .flat_map(move |row| (0..self.width).rev().map(move |bit| row >> bit & 1 == 1))
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.fill(0);
+        self.dirty = true;
+    }
+
+    // XORs an 8 pixel wide sprite onto the screen at (x, y), which wrap around
+    // the screen. The sprite itself wraps around both edges too, or with
+    // `clip` is cut off at them. Returns true if any lit pixel was turned off.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], clip: bool) -> bool {
+        let (x, y) = (x % self.width, y % self.height);
+        let mut collision = 0;
+        for (i, &byte) in sprite.iter().enumerate() {
+            if clip && y + i >= self.height {
+                break;
+            }
+            let bits = self.place(byte, x, clip);
+            let row = &mut self.rows[(y + i) % self.height];
+            collision |= *row & bits;
+            *row ^= bits;
+        }
+        self.dirty |= !sprite.is_empty();
+        collision != 0
+    }
+
+    // Fingerprint of the size and contents.
+    pub fn hash(&self) -> u64 {
+        let mut bytes = Vec::with_capacity(16 * (self.rows.len() + 1));
+        bytes.extend_from_slice(&(self.width as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.height as u64).to_le_bytes());
+        for row in &self.rows {
+            bytes.extend_from_slice(&row.to_le_bytes());
+        }
+        fnv1a(&bytes)
+    }
+
+    // Returns whether the screen changed since the last call.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    // Row bits for `byte` drawn with its leftmost pixel at column `x`.
+    fn place(&self, byte: u8, x: usize, clip: bool) -> u128 {
+        let bits = (byte as u128) << (self.width - 8);
+        match x {
+            0 => bits,
+            x if clip => bits >> x,
+            x => ((bits >> x) | (bits << (self.width - x))) & self.mask,
+        }
+    }
+}
+
+// Screens are equal when they show the same thing, however they were drawn.
+impl PartialEq for Framebuffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width && self.height == other.height && self.rows == other.rows
+    }
+}
+
+impl Eq for Framebuffer {}
+
+// ASCII art, `#` for lit pixels and `.` for dark ones, one line per row.
+impl fmt::Display for Framebuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.height {
+            let line: String = (0..self.width).map(|x| if self.get(x, y) { '#' } else { '.' }).collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,609 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Instant;
+
+use log::info;
+
+use crate::breakpoint::{self, parse_addr, Breakpoint, Breakpoints, Place};
+use crate::chip_machine::{CHIPMachine, Status, FRAME_DURATION};
+use crate::expr::Expr;
+use crate::options::Options;
+use crate::processor::{Fault, Processor};
+use crate::watch::{Hit, Location, Target as WatchTarget, Trigger, Watchpoint};
+
+const DEFAULT_PORT: u16 = 1234;
+
+// `monitor help`. Addresses are hex, as in the debugger prompt.
+const MONITOR_HELP: &str = "\
+break WHERE [if EXPR]  stop at an address, op PATTERN, class NAME or collision,
+                       only when EXPR isn't 0
+log WHERE TEXT         print TEXT there instead, with {EXPR} replaced
+delete WHERE           remove a breakpoint set with monitor
+breakpoints            list them
+print EXPR             e.g. v3 == 0x10 && i > 0x300";
+
+// Registers as gdb numbers them. Everything is sent little-endian.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+const I: usize = 16;
+const PC: usize = 17;
+const SP: usize = 18;
+const DT: usize = 19;
+const ST: usize = 20;
+const REGISTERS: usize = 21;
+
+// Stop signals.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// Why the target stopped, as a stop reply packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stop {
+    Signal(u8),
+    Breakpoint,
+    Watchpoint(Trigger, u16),
+}
+
+impl Stop {
+    fn reply(&self) -> String {
+        match self {
+            Stop::Signal(signal) => format!("S{:02x}", signal),
+            Stop::Breakpoint => format!("T{:02x}swbreak:;", SIGTRAP),
+            Stop::Watchpoint(kind, addr) => {
+                let name = match kind {
+                    Trigger::Read => "rwatch",
+                    Trigger::Access => "awatch",
+                    Trigger::Write | Trigger::Change => "watch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, name, addr)
+            }
+        }
+    }
+}
+
+// A gdb remote serial protocol server for one machine. Run it with
+// `chip8 gdb ROM`, then `target remote :PORT` in gdb.
+pub struct GdbServer {
+    listener: TcpListener,
+    target: Target,
+}
+
+impl GdbServer {
+    // Listens on localhost only, port 0 picks a free one.
+    pub fn bind(chip8: CHIPMachine, port: u16) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(("127.0.0.1", port))?,
+            target: Target {
+                chip8,
+                breakpoints: BTreeSet::new(),
+                monitor: Breakpoints::default(),
+                output: Vec::new(),
+            },
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Serves one connection until the debugger detaches, kills the target or
+    // goes away, and returns the machine.
+    pub fn serve(mut self) -> io::Result<CHIPMachine> {
+        let (stream, peer) = self.listener.accept()?;
+        info!("gdb connected from {}", peer);
+        stream.set_nodelay(true)?;
+        let mut conn = Connection {
+            stream,
+            buffer: Vec::new(),
+            ack: true,
+        };
+        while let Some(packet) = conn.read_packet()? {
+            let Packet::Command(command) = packet else {
+                // Interrupting a stopped target changes nothing.
+                continue;
+            };
+            match command.as_str() {
+                "k" => break,
+                "D" => {
+                    conn.send("OK")?;
+                    break;
+                }
+                "QStartNoAckMode" => {
+                    // This packet was still acked, from here on nothing is.
+                    conn.send("OK")?;
+                    conn.ack = false;
+                }
+                _ => {
+                    let reply = self.target.handle(&command, &mut conn)?;
+                    conn.send(&reply)?;
+                }
+            }
+        }
+        info!("gdb disconnected");
+        Ok(self.target.chip8)
+    }
+}
+
+// `chip8 gdb`: waits for gdb on `--port`, 1234 by default, and serves it.
+pub fn run(options: &Options) -> Result<(), String> {
+    let mut chip8 = options.machine()?;
+    chip8.disable_aot();
+    let port = options.port.unwrap_or(DEFAULT_PORT);
+    let server = GdbServer::bind(chip8, port).map_err(|err| format!("port {}: {}", port, err))?;
+    let addr = server.local_addr().map_err(|err| err.to_string())?;
+    println!("waiting for gdb on {}, use `target remote {}`", addr, addr);
+    server.serve().map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+enum Packet {
+    Command(String),
+    // Ctrl-C, a bare 0x03 byte outside a packet.
+    Interrupt,
+}
+
+struct Connection {
+    stream: TcpStream,
+    // Received but not yet parsed.
+    buffer: Vec<u8>,
+    // Acknowledge packets with `+` until the debugger asks for no-ack mode.
+    ack: bool,
+}
+
+impl Connection {
+    // The next packet, or `None` once the debugger has gone away.
+    fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            if let Some(packet) = self.parse()? {
+                return Ok(Some(packet));
+            }
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(None),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn parse(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            match self.buffer.first() {
+                None => return Ok(None),
+                Some(0x03) => {
+                    self.buffer.remove(0);
+                    return Ok(Some(Packet::Interrupt));
+                }
+                Some(b'$') => break,
+                // Acks, and anything else between packets.
+                Some(_) => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        let Some(hash) = self.buffer.iter().position(|&b| b == b'#') else {
+            return Ok(None);
+        };
+        if self.buffer.len() < hash + 3 {
+            return Ok(None);
+        }
+        let data = self.buffer[1..hash].to_vec();
+        let checksum = std::str::from_utf8(&self.buffer[hash + 1..hash + 3])
+            .ok()
+            .and_then(|text| u8::from_str_radix(text, 16).ok());
+        self.buffer.drain(..hash + 3);
+        if self.ack {
+            let valid = checksum == Some(data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)));
+            self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            if !valid {
+                return self.parse();
+            }
+        }
+        Ok(Some(Packet::Command(String::from_utf8_lossy(&data).into_owned())))
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        self.stream.write_all(format!("${}#{:02x}", data, checksum).as_bytes())
+    }
+
+    // Whether Ctrl-C arrived, without waiting for it. Anything else that
+    // arrived stays buffered.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut chunk = [0; 4096];
+        let read = self.stream.read(&mut chunk);
+        self.stream.set_nonblocking(false)?;
+        match read {
+            Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => (),
+            Err(err) => return Err(err),
+        }
+        match self.buffer.iter().position(|&b| b == 0x03) {
+            Some(at) => {
+                self.buffer.remove(at);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+struct Target {
+    chip8: CHIPMachine,
+    breakpoints: BTreeSet<u16>,
+    // Set with `monitor break` and `monitor log`, gdb doesn't know about them.
+    monitor: Breakpoints,
+    // Log point output not yet sent.
+    output: Vec<String>,
+}
+
+impl Target {
+    // The reply to `command`. An empty reply tells gdb it isn't supported.
+    fn handle(&mut self, command: &str, conn: &mut Connection) -> io::Result<String> {
+        // Split after the first char, which needn't be ASCII in a bad packet.
+        let first = command.chars().next().map_or(0, char::len_utf8);
+        let reply = match command.split_at(first) {
+            ("?", _) => Stop::Signal(SIGTRAP).reply(),
+            ("g", _) => self.read_registers(),
+            ("G", hex) => self.write_registers(hex),
+            ("p", reg) => self.read_register(reg),
+            ("P", args) => self.write_register(args),
+            ("m", args) => self.read_memory(args),
+            ("M", args) => self.write_memory(args),
+            ("Z", args) => self.insert(args, true),
+            ("z", args) => self.insert(args, false),
+            ("s", _) => self.step(conn)?.reply(),
+            ("c", _) => self.resume(conn)?.reply(),
+            ("H", _) => ok(),
+            _ => self.query(command, conn)?,
+        };
+        Ok(reply)
+    }
+
+    fn query(&mut self, command: &str, conn: &mut Connection) -> io::Result<String> {
+        let reply = match command {
+            "qAttached" => String::from("1"),
+            "qC" => String::from("QC1"),
+            "qfThreadInfo" => String::from("m1"),
+            "qsThreadInfo" => String::from("l"),
+            "vCont?" => String::from("vCont;c;s"),
+            "vCont;s" | "vCont;s:1" => self.step(conn)?.reply(),
+            "vCont;c" | "vCont;c:1" => self.resume(conn)?.reply(),
+            _ if command.starts_with("qSupported") => String::from("PacketSize=4000;qXfer:features:read+;swbreak+;QStartNoAckMode+"),
+            _ if command.starts_with("qXfer:features:read:target.xml:") => {
+                let range = &command["qXfer:features:read:target.xml:".len()..];
+                transfer(TARGET_XML, range)
+            }
+            _ if command.starts_with("qRcmd,") => self.monitor(&command["qRcmd,".len()..]),
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    // A `monitor` command, for breakpoints with conditions and log points.
+    // The reply is the output, which gdb prints.
+    fn monitor(&mut self, command: &str) -> String {
+        let Some(command) = unhex(command).and_then(|bytes| String::from_utf8(bytes).ok()) else {
+            return error(1);
+        };
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let result = match words.split_first() {
+            Some((&"break", args)) => Breakpoint::parse(args, parse_addr).map(|breakpoint| self.add_breakpoint(breakpoint)),
+            Some((&"log", args)) => Breakpoint::parse_log(args, parse_addr).map(|breakpoint| self.add_breakpoint(breakpoint)),
+            Some((&"delete", args)) => Place::parse(args, parse_addr).and_then(|(place, _)| {
+                let breakpoint = self.monitor.remove(place).ok_or_else(|| format!("no breakpoint at {}", place))?;
+                Ok(format!("Deleted breakpoint at {}", breakpoint))
+            }),
+            Some((&"breakpoints", _)) => Ok(self.monitor.iter().map(|breakpoint| breakpoint.to_string()).collect::<Vec<_>>().join("\n")),
+            Some((&"print", args)) => Expr::parse(&args.join(" "))
+                .and_then(|expr| expr.eval(self.chip8.cpu(), 0))
+                .map(Expr::describe),
+            _ => Ok(String::from(MONITOR_HELP)),
+        };
+        let text = result.unwrap_or_else(|err| format!("Error: {}", err));
+        hex(format!("{}\n", text).as_bytes())
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> String {
+        let text = format!("Breakpoint at {}", breakpoint);
+        self.monitor.insert(breakpoint);
+        text
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTERS).map(|reg| hex(&register(self.chip8.cpu(), reg))).collect()
+    }
+
+    fn write_registers(&mut self, hex: &str) -> String {
+        let Some(bytes) = unhex(hex) else {
+            return error(1);
+        };
+        let mut rest = bytes.as_slice();
+        for reg in 0..REGISTERS {
+            let size = register(self.chip8.cpu(), reg).len();
+            if rest.len() < size {
+                return error(1);
+            }
+            let (value, next) = rest.split_at(size);
+            if !set_register(self.chip8.cpu_mut(), reg, value) {
+                return error(1);
+            }
+            rest = next;
+        }
+        ok()
+    }
+
+    fn read_register(&self, reg: &str) -> String {
+        match usize::from_str_radix(reg, 16) {
+            Ok(reg) if reg < REGISTERS => hex(&register(self.chip8.cpu(), reg)),
+            _ => error(1),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let parsed = args
+            .split_once('=')
+            .and_then(|(reg, value)| Some((usize::from_str_radix(reg, 16).ok()?, unhex(value)?)));
+        match parsed {
+            Some((reg, value)) if reg < REGISTERS && set_register(self.chip8.cpu_mut(), reg, &value) => ok(),
+            _ => error(1),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let ram = &self.chip8.cpu().ram;
+        match parse_range(args, ram.len()) {
+            Some((addr, len)) => hex(&ram[addr..addr + len]),
+            None => error(14),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let Some((range, data)) = args.split_once(':') else {
+            return error(1);
+        };
+        let cpu = self.chip8.cpu_mut();
+        match (parse_range(range, cpu.ram.len()), unhex(data)) {
+            (Some((addr, len)), Some(bytes)) if bytes.len() == len => {
+                for (offset, byte) in bytes.into_iter().enumerate() {
+                    cpu.write_ram(addr + offset, byte);
+                }
+                ok()
+            }
+            _ => error(14),
+        }
+    }
+
+    // Z/z TYPE,ADDR,KIND. Software and hardware breakpoints are the same
+    // thing here, KIND is the length for watchpoints.
+    fn insert(&mut self, args: &str, insert: bool) -> String {
+        let fields: Vec<&str> = args.split(',').collect();
+        let [kind, addr, len] = fields[..] else {
+            return error(1);
+        };
+        let (Ok(addr), Ok(len)) = (u16::from_str_radix(addr, 16), u16::from_str_radix(len, 16)) else {
+            return error(1);
+        };
+        let trigger = match kind {
+            "0" | "1" => None,
+            "2" => Some(Trigger::Write),
+            "3" => Some(Trigger::Read),
+            "4" => Some(Trigger::Access),
+            _ => return String::new(),
+        };
+        let watches = &mut self.chip8.cpu_mut().watches;
+        match (trigger, insert) {
+            (None, true) => {
+                self.breakpoints.insert(addr);
+            }
+            (None, false) => {
+                self.breakpoints.remove(&addr);
+            }
+            (Some(trigger), insert) => {
+                let end = addr.saturating_add(len.max(1) - 1);
+                let watchpoint = Watchpoint::new(WatchTarget::Ram(addr..=end), trigger);
+                let existing = watches.iter().find(|(_, w)| *w == watchpoint).map(|(id, _)| *id);
+                match (existing, insert) {
+                    (None, true) => {
+                        watches.add(watchpoint);
+                    }
+                    (Some(id), false) => {
+                        watches.remove(id);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        ok()
+    }
+
+    // Runs one instruction and reports whether that finished a frame, and
+    // why to stop there if anything should.
+    fn execute(&mut self) -> (bool, Option<Stop>) {
+        let ran = self.chip8.cpu().get_instruction();
+        let frame_done = self.chip8.step();
+        // Taken after every instruction, so none carry over to the next one.
+        let hits = self.chip8.cpu_mut().watches.take_hits();
+        let check = breakpoint::check(&mut self.monitor, self.chip8.cpu(), ran);
+        self.output.extend(check.logs);
+        let fault = match self.chip8.status() {
+            Status::Faulted(Fault::UnknownOpcode(_)) => Some(Stop::Signal(SIGILL)),
+            Status::Faulted(_) => Some(Stop::Signal(SIGSEGV)),
+            _ => None,
+        };
+        let stop = match fault {
+            // A stop reply has room for the fault or the watchpoint, so the
+            // watchpoint hits go out as console output.
+            Some(fault) => {
+                self.output.extend(hits.iter().map(Hit::to_string));
+                Some(fault)
+            }
+            None => self.watched_access(&hits).or_else(|| match self.breakpoints.contains(&self.chip8.cpu().pc) {
+                true => Some(Stop::Breakpoint),
+                // Not a breakpoint gdb inserted, so a plain SIGTRAP.
+                false => check.stop.map(|_| Stop::Signal(SIGTRAP)),
+            }),
+        };
+        (frame_done, stop)
+    }
+
+    fn step(&mut self, conn: &mut Connection) -> io::Result<Stop> {
+        let stop = self.execute().1;
+        self.flush(conn)?;
+        Ok(stop.unwrap_or(Stop::Signal(SIGTRAP)))
+    }
+
+    // Sends log point output as console output packets, which gdb prints
+    // while the target runs.
+    fn flush(&mut self, conn: &mut Connection) -> io::Result<()> {
+        for text in self.output.drain(..) {
+            conn.send(&format!("O{}", hex(format!("{}\n", text).as_bytes())))?;
+        }
+        Ok(())
+    }
+
+    // Runs at the usual 60 Hz until something stops it, or gdb interrupts.
+    fn resume(&mut self, conn: &mut Connection) -> io::Result<Stop> {
+        let mut next_frame = Instant::now();
+        loop {
+            let mut frame_done = false;
+            while !frame_done {
+                let stop;
+                (frame_done, stop) = self.execute();
+                self.flush(conn)?;
+                if let Some(stop) = stop {
+                    return Ok(stop);
+                }
+            }
+            if conn.interrupted()? {
+                return Ok(Stop::Signal(SIGINT));
+            }
+            next_frame += FRAME_DURATION;
+            thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    // The stop for the first of `hits`, if it's one gdb knows about.
+    fn watched_access(&self, hits: &[Hit]) -> Option<Stop> {
+        let hit = hits.first()?;
+        let (_, watchpoint) = self.chip8.cpu().watches.iter().find(|(id, _)| *id == hit.id)?;
+        match hit.location {
+            Location::Ram(addr) => Some(Stop::Watchpoint(watchpoint.trigger, addr)),
+            _ => None,
+        }
+    }
+}
+
+fn register(cpu: &Processor, reg: usize) -> Vec<u8> {
+    match reg {
+        0..=15 => vec![cpu.v[reg]],
+        I => cpu.i.to_le_bytes().to_vec(),
+        PC => cpu.pc.to_le_bytes().to_vec(),
+        SP => vec![cpu.sp as u8],
+        DT => vec![cpu.delay_timer],
+        ST => vec![cpu.sound_timer],
+        _ => unreachable!(),
+    }
+}
+
+// Returns false for values the processor can't take, e.g. PC outside RAM.
+fn set_register(cpu: &mut Processor, reg: usize, value: &[u8]) -> bool {
+    let word = || value.try_into().ok().map(u16::from_le_bytes);
+    match (reg, value) {
+        (0..=15, &[byte]) => cpu.v[reg] = byte,
+        (I, _) => match word() {
+            Some(i) => cpu.i = i,
+            None => return false,
+        },
+        (PC, _) => match word() {
+            Some(pc) if pc as usize <= crate::processor::LAST_PC => cpu.pc = pc,
+            _ => return false,
+        },
+        (SP, &[sp]) if sp as usize <= cpu.stack.len() => cpu.sp = sp as u16,
+        (DT, &[byte]) => cpu.delay_timer = byte,
+        (ST, &[byte]) => cpu.sound_timer = byte,
+        _ => return false,
+    }
+    true
+}
+
+// ADDR,LENGTH within RAM.
+fn parse_range(args: &str, size: usize) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    let (addr, len) = (usize::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?);
+    (addr.checked_add(len)? <= size).then_some((addr, len))
+}
+
+// The OFFSET,LENGTH slice of a qXfer object, `l` marking the last one.
+fn transfer(object: &str, range: &str) -> String {
+    let Some((offset, len)) = range
+        .split_once(',')
+        .and_then(|(offset, len)| Some((usize::from_str_radix(offset, 16).ok()?, usize::from_str_radix(len, 16).ok()?)))
+    else {
+        return error(1);
+    };
+    let start = offset.min(object.len());
+    let end = offset.saturating_add(len).min(object.len());
+    let marker = if end == object.len() { 'l' } else { 'm' };
+    // The XML has no `$`, `#`, `*` or `}` that would need escaping.
+    format!("{}{}", marker, &object[start..end])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{:02x}", byte);
+        out
+    })
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+fn ok() -> String {
+    String::from("OK")
+}
+
+fn error(code: u8) -> String {
+    format!("E{:02x}", code)
+}
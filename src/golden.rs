@@ -0,0 +1,131 @@
+use std::fs;
+
+use crate::chip_machine::CHIPMachine;
+use crate::framebuffer::Framebuffer;
+use crate::headless;
+use crate::options::Options;
+use crate::script::InputScript;
+
+// What the screen should look like once a ROM test has run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expected {
+    // The screen as ASCII art, in the format `Framebuffer` displays as:
+    // `#` for lit pixels and `.` for dark ones, one line per row.
+    Screen(String),
+    // `Framebuffer::hash` of the screen.
+    Hash(u64),
+}
+
+#[derive(Clone, Debug)]
+pub struct RomTest {
+    pub frames: usize,
+    pub input: InputScript,
+    pub expected: Expected,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub passed: bool,
+    // Frames actually run, fewer than asked for when the ROM halted.
+    pub frames: usize,
+    // On failure, what differed and the screen as ASCII art.
+    pub report: String,
+}
+
+impl RomTest {
+    pub fn run(&self, chip8: &mut CHIPMachine) -> Outcome {
+        let frames = headless::run_frames(chip8, self.frames, &self.input);
+        let actual = &chip8.cpu().display;
+        let report = match &self.expected {
+            Expected::Hash(hash) if *hash == actual.hash() => None,
+            Expected::Hash(hash) => Some(format!(
+                "expected screen hash {:016x}, got {:016x}\n{}",
+                hash,
+                actual.hash(),
+                actual
+            )),
+            Expected::Screen(screen) => diff(screen, actual),
+        };
+        Outcome {
+            passed: report.is_none(),
+            frames,
+            report: report.unwrap_or_default(),
+        }
+    }
+}
+
+// Compares the golden ASCII art with the actual screen. Returns `None` when
+// they match, otherwise a map of the screen with the differences marked.
+pub fn diff(expected: &str, actual: &Framebuffer) -> Option<String> {
+    let expected: Vec<Vec<bool>> = expected
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.chars().map(|c| c == '#').collect())
+        .collect();
+    let (width, height) = (actual.width(), actual.height());
+    if expected.len() != height || expected.iter().any(|row| row.len() != width) {
+        let expected_width = expected.first().map_or(0, |row| row.len());
+        return Some(format!(
+            "expected a {}x{} screen, got {}x{}\n{}",
+            expected_width,
+            expected.len(),
+            width,
+            height,
+            actual
+        ));
+    }
+
+    let mut map = String::new();
+    let mut wrong = 0;
+    for (y, row) in expected.iter().enumerate() {
+        for (x, &want) in row.iter().enumerate() {
+            map.push(match (want, actual.get(x, y)) {
+                (true, true) => '#',
+                (false, false) => '.',
+                (false, true) => '+',
+                (true, false) => '-',
+            });
+            wrong += (want != actual.get(x, y)) as usize;
+        }
+        map.push('\n');
+    }
+    (wrong > 0).then(|| {
+        format!(
+            "{} of {} pixels differ (+ lit but should be dark, - dark but should be lit)\n{}",
+            wrong,
+            width * height,
+            map
+        )
+    })
+}
+
+// `chip8 test`: runs the ROM and checks the final screen against `--golden`
+// or `--hash`. With `--update` the golden file is rewritten from this run.
+pub fn run(options: &Options) -> Result<Outcome, String> {
+    let expected = match (&options.golden, options.hash) {
+        (Some(_), Some(_)) => return Err(String::from("give either --golden or --hash, not both")),
+        (Some(_), None) if options.update => Expected::Screen(String::new()),
+        (Some(path), None) => Expected::Screen(fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?),
+        (None, Some(hash)) => Expected::Hash(hash),
+        (None, None) => return Err(String::from("test needs --golden PATH or --hash HEX")),
+    };
+    let test = RomTest {
+        frames: options.frames,
+        input: options.input_script()?,
+        expected,
+    };
+    let mut chip8 = options.machine()?;
+    let outcome = test.run(&mut chip8);
+    match &options.golden {
+        Some(path) if options.update => {
+            fs::write(path, chip8.cpu().display.to_string()).map_err(|err| format!("{}: {}", path, err))?;
+            Ok(Outcome {
+                passed: true,
+                report: format!("updated {}\n", path),
+                ..outcome
+            })
+        }
+        _ => Ok(outcome),
+    }
+}
@@ -0,0 +1,6 @@
+// 64 bit FNV-1a. Not cryptographic, but stable across runs and platforms,
+// which is all ROM and screen fingerprints need.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+
+use log::{error, info};
+
+use crate::chip_machine::{CHIPMachine, Status};
+use crate::crash;
+use crate::options::Options;
+use crate::recorder::Recorder;
+use crate::screenshot;
+use crate::script::InputScript;
+
+// Runs up to `frames` frames with the scripted key presses, stopping early
+// once the machine halts or faults since the screen can't change after that.
+// Returns the frames run.
+pub fn run_frames(chip8: &mut CHIPMachine, frames: usize, input: &InputScript) -> usize {
+    for frame in 0..frames {
+        if chip8.status() != Status::Running {
+            return frame;
+        }
+        run_scripted_frame(chip8, frame, input);
+    }
+    frames
+}
+
+fn run_scripted_frame(chip8: &mut CHIPMachine, frame: usize, input: &InputScript) {
+    for (key, pressed) in input.events(frame) {
+        chip8.set_key(key, pressed);
+    }
+    chip8.run_frame();
+}
+
+// `chip8 screenshot`: runs the ROM without a window and saves the final screen.
+pub fn screenshot(options: &Options) -> Result<PathBuf, String> {
+    let mut chip8 = options.machine()?;
+    let frames = run_frames(&mut chip8, options.frames, &options.input_script()?);
+    if frames < options.frames {
+        info!("stopped after {} frames: {:?}", frames, chip8.status());
+    }
+    report_crash(&chip8, options);
+    let saved = match &options.out {
+        Some(path) => screenshot::save_as(&chip8, Path::new(path), options.screenshot_scale).map(|_| PathBuf::from(path)),
+        None => screenshot::save(&chip8, Path::new(&options.screenshot_dir), options.screenshot_scale),
+    };
+    saved.map_err(|err| format!("unable to save screenshot: {}", err))
+}
+
+// `chip8 record`: runs the ROM without a window, recording every frame to a
+// GIF. Stops early when the machine halts or faults.
+pub fn record(options: &Options) -> Result<PathBuf, String> {
+    let mut chip8 = options.machine()?;
+    let input = options.input_script()?;
+    let started = match &options.out {
+        Some(path) => Recorder::start_as(&chip8, Path::new(path), options.record_scale),
+        None => Recorder::start(&chip8, Path::new(&options.screenshot_dir), options.record_scale),
+    };
+    let mut recorder = started.map_err(|err| format!("unable to start recording: {}", err))?;
+    for frame in 0..options.frames {
+        if chip8.status() != Status::Running {
+            break;
+        }
+        run_scripted_frame(&mut chip8, frame, &input);
+        recorder.push(&chip8).map_err(|err| format!("unable to record frame: {}", err))?;
+    }
+    report_crash(&chip8, options);
+    recorder.finish().map_err(|err| format!("unable to save recording: {}", err))
+}
+
+// Saves a crash report next to the screenshots when the run ended in a fault.
+fn report_crash(chip8: &CHIPMachine, options: &Options) {
+    if !options.crash_report || !matches!(chip8.status(), Status::Faulted(_)) {
+        return;
+    }
+    match crash::save(chip8, Path::new(&options.screenshot_dir), options.screenshot_scale) {
+        Ok(path) => error!("ROM faulted, saved crash report {}", path.display()),
+        Err(err) => error!("unable to save crash report: {}", err),
+    }
+}
@@ -0,0 +1,42 @@
+use winit::event::VirtualKeyCode;
+
+// The COSMAC VIP hex keypad mapped onto the left of a QWERTY keyboard:
+//
+//     1 2 3 C        1 2 3 4
+//     4 5 6 D   ->   Q W E R
+//     7 8 9 E        A S D F
+//     A 0 B F        Z X C V
+const LAYOUT: [(char, u8); 16] = [
+    ('1', 0x1), ('2', 0x2), ('3', 0x3), ('4', 0xC),
+    ('q', 0x4), ('w', 0x5), ('e', 0x6), ('r', 0xD),
+    ('a', 0x7), ('s', 0x8), ('d', 0x9), ('f', 0xE),
+    ('z', 0xA), ('x', 0x0), ('c', 0xB), ('v', 0xF),
+];
+
+pub fn from_char(c: char) -> Option<u8> {
+    let c = c.to_ascii_lowercase();
+    LAYOUT.iter().find(|(key, _)| *key == c).map(|(_, hex)| *hex)
+}
+
+pub fn from_virtual_key(key: VirtualKeyCode) -> Option<u8> {
+    let c = match key {
+        VirtualKeyCode::Key1 => '1',
+        VirtualKeyCode::Key2 => '2',
+        VirtualKeyCode::Key3 => '3',
+        VirtualKeyCode::Key4 => '4',
+        VirtualKeyCode::Q => 'q',
+        VirtualKeyCode::W => 'w',
+        VirtualKeyCode::E => 'e',
+        VirtualKeyCode::R => 'r',
+        VirtualKeyCode::A => 'a',
+        VirtualKeyCode::S => 's',
+        VirtualKeyCode::D => 'd',
+        VirtualKeyCode::F => 'f',
+        VirtualKeyCode::Z => 'z',
+        VirtualKeyCode::X => 'x',
+        VirtualKeyCode::C => 'c',
+        VirtualKeyCode::V => 'v',
+        _ => return None,
+    };
+    from_char(c)
+}
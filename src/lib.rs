@@ -0,0 +1,44 @@
+#![deny(clippy::all)]
+#![forbid(unsafe_code)]
+pub mod aot;
+pub mod batch;
+pub mod bench;
+pub mod breakpoint;
+pub mod chip_machine;
+pub mod config;
+pub mod conformance;
+pub mod crash;
+pub mod dap;
+pub mod debugger;
+pub mod disasm;
+pub mod decode_cache;
+pub mod emulation;
+pub mod expr;
+pub mod font;
+pub mod framebuffer;
+pub mod gdb;
+pub mod golden;
+pub mod hash;
+pub mod headless;
+pub mod keypad;
+pub mod options;
+pub mod osd;
+pub mod palette;
+pub mod phosphor;
+pub mod processor;
+pub mod profile;
+pub mod quirks;
+pub mod recompiler;
+pub mod recorder;
+pub mod screenshot;
+pub mod script;
+pub mod terminal;
+pub mod trace;
+pub mod upscale;
+pub mod watch;
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+// The SUPER-CHIP high resolution mode, entered with 00FF.
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
@@ -1,25 +1,137 @@
 #![deny(clippy::all)]
 #![forbid(unsafe_code)]
-mod chip_machine;
-mod processor;
 
-use chip_machine::CHIPMachine;
-use log::error;
+use chip8::chip_machine::CHIPMachine;
+use chip8::debugger::Debugger;
+use chip8::emulation::{Captures, Emulation, Input};
+use chip8::options::{Command, Frontend, Options};
+use chip8::osd::Osd;
+use chip8::{batch, bench, conformance, dap, debugger, gdb, golden, headless, profile, recompiler, terminal, trace, HEIGHT, WIDTH};
+use log::{debug, error};
 use pixels::{Error, Pixels, SurfaceTexture};
+use std::{path::PathBuf, thread};
 use winit::{
     dpi::LogicalSize,
-    event::{Event, WindowEvent },
-    event_loop::{ControlFlow, EventLoop},
+    event::{ElementState, Event, VirtualKeyCode, WindowEvent },
+    event_loop::{ControlFlow, EventLoopBuilder},
     window::WindowBuilder,
 };
 
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
-
 fn main() -> Result<(), Error> {
-    println!("START");
     env_logger::init();
-    let event_loop = EventLoop::new();
+    let options = Options::parse(std::env::args()).unwrap_or_else(|err| exit_with(&err));
+    debug!("starting {:?}", options.command);
+    match options.command {
+        Command::Run => {
+            let chip8 = options.machine().unwrap_or_else(|err| exit_with(&err));
+            match options.frontend {
+                Frontend::Window => run_window(chip8, options, None),
+                Frontend::Terminal => {
+                    terminal::run(chip8, options.glyphs).unwrap_or_else(|err| exit_with(&err.to_string()));
+                    Ok(())
+                }
+            }
+        }
+        Command::Screenshot => {
+            let path = headless::screenshot(&options).unwrap_or_else(|err| exit_with(&err));
+            println!("saved {}", path.display());
+            Ok(())
+        }
+        Command::Bench => {
+            let report = bench::run(&options).unwrap_or_else(|err| exit_with(&err));
+            println!("{}", report);
+            Ok(())
+        }
+        Command::Batch => {
+            let report = batch::run_manifest(&options).unwrap_or_else(|err| exit_with(&err));
+            match &options.out {
+                Some(path) => {
+                    std::fs::write(path, report).unwrap_or_else(|err| exit_with(&format!("{}: {}", path, err)));
+                    println!("saved {}", path);
+                }
+                None => print!("{}", report),
+            }
+            Ok(())
+        }
+        Command::Test => {
+            let outcome = golden::run(&options).unwrap_or_else(|err| exit_with(&err));
+            let verdict = if outcome.passed { "PASS" } else { "FAIL" };
+            println!("{} {} after {} frames", verdict, options.rom, outcome.frames);
+            if !outcome.report.is_empty() {
+                print!("{}", outcome.report);
+            }
+            if !outcome.passed {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Conformance => {
+            let (passed, report) = conformance::run(&options).unwrap_or_else(|err| exit_with(&err));
+            print!("{}", report);
+            if !passed {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Debug => {
+            let mut chip8 = options.machine().unwrap_or_else(|err| exit_with(&err));
+            chip8.disable_aot();
+            let mut debugger = Debugger::default();
+            if let Some(path) = &options.labels {
+                debugger.load_labels(path).unwrap_or_else(|err| exit_with(&err));
+            }
+            if !options.no_window {
+                return run_window(chip8, options, Some(debugger));
+            }
+            let (mut emulation, output) = Emulation::spawn_debug(chip8, debugger, captures(&options), false, || ());
+            debugger::repl(emulation.sender(), output);
+            emulation.stop();
+            Ok(())
+        }
+        Command::Gdb => {
+            gdb::run(&options).unwrap_or_else(|err| exit_with(&err));
+            Ok(())
+        }
+        Command::Dap => {
+            dap::run(&options).unwrap_or_else(|err| exit_with(&err));
+            Ok(())
+        }
+        Command::Trace => {
+            trace::run(&options).unwrap_or_else(|err| exit_with(&err));
+            Ok(())
+        }
+        Command::Profile => {
+            let report = profile::run(&options).unwrap_or_else(|err| exit_with(&err));
+            match &options.out {
+                Some(path) => {
+                    std::fs::write(path, report).unwrap_or_else(|err| exit_with(&format!("{}: {}", path, err)));
+                    println!("saved {}", path);
+                }
+                None => print!("{}", report),
+            }
+            Ok(())
+        }
+        Command::Recompile => {
+            let path = recompiler::run(&options).unwrap_or_else(|err| exit_with(&err));
+            println!("saved {}", path.display());
+            Ok(())
+        }
+        Command::Record => {
+            let path = headless::record(&options).unwrap_or_else(|err| exit_with(&err));
+            println!("saved {}", path.display());
+            Ok(())
+        }
+    }
+}
+
+fn run_window(chip8: CHIPMachine, options: Options, debugger: Option<Debugger>) -> Result<(), Error> {
+    let event_loop = EventLoopBuilder::with_user_event().build();
+    let (mut frame_width, mut frame_height) = chip8.output_size();
+    let mut osd = options.osd.then(|| {
+        let mut osd = Osd::default();
+        osd.set_info(format!("{} {}", chip8.rom_name(), chip8.quirks().name()).to_uppercase());
+        osd
+    });
 
     // setup window
     let window = {
@@ -37,20 +149,59 @@ fn main() -> Result<(), Error> {
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture)?
+        Pixels::new(frame_width as u32, frame_height as u32, surface_texture)?
+    };
+
+    // The on-screen display needs redrawing every frame for its counters and
+    // messages, otherwise only frames that changed are sent.
+    let proxy = event_loop.create_proxy();
+    let wake = move || {
+        let _ = proxy.send_event(());
+    };
+    let mut emulation = match debugger {
+        Some(debugger) => {
+            let (emulation, output) = Emulation::spawn_debug(chip8, debugger, captures(&options), osd.is_some(), wake);
+            // The prompt only returns once the emulation thread is gone.
+            let input = emulation.sender();
+            thread::spawn(move || {
+                debugger::repl(input, output);
+                std::process::exit(0);
+            });
+            emulation
+        }
+        None => Emulation::spawn(chip8, captures(&options), osd.is_some(), wake),
     };
 
-    let mut chip8: CHIPMachine = CHIPMachine::new(WIDTH as usize, HEIGHT as usize);
-    chip8.load_rom(String::from("./roms/test_opcode.ch8"));
     event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
         match event {
             Event::WindowEvent { event, .. } => match event {
-                WindowEvent::KeyboardInput { input, .. } => {
-                    let key = input.virtual_keycode.unwrap();
-                    chip8.process_key(key);
-                }
+                WindowEvent::KeyboardInput { input, .. } => match input.virtual_keycode {
+                    Some(VirtualKeyCode::F2) if input.state == ElementState::Pressed => {
+                        emulation.send(Input::CyclePalette);
+                    }
+                    Some(VirtualKeyCode::F3) if input.state == ElementState::Pressed => {
+                        if let Some(osd) = osd.as_mut() {
+                            osd.show_stats = !osd.show_stats;
+                        }
+                    }
+                    Some(VirtualKeyCode::F4) if input.state == ElementState::Pressed => {
+                        if let Some(osd) = osd.as_mut() {
+                            osd.show_info = !osd.show_info;
+                        }
+                    }
+                    Some(VirtualKeyCode::F12) if input.state == ElementState::Pressed => {
+                        emulation.send(Input::Screenshot);
+                    }
+                    Some(VirtualKeyCode::F10) if input.state == ElementState::Pressed => {
+                        emulation.send(Input::ToggleRecording);
+                    }
+                    Some(key) => emulation.send(Input::Key(key, input.state == ElementState::Pressed)),
+                    None => (),
+                },
                 WindowEvent::CloseRequested => {
-                    println!("Window close event detected");
+                    debug!("window closed");
+                    emulation.stop();
                     *control_flow = ControlFlow::Exit
                 },
                 WindowEvent::Resized(size) => {
@@ -63,24 +214,54 @@ fn main() -> Result<(), Error> {
                 }
                 _ => ()
             },
-            Event::MainEventsCleared => {
-                // chip8 processing 
-                let elapsed = chip8.start_time.elapsed();
-                if elapsed >= chip8.cycle_duration {
-                    chip8.cycle();
-                    chip8.reset_start_time();
-                }
-                window.request_redraw();
-            },
+            // A new frame is waiting in the triple buffer.
+            Event::UserEvent(()) => window.request_redraw(),
             Event::RedrawRequested(_) => {
-                chip8.draw(pixels.get_frame_mut());
+                let frame = emulation.frame();
+                // 00FE and 00FF switch resolution.
+                if (frame.width, frame.height) != (frame_width, frame_height) {
+                    (frame_width, frame_height) = (frame.width, frame.height);
+                    if let Err(err) = pixels.resize_buffer(frame_width as u32, frame_height as u32) {
+                        error!("pixels.resize_buffer() failed: {}", err);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+                pixels.get_frame_mut().copy_from_slice(&frame.rgba);
+                let instructions = frame.instructions;
+                for message in emulation.messages() {
+                    notify(&mut osd, message);
+                }
+                if let Some(osd) = osd.as_mut() {
+                    osd.frame(instructions);
+                    osd.draw(pixels.get_frame_mut(), frame_width, frame_height);
+                }
                 if let Err(err) = pixels.render() {
                     error!("pixels.render() failed: {}", err);
                     *control_flow = ControlFlow::Exit;
-                    return;
                 }
             },
             _ => ()
         }
     });
 }
+
+fn captures(options: &Options) -> Captures {
+    Captures {
+        dir: PathBuf::from(&options.screenshot_dir),
+        screenshot_scale: options.screenshot_scale,
+        record_scale: options.record_scale,
+        crash_reports: options.crash_report,
+    }
+}
+
+fn notify(osd: &mut Option<Osd>, text: impl Into<String>) {
+    if let Some(osd) = osd.as_mut() {
+        osd.message(text);
+    }
+}
+
+fn exit_with(err: &str) -> ! {
+    eprintln!("{}", err);
+    std::process::exit(2);
+}
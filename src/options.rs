@@ -0,0 +1,340 @@
+use log::info;
+
+use crate::chip_machine::CHIPMachine;
+use crate::config::Config;
+use crate::font::{Font, FontSet};
+use crate::osd;
+use crate::phosphor::Persistence;
+use crate::quirks::Quirks;
+use crate::script::InputScript;
+use crate::terminal::Glyphs;
+use crate::trace::{self, Tracer};
+use crate::upscale::{self, Filter};
+use crate::{HEIGHT, WIDTH};
+
+const DEFAULT_ROM: &str = "./roms/test_opcode.ch8";
+const DEFAULT_FRAMES: usize = 60;
+// Upscaling used when the on-screen display needs more room than the filter gives.
+const OSD_FILTER: Filter = Filter::Nearest(4);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    // Play in a window.
+    Run,
+    // Run headless for `--frames` frames, then save a screenshot.
+    Screenshot,
+    // Run headless for `--frames` frames, recording them to a GIF.
+    Record,
+    // Compare the interpreter with the decode cache for `--frames` frames.
+    Bench,
+    // Translate the ROM into a Rust module of recompiled blocks.
+    Recompile,
+    // Run every machine in a manifest in parallel and report how they ended.
+    Batch,
+    // Run headless for `--frames` frames and compare the screen with `--golden` or `--hash`.
+    Test,
+    // Run the instruction conformance cases in a JSON file.
+    Conformance,
+    // Run under a gdb-like prompt on stdin, with the window open alongside.
+    Debug,
+    // Wait for gdb to connect on `--port` and let it drive the machine.
+    Gdb,
+    // Serve the Debug Adapter Protocol on stdio, or on `--port`.
+    Dap,
+    // Print a binary trace as text.
+    Trace,
+    // Run headless for `--frames` frames and report where the time went.
+    Profile,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "run" => Some(Command::Run),
+            "screenshot" => Some(Command::Screenshot),
+            "record" => Some(Command::Record),
+            "bench" => Some(Command::Bench),
+            "recompile" => Some(Command::Recompile),
+            "batch" => Some(Command::Batch),
+            "test" => Some(Command::Test),
+            "conformance" => Some(Command::Conformance),
+            "debug" => Some(Command::Debug),
+            "gdb" => Some(Command::Gdb),
+            "dap" => Some(Command::Dap),
+            "trace" => Some(Command::Trace),
+            "profile" => Some(Command::Profile),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frontend {
+    Window,
+    Terminal,
+}
+
+impl Frontend {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "window" => Ok(Frontend::Window),
+            "terminal" => Ok(Frontend::Terminal),
+            _ => Err(format!("unknown frontend '{}', expected window or terminal", text)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub command: Command,
+    pub rom: String,
+    pub font_set: FontSet,
+    pub font_file: Option<String>,
+    pub font_base: u16,
+    pub config: Option<String>,
+    pub palette: Option<String>,
+    pub persistence: Persistence,
+    pub filter: Filter,
+    pub frames: usize,
+    pub out: Option<String>,
+    pub screenshot_dir: String,
+    pub screenshot_scale: usize,
+    pub record_scale: usize,
+    pub frontend: Frontend,
+    pub glyphs: Glyphs,
+    pub osd: bool,
+    pub no_aot: bool,
+    pub seed: Option<u64>,
+    // The manifest for batch, the case file for conformance or the binary trace for trace.
+    pub file: Option<String>,
+    pub threads: Option<usize>,
+    pub input: Option<String>,
+    pub golden: Option<String>,
+    pub hash: Option<u64>,
+    pub update: bool,
+    // Label file for the debugger.
+    pub labels: Option<String>,
+    // Debug without opening the window.
+    pub no_window: bool,
+    // Port for the gdb and DAP servers.
+    pub port: Option<u16>,
+    // Where to write the instruction trace, and how.
+    pub trace: Option<String>,
+    pub trace_format: trace::Format,
+    pub trace_filter: trace::Filter,
+    // Only keep the last N instructions, written out at the end.
+    pub trace_ring: Option<usize>,
+    // Write a crash report when the ROM faults, with the last N instructions
+    // in it when `crash_ring` is set.
+    pub crash_report: bool,
+    pub crash_ring: Option<usize>,
+    // Where `profile` saves the call stacks for a flamegraph.
+    pub folded: Option<String>,
+    pub quirks: Quirks,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            command: Command::Run,
+            rom: String::from(DEFAULT_ROM),
+            font_set: FontSet::Octo,
+            font_file: None,
+            font_base: 0,
+            config: None,
+            palette: None,
+            persistence: Persistence::Off,
+            filter: Filter::None,
+            frames: DEFAULT_FRAMES,
+            out: None,
+            screenshot_dir: String::from("."),
+            screenshot_scale: 1,
+            record_scale: 1,
+            frontend: Frontend::Window,
+            glyphs: Glyphs::HalfBlock,
+            osd: false,
+            no_aot: false,
+            seed: None,
+            file: None,
+            threads: None,
+            input: None,
+            golden: None,
+            hash: None,
+            update: false,
+            labels: None,
+            no_window: false,
+            port: None,
+            trace: None,
+            trace_format: trace::Format::Text,
+            trace_filter: trace::Filter::default(),
+            trace_ring: None,
+            crash_report: true,
+            crash_ring: None,
+            folded: None,
+            quirks: Quirks::default(),
+        }
+    }
+}
+
+impl Options {
+    // chip8 batch MANIFEST [--threads N] [--out PATH] [options]
+    // chip8 gdb [rom] [--port N] [options]
+    // chip8 dap [--port N] [options]
+    // chip8 trace FILE
+    // chip8 profile [rom] [--frames N] [--input PATH] [--labels PATH] [--folded PATH] [--out PATH] [options]
+    // chip8 test [rom] (--golden PATH [--update] | --hash HEX) [options]
+    // chip8 [run|screenshot|record|bench|recompile] [rom] [--font NAME] [--font-file PATH] [--font-base ADDR]
+    //       [--config PATH] [--palette NAME] [--persistence MODE]
+    //       [--filter NAME] [--frames N] [--out PATH]
+    //       [--screenshot-dir DIR] [--screenshot-scale N] [--record-scale N]
+    //       [--frontend window|terminal] [--glyphs half|braille] [--osd] [--no-aot] [--seed N] [--input PATH] [--quirks NAME]
+    //       [--trace PATH [--trace-format text|binary] [--trace-filter SPEC] [--trace-ring N]]
+    //       [--no-crash-report] [--crash-ring N]
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.skip(1).peekable();
+        if let Some(command) = args.peek().and_then(|arg| Command::from_name(arg)) {
+            options.command = command;
+            args.next();
+        }
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--font" => {
+                    let name = value(&mut args, &arg)?;
+                    options.font_set = FontSet::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = FontSet::ALL.iter().map(|set| set.name()).collect();
+                        format!("unknown font '{}', expected one of: {}", name, names.join(", "))
+                    })?;
+                }
+                "--font-file" => options.font_file = Some(value(&mut args, &arg)?),
+                "--font-base" => options.font_base = parse_addr(&value(&mut args, &arg)?)?,
+                "--config" => options.config = Some(value(&mut args, &arg)?),
+                "--palette" => options.palette = Some(value(&mut args, &arg)?),
+                "--persistence" => options.persistence = Persistence::parse(&value(&mut args, &arg)?)?,
+                "--filter" => options.filter = Filter::parse(&value(&mut args, &arg)?)?,
+                "--frames" => options.frames = parse_count(&value(&mut args, &arg)?)?,
+                "--out" => options.out = Some(value(&mut args, &arg)?),
+                "--screenshot-dir" => options.screenshot_dir = value(&mut args, &arg)?,
+                "--screenshot-scale" => options.screenshot_scale = parse_scale(&value(&mut args, &arg)?)?,
+                "--record-scale" => options.record_scale = parse_scale(&value(&mut args, &arg)?)?,
+                "--frontend" => options.frontend = Frontend::parse(&value(&mut args, &arg)?)?,
+                "--glyphs" => options.glyphs = Glyphs::parse(&value(&mut args, &arg)?)?,
+                "--osd" => options.osd = true,
+                "--no-aot" => options.no_aot = true,
+                "--labels" => options.labels = Some(value(&mut args, &arg)?),
+                "--no-window" => options.no_window = true,
+                "--port" => {
+                    let text = value(&mut args, &arg)?;
+                    options.port = Some(text.parse().map_err(|_| format!("invalid port '{}'", text))?);
+                }
+                "--trace" => options.trace = Some(value(&mut args, &arg)?),
+                "--trace-format" => options.trace_format = trace::Format::parse(&value(&mut args, &arg)?)?,
+                "--trace-filter" => options.trace_filter = trace::Filter::parse(&value(&mut args, &arg)?)?,
+                "--trace-ring" => options.trace_ring = Some(parse_count(&value(&mut args, &arg)?)?),
+                "--folded" => options.folded = Some(value(&mut args, &arg)?),
+                "--no-crash-report" => options.crash_report = false,
+                "--crash-ring" => options.crash_ring = Some(parse_count(&value(&mut args, &arg)?)?),
+                "--seed" => {
+                    let text = value(&mut args, &arg)?;
+                    options.seed = Some(text.parse().map_err(|_| format!("invalid seed '{}'", text))?);
+                }
+                "--input" => options.input = Some(value(&mut args, &arg)?),
+                "--golden" => options.golden = Some(value(&mut args, &arg)?),
+                "--hash" => {
+                    let text = value(&mut args, &arg)?;
+                    options.hash = Some(u64::from_str_radix(&text, 16).map_err(|_| format!("invalid hash '{}'", text))?);
+                }
+                "--update" => options.update = true,
+                "--quirks" => options.quirks = Quirks::from_name(&value(&mut args, &arg)?)?,
+                "--threads" => options.threads = Some(parse_count(&value(&mut args, &arg)?)?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+                _ if matches!(options.command, Command::Batch | Command::Conformance | Command::Trace) => options.file = Some(arg),
+                _ => options.rom = arg,
+            }
+        }
+        // There's one tracer, and a streamed trace keeps nothing for the report.
+        if options.trace.is_some() && options.crash_ring.is_some() {
+            return Err(String::from("--crash-ring can't be combined with --trace"));
+        }
+        Ok(options)
+    }
+
+    pub fn font(&self) -> Result<Font, String> {
+        let font = match &self.font_file {
+            Some(path) => Font::from_file(path).map_err(|err| format!("{}: {}", path, err))?,
+            None => Font::builtin(self.font_set),
+        };
+        let font = font.with_base(self.font_base);
+        if !font.fits() {
+            return Err(format!("font base {:#05X} leaves no room below 0x200", self.font_base));
+        }
+        Ok(font)
+    }
+
+    // The `--input` script, or none.
+    pub fn input_script(&self) -> Result<InputScript, String> {
+        match &self.input {
+            Some(path) => InputScript::load(path),
+            None => Ok(InputScript::default()),
+        }
+    }
+
+    // Builds a machine with the font, palettes and video settings applied and the ROM loaded.
+    pub fn machine(&self) -> Result<CHIPMachine, String> {
+        let font = self.font()?;
+        let config = Config::load(self.config.as_deref())?;
+        let mut chip8 = CHIPMachine::new(WIDTH, HEIGHT);
+        chip8.load_font(&font);
+        chip8.set_palettes(config.palettes()?, self.palette.as_deref().or(config.palette.as_deref()))?;
+        chip8.set_quirks(self.quirks);
+        chip8.set_persistence(self.persistence);
+        chip8.set_filter(self.filter);
+        if self.osd && chip8.output_size().0 < osd::MIN_WIDTH {
+            chip8.set_filter(OSD_FILTER);
+        }
+        if let Some(seed) = self.seed {
+            chip8.seed(seed);
+        }
+        if let Some(path) = &self.trace {
+            let tracer = Tracer::file(path, self.trace_format, self.trace_ring)?;
+            chip8.set_tracer(tracer.with_filter(self.trace_filter.clone()));
+        } else if let Some(capacity) = self.crash_ring.filter(|_| self.crash_report) {
+            chip8.set_tracer(Tracer::ring(capacity));
+        }
+        chip8.load_rom(self.rom.clone()).map_err(|err| format!("{}: {}", self.rom, err))?;
+        if self.no_aot {
+            chip8.disable_aot();
+        }
+        if chip8.aot() {
+            info!("running recompiled blocks for {}", self.rom);
+        }
+        Ok(chip8)
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("missing value for '{}'", flag))
+}
+
+fn parse_count(text: &str) -> Result<usize, String> {
+    match text.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("invalid count '{}', expected a positive integer", text)),
+    }
+}
+
+fn parse_scale(text: &str) -> Result<usize, String> {
+    match text.parse() {
+        Ok(scale) if (1..=upscale::MAX_SCALE).contains(&scale) => Ok(scale),
+        _ => Err(format!("invalid scale '{}', expected 1 to {}", text, upscale::MAX_SCALE)),
+    }
+}
+
+// Accepts decimal or 0x-prefixed hexadecimal addresses.
+pub fn parse_addr(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid address '{}'", text))
+}
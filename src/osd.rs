@@ -0,0 +1,217 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+const MAX_MESSAGES: usize = 4;
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+// Glyphs are 3x5 with one pixel of spacing, drawn at a multiple of this size
+// that grows with the frame so text stays readable.
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2;
+const WIDTH_PER_TEXT_SCALE: usize = 256;
+
+// Frames narrower than this can't fit a useful line of text.
+pub const MIN_WIDTH: usize = 128;
+
+const TEXT_COLOR: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+// Text sits on a darkened box, this much of the original brightness is kept, out of 256.
+const BOX_LEVEL: u16 = 64;
+
+// On-screen display drawn on top of the finished RGBA frame, with a small
+// built-in bitmap font so no GPU text rendering is needed.
+#[derive(Clone, Debug)]
+pub struct Osd {
+    messages: VecDeque<(String, Instant)>,
+    pub show_stats: bool,
+    pub show_info: bool,
+    info: String,
+    stats: String,
+    stats_since: Instant,
+    frames: u32,
+    instructions: u64,
+}
+
+impl Default for Osd {
+    fn default() -> Self {
+        Self {
+            messages: VecDeque::new(),
+            show_stats: false,
+            show_info: false,
+            info: String::new(),
+            stats: String::new(),
+            stats_since: Instant::now(),
+            frames: 0,
+            instructions: 0,
+        }
+    }
+}
+
+impl Osd {
+    // Shows `text` for a couple of seconds.
+    pub fn message(&mut self, text: impl Into<String>) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((text.into(), Instant::now()));
+    }
+
+    // The ROM name and machine settings line.
+    pub fn set_info(&mut self, info: impl Into<String>) {
+        self.info = info.into();
+    }
+
+    // Called once per displayed frame with the total instructions executed so far.
+    pub fn frame(&mut self, instructions: u64) {
+        self.frames += 1;
+        let elapsed = self.stats_since.elapsed();
+        if elapsed >= STATS_INTERVAL {
+            let secs = elapsed.as_secs_f64();
+            let ips = instructions.saturating_sub(self.instructions) as f64 / secs;
+            self.stats = format!("{:.0} FPS {:.0} IPS", self.frames as f64 / secs, ips);
+            self.stats_since = Instant::now();
+            self.frames = 0;
+            self.instructions = instructions;
+        }
+        self.messages.retain(|(_, shown)| shown.elapsed() < MESSAGE_DURATION);
+    }
+
+    pub fn draw(&self, screen: &mut [u8], width: usize, height: usize) {
+        debug_assert_eq!(screen.len(), 4 * width * height);
+        let mut canvas = Canvas {
+            screen,
+            width,
+            height,
+            scale: (width / WIDTH_PER_TEXT_SCALE).max(1),
+        };
+        let line = LINE_HEIGHT * canvas.scale;
+        if self.show_info && !self.info.is_empty() {
+            canvas.text(&self.info, 0, 0);
+        }
+        if self.show_stats && !self.stats.is_empty() {
+            let x = width.saturating_sub(canvas.text_width(&self.stats));
+            canvas.text(&self.stats, x, 0);
+        }
+        let bottom = height.saturating_sub(self.messages.len() * line);
+        for (i, (text, _)) in self.messages.iter().enumerate() {
+            canvas.text(text, 0, bottom + i * line);
+        }
+    }
+}
+
+struct Canvas<'a> {
+    screen: &'a mut [u8],
+    width: usize,
+    height: usize,
+    scale: usize,
+}
+
+impl Canvas<'_> {
+    fn text_width(&self, text: &str) -> usize {
+        (text.chars().count() * ADVANCE + 1) * self.scale
+    }
+
+    // Draws `text` with its box's top left corner at (x, y), clipped to the frame.
+    fn text(&mut self, text: &str, x: usize, y: usize) {
+        let s = self.scale;
+        self.darken_box(x, y, self.text_width(text), LINE_HEIGHT * s);
+        for (i, c) in text.chars().enumerate() {
+            let bits = glyph(c);
+            for row in 0..GLYPH_HEIGHT {
+                for col in 0..GLYPH_WIDTH {
+                    let bit = (GLYPH_HEIGHT - 1 - row) * GLYPH_WIDTH + (GLYPH_WIDTH - 1 - col);
+                    if bits >> bit & 1 == 1 {
+                        let px = x + (1 + i * ADVANCE + col) * s;
+                        let py = y + (1 + row) * s;
+                        self.fill(px, py, s, s, |_| TEXT_COLOR);
+                    }
+                }
+            }
+        }
+    }
+
+    fn darken_box(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        self.fill(x, y, width, height, |[r, g, b, a]| {
+            let dim = |c: u8| ((c as u16 * BOX_LEVEL) >> 8) as u8;
+            [dim(r), dim(g), dim(b), a]
+        });
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: impl Fn([u8; 4]) -> [u8; 4]) {
+        for py in y..(y + height).min(self.height) {
+            for px in x..(x + width).min(self.width) {
+                let index = 4 * (py * self.width + px);
+                let pix = &mut self.screen[index..index + 4];
+                let old = [pix[0], pix[1], pix[2], pix[3]];
+                pix.copy_from_slice(&color(old));
+            }
+        }
+    }
+}
+
+// 3x5 glyph, one row per 3 bits with the top row in the highest bits.
+fn glyph(c: char) -> u16 {
+    match c.to_ascii_uppercase() {
+        '0' => 0b111_101_101_101_111,
+        '1' => 0b010_110_010_010_111,
+        '2' => 0b111_001_111_100_111,
+        '3' => 0b111_001_111_001_111,
+        '4' => 0b101_101_111_001_001,
+        '5' => 0b111_100_111_001_111,
+        '6' => 0b111_100_111_101_111,
+        '7' => 0b111_001_001_010_010,
+        '8' => 0b111_101_111_101_111,
+        '9' => 0b111_101_111_001_111,
+        'A' => 0b010_101_111_101_101,
+        'B' => 0b110_101_110_101_110,
+        'C' => 0b011_100_100_100_011,
+        'D' => 0b110_101_101_101_110,
+        'E' => 0b111_100_110_100_111,
+        'F' => 0b111_100_110_100_100,
+        'G' => 0b011_100_101_101_011,
+        'H' => 0b101_101_111_101_101,
+        'I' => 0b111_010_010_010_111,
+        'J' => 0b001_001_001_101_010,
+        'K' => 0b101_101_110_101_101,
+        'L' => 0b100_100_100_100_111,
+        'M' => 0b101_111_111_101_101,
+        'N' => 0b110_101_101_101_101,
+        'O' => 0b010_101_101_101_010,
+        'P' => 0b110_101_110_100_100,
+        'Q' => 0b010_101_101_110_011,
+        'R' => 0b110_101_110_101_101,
+        'S' => 0b011_100_010_001_110,
+        'T' => 0b111_010_010_010_010,
+        'U' => 0b101_101_101_101_111,
+        'V' => 0b101_101_101_101_010,
+        'W' => 0b101_101_111_111_101,
+        'X' => 0b101_101_010_101_101,
+        'Y' => 0b101_101_010_010_010,
+        'Z' => 0b111_001_010_100_111,
+        ' ' => 0,
+        '.' => 0b000_000_000_000_010,
+        ',' => 0b000_000_000_010_100,
+        ':' => 0b000_010_000_010_000,
+        '%' => 0b101_001_010_100_101,
+        '-' => 0b000_000_111_000_000,
+        '+' => 0b000_010_111_010_000,
+        '=' => 0b000_111_000_111_000,
+        '/' => 0b001_001_010_100_100,
+        '(' => 0b001_010_010_010_001,
+        ')' => 0b100_010_010_010_100,
+        '[' => 0b011_010_010_010_011,
+        ']' => 0b110_010_010_010_110,
+        '<' => 0b001_010_100_010_001,
+        '>' => 0b100_010_001_010_100,
+        '!' => 0b010_010_010_000_010,
+        '#' => 0b101_111_101_111_101,
+        '*' => 0b000_101_010_101_000,
+        '_' => 0b000_000_000_000_111,
+        '\'' => 0b010_010_000_000_000,
+        _ => 0b110_001_010_000_010, // ?
+    }
+}
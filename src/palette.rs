@@ -0,0 +1,82 @@
+pub type Color = [u8; 4];
+
+// One colour per combination of the two XO-CHIP bitplanes:
+// background, plane 1 only, plane 2 only, both planes.
+pub const PALETTE_SZ: usize = 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [Color; PALETTE_SZ],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::builtin()[0].clone()
+    }
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: [Color; PALETTE_SZ]) -> Self {
+        Self {
+            name: String::from(name),
+            colors,
+        }
+    }
+
+    pub fn from_hex(name: &str, colors: &[impl AsRef<str>]) -> Result<Self, String> {
+        if colors.len() != 2 && colors.len() != PALETTE_SZ {
+            return Err(format!(
+                "palette '{}' needs 2 or {} colours, got {}",
+                name,
+                PALETTE_SZ,
+                colors.len()
+            ));
+        }
+        let mut parsed = Vec::with_capacity(PALETTE_SZ);
+        for color in colors {
+            parsed.push(parse_color(color.as_ref())?);
+        }
+        // A two colour palette draws both planes in the foreground colour.
+        while parsed.len() < PALETTE_SZ {
+            parsed.push(parsed[1]);
+        }
+        Ok(Palette::new(name, [parsed[0], parsed[1], parsed[2], parsed[3]]))
+    }
+
+    // `planes` holds plane 1 in bit 0 and plane 2 in bit 1.
+    pub fn color(&self, planes: u8) -> Color {
+        self.colors[planes as usize & (PALETTE_SZ - 1)]
+    }
+
+    pub fn builtin() -> Vec<Palette> {
+        vec![
+            //#264653 #2a9d8f #e9c46a #e76f51
+            Palette::new("teal", [rgb(0x264653), rgb(0x2a9d8f), rgb(0xe9c46a), rgb(0xe76f51)]),
+            Palette::new("green", [rgb(0x0b1a0b), rgb(0x33ff33), rgb(0x1a801a), rgb(0xaaffaa)]),
+            Palette::new("amber", [rgb(0x1a0f00), rgb(0xffb000), rgb(0x805800), rgb(0xffd866)]),
+            Palette::new("lcd", [rgb(0x9bbc0f), rgb(0x0f380f), rgb(0x306230), rgb(0x8bac0f)]),
+            Palette::new("octo", [rgb(0x996600), rgb(0xffcc00), rgb(0xff6600), rgb(0x662200)]),
+            Palette::new("contrast", [rgb(0x000000), rgb(0xffffff), rgb(0xaaaaaa), rgb(0x555555)]),
+            // Colour-blind-safe presets. Their orange and blue are about as
+            // bright as each other, but differ along the blue-yellow axis that
+            // red-green colour blindness leaves intact.
+            Palette::new("okabe-ito", [rgb(0x000000), rgb(0xe69f00), rgb(0x56b4e9), rgb(0xf0e442)]),
+            Palette::new("blue-orange", [rgb(0x0c2340), rgb(0xffa630), rgb(0x4cb5f5), rgb(0xffffff)]),
+        ]
+    }
+}
+
+const fn rgb(hex: u32) -> Color {
+    [(hex >> 16) as u8, (hex >> 8) as u8, hex as u8, 0xFF]
+}
+
+// Parses `#rrggbb` (the leading `#` is optional).
+pub fn parse_color(text: &str) -> Result<Color, String> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    // from_str_radix would take a leading `+` too.
+    match hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        true => Ok(rgb(u32::from_str_radix(hex, 16).unwrap_or_default())),
+        false => Err(format!("invalid colour '{}', expected #rrggbb", text)),
+    }
+}
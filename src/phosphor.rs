@@ -0,0 +1,116 @@
+use crate::framebuffer::Framebuffer;
+use crate::palette::{Color, Palette};
+
+pub const DEFAULT_FADE_FRAMES: u8 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Persistence {
+    Off,
+    // Pixels that turn off fade to the background over this many frames.
+    Fade(u8),
+    // Each pixel shows the max of the current and the previous frame.
+    Deflicker,
+}
+
+impl Persistence {
+    // `off`, `deflicker`, `fade` or `fade:N`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.split_once(':') {
+            None if text == "off" => Ok(Persistence::Off),
+            None if text == "deflicker" => Ok(Persistence::Deflicker),
+            None if text == "fade" => Ok(Persistence::Fade(DEFAULT_FADE_FRAMES)),
+            Some(("fade", frames)) => match frames.parse() {
+                Ok(frames) if frames > 0 => Ok(Persistence::Fade(frames)),
+                _ => Err(format!("invalid fade length '{}'", frames)),
+            },
+            _ => Err(format!("unknown persistence mode '{}', expected off, deflicker or fade[:N]", text)),
+        }
+    }
+}
+
+// Simulates the afterglow of the VIP's CRT on the CPU. Keeps one byte of
+// brightness per pixel, where `u8::MAX` is fully lit.
+#[derive(Clone, Debug)]
+pub struct Phosphor {
+    mode: Persistence,
+    glow: Vec<u8>,
+    // Whether some pixel was still fading after the last `apply`.
+    fading: bool,
+    // The frame of the last `apply`, so the glow decays once per frame
+    // however often the screen is drawn.
+    frame: Option<u64>,
+}
+
+impl Phosphor {
+    pub fn new(mode: Persistence, sz: usize) -> Self {
+        Self {
+            mode,
+            glow: vec![0; sz],
+            fading: false,
+            frame: None,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: Persistence) {
+        self.mode = mode;
+        self.glow.fill(0);
+    }
+
+    // For a screen of `sz` pixels, everything dark.
+    pub fn resize(&mut self, sz: usize) {
+        self.glow = vec![0; sz];
+    }
+
+    // The next frame will differ from the last one even if the screen doesn't change.
+    pub fn is_fading(&self) -> bool {
+        self.fading
+    }
+
+    // Writes one RGBA pixel per pixel of `display` into `screen`, `frame`
+    // being the number of the 60 Hz frame it shows.
+    pub fn apply(&mut self, display: &Framebuffer, palette: &Palette, screen: &mut [u8], frame: u64) {
+        debug_assert_eq!(display.width() * display.height(), self.glow.len());
+        let background = palette.color(0);
+        let foreground = palette.color(1);
+        // Frames since the last call, none when the same frame is drawn again.
+        let frames = match self.frame {
+            Some(last) => frame.saturating_sub(last),
+            None => 1,
+        };
+        self.frame = Some(frame);
+        let per_frame = |decay: u8| (decay as u64 * frames).min(u8::MAX as u64) as u8;
+        let decay = match self.mode {
+            Persistence::Off => u8::MAX,
+            Persistence::Fade(length) => per_frame((u8::MAX as u16 / (length as u16 + 1)).max(1) as u8),
+            // Anything lit last frame stays lit for exactly one more.
+            Persistence::Deflicker => per_frame(u8::MAX - 1),
+        };
+
+        self.fading = false;
+        for ((on, glow), pix) in display.pixels().zip(self.glow.iter_mut()).zip(screen.chunks_exact_mut(4)) {
+            let color = if on {
+                *glow = u8::MAX;
+                foreground
+            } else {
+                *glow = glow.saturating_sub(decay);
+                self.fading |= *glow > 0;
+                match self.mode {
+                    Persistence::Deflicker if *glow > 0 => foreground,
+                    Persistence::Fade(_) => blend(background, foreground, *glow),
+                    _ => background,
+                }
+            };
+            pix.copy_from_slice(&color);
+        }
+    }
+}
+
+// Linear blend from `from` (weight 0) to `to` (weight 255).
+fn blend(from: Color, to: Color, weight: u8) -> Color {
+    let mut out = from;
+    for (channel, (a, b)) in out.iter_mut().zip(from.iter().zip(to.iter())) {
+        let (a, b, w) = (*a as u16, *b as u16, weight as u16);
+        *channel = ((a * (255 - w) + b * w) / 255) as u8;
+    }
+    out
+}
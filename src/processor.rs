@@ -1,15 +1,23 @@
-use rand::Rng;
-use crate::{HEIGHT, WIDTH};
+use std::fmt;
 
-const RAM_SZ: usize = 4096;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use crate::decode_cache::{self, DecodeCache};
+use crate::font::{Font, BIG_GLYPH_SZ, SMALL_GLYPH_SZ};
+use crate::framebuffer::Framebuffer;
+use crate::quirks::Quirks;
+use crate::watch::{Access, Location, Watches};
+use crate::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+
+pub const RAM_SZ: usize = 4096;
 const STACK_SZ: usize = 16;
 const KEYS_SZ: usize = 16;
 const V_SZ: usize = 16;
+// Highest address an instruction can start at and still fit in RAM.
+pub const LAST_PC: usize = RAM_SZ - 2;
 
 #[derive(Clone, Debug)]
 pub struct Processor {
-    pub pixels: Vec<bool>,
-    cycle_buffer: Vec<bool>,
+    pub display: Framebuffer,
 
     // memory
     pub ram: [u8; RAM_SZ],
@@ -21,22 +29,69 @@ pub struct Processor {
     pub delay_timer: u8,
     pub sound_timer: u8,
     pub keypad: [bool; KEYS_SZ],
+    // Key held down while Fx0A waits for it to be released.
+    pub awaited_key: Option<u8>,
+
+    // font
+    pub font_base: u16,
+    pub big_font_base: u16,
+
+    pub quirks: Quirks,
+
+    // Set when an instruction couldn't run, after which the processor stops
+    // with PC still on it.
+    pub fault: Option<Fault>,
+
+    // Checked on every access an instruction makes, see `read_v` and friends.
+    pub watches: Watches,
+
+    // Source for Cxkk, seeded so runs can be repeated.
+    rng: StdRng,
+
+    cache: DecodeCache,
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ProcessorAction {
     NextInstruction,
     SkipInstruction,
     JumpInstruction(u16),
+    // Run the same instruction again on the next cycle.
+    RepeatInstruction,
+    Fault(Fault),
+}
+
+// Things a ROM can do that no interpreter could carry on from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    // 2nnn with all 16 stack entries in use.
+    StackOverflow,
+    // 00EE with nothing to return to.
+    StackUnderflow,
+    // PC would have moved to this address, past the last instruction that fits in RAM.
+    PcOutOfRange(u16),
+    // An opcode no supported platform defines.
+    UnknownOpcode(u16),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::StackOverflow => write!(f, "stack overflow"),
+            Fault::StackUnderflow => write!(f, "stack underflow"),
+            Fault::PcOutOfRange(addr) => write!(f, "PC out of range at {:#05X}", addr),
+            Fault::UnknownOpcode(op) => write!(f, "unknown opcode {:04X}", op),
+        }
+    }
 }
 
 #[allow(non_snake_case)]
 #[allow(unused)]
 impl Processor {
-    pub fn new(sz: usize) -> Self {
+    pub fn new(width: usize, height: usize) -> Self {
         let mut cpu = Self {
-            pixels: vec![false; sz],
-            cycle_buffer: vec![false; sz],
+            display: Framebuffer::new(width, height),
 
             // memory
             ram: [0; RAM_SZ],
@@ -48,16 +103,34 @@ impl Processor {
             delay_timer: 0,
             sound_timer: 0,
             keypad: [false; KEYS_SZ],
-        };
+            awaited_key: None,
 
-        // Load in font for first 0x200 bytes
-        for (i, byte) in FONT_MEM.iter().enumerate() {
-            cpu.ram[i] = *byte;
-            println!("{}", byte);
-        }
+            // font
+            font_base: 0,
+            big_font_base: 0,
+
+            quirks: Quirks::default(),
+            fault: None,
+            watches: Watches::default(),
+            rng: StdRng::from_entropy(),
+
+            cache: DecodeCache::default(),
+        };
+        cpu.load_font(&Font::default());
         cpu
     }
 
+    // Copies both fonts into the interpreter area and remembers where they live for Fx29/Fx30.
+    pub fn load_font(&mut self, font: &Font) {
+        let base = font.base as usize;
+        let big_base = font.big_base() as usize;
+        self.ram[base..base + font.small.len()].copy_from_slice(&font.small);
+        self.ram[big_base..big_base + font.big.len()].copy_from_slice(&font.big);
+        self.font_base = font.base;
+        self.big_font_base = font.big_base();
+        self.cache.clear();
+    }
+
     pub fn load(&mut self, data: Vec<u8>) {
         for (i, &byte) in data.iter().enumerate() {
             let addr = 0x200 + i;
@@ -67,11 +140,26 @@ impl Processor {
                 break;
             }
         }
+        self.cache.clear();
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    // Called at 60 Hz, independent of the instruction rate.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
     }
 
     pub fn get_instruction(&self) -> Option<u16> {
-        let high_nibble = self.ram.get(self.pc as usize);
-        let low_nibble = self.ram.get((self.pc + 1) as usize);
+        self.instruction_at(self.pc)
+    }
+
+    pub fn instruction_at(&self, addr: u16) -> Option<u16> {
+        let high_nibble = self.ram.get(addr as usize);
+        let low_nibble = self.ram.get(addr as usize + 1);
         match (high_nibble, low_nibble) {
             (Some(high), Some(low)) => Some(((*high as u16) << 8) | *low as u16),
             _ => None,
@@ -79,14 +167,19 @@ impl Processor {
     }
 
     pub fn tick(&mut self, op: u16) {
-        let nibbles = (
-            ((op & 0xF000) >> 12) as u8,
-            ((op & 0x0F00) >> 8) as u8,
-            ((op & 0x00F0) >> 4) as u8,
-            (op & 0x000F) as u8,
-        );
-
-        let action = match nibbles {
+        if self.fault.is_some() {
+            return;
+        }
+        let action = self.execute(op);
+        self.advance(action);
+    }
+
+    // Runs `op` without moving PC. With a constant `op` this compiles down to
+    // a direct call of its handler, which recompiled blocks rely on.
+    #[inline(always)]
+    pub fn execute(&mut self, op: u16) -> ProcessorAction {
+        let nibbles = split_nibbles(op);
+        match nibbles {
             (0x0, 0x0, 0xF, 0xF) => self.op_00FF(nibbles),
             (0x0, 0x0, 0xE, 0x0) => self.op_00E0(),
             (0x0, 0x0, 0xE, 0xE) => self.op_00EE(),
@@ -112,7 +205,7 @@ impl Processor {
             (0xC, _, _, _) => self.op_Cxkk(nibbles),
             (0xD, _, _, 0x0) => self.op_Dxy0(nibbles),
             (0xD, _, _, _) => self.op_Dxyn(nibbles),
-            (0xE, _, 0x9, 0x1) => self.op_Ex9E(nibbles),
+            (0xE, _, 0x9, 0xE) => self.op_Ex9E(nibbles),
             (0xE, _, 0xA, 0x1) => self.op_ExA1(nibbles),
             (0xF, _, 0x0, 0x7) => self.op_Fx07(nibbles),
             (0xF, _, 0x0, 0xA) => self.op_Fx0A(nibbles),
@@ -131,28 +224,179 @@ impl Processor {
             (0xF, _, 0x3, 0x0) => self.op_Fx30(nibbles),
             (0xF, _, 0x7, 0x5) => self.op_Fx75(nibbles),
             (0xF, _, 0x8, 0x5) => self.op_Fx85(nibbles),
-            _ => ProcessorAction::NextInstruction,
+            _ => self.op_unknown(nibbles),
+        }
+    }
+
+    // Runs the instruction at PC, decoding it only the first time that address
+    // is executed. Same result as `tick(get_instruction())`.
+    pub fn step(&mut self) {
+        self.run(1);
+    }
+
+    // Runs up to `budget` instructions like `step`, stopping early after a
+    // jump, call or return, or a fault, and returns how many ran. PC stays in
+    // a local in between, which is most of what makes this faster than `tick`.
+    #[inline(always)]
+    pub fn run(&mut self, budget: usize) -> usize {
+        if self.fault.is_some() {
+            return 0;
+        }
+        // The cached instructions don't check watchpoints.
+        if !self.watches.is_empty() {
+            let op = self.get_instruction().unwrap_or_default();
+            self.tick(op);
+            return 1;
+        }
+        let mut pc = self.pc;
+        let mut ran = 0;
+        while ran < budget {
+            let decoded = self.cache.get_or_decode(&self.ram, pc);
+            let next = decode_cache::run(self, decoded, pc);
+            ran += 1;
+            if next > LAST_PC {
+                self.fault = Some(Fault::PcOutOfRange(next as u16));
+                break;
+            }
+            pc = next as u16;
+            if decoded.ends_block() {
+                break;
+            }
+        }
+        self.pc = pc;
+        ran
+    }
+
+    // Runs `op` the way `step` would run it from the decode cache.
+    pub fn step_decoded(&mut self, op: u16) {
+        if self.fault.is_some() {
+            return;
+        }
+        let next = decode_cache::run(self, decode_cache::decode(op), self.pc);
+        if next > LAST_PC {
+            self.fault = Some(Fault::PcOutOfRange(next as u16));
+        } else {
+            self.pc = next as u16;
+        }
+    }
+
+    pub fn advance(&mut self, action: ProcessorAction) {
+        let next = match action {
+            ProcessorAction::NextInstruction => self.pc as usize + 2,
+            ProcessorAction::SkipInstruction => self.pc as usize + 4,
+            ProcessorAction::JumpInstruction(j) => j as usize,
+            ProcessorAction::RepeatInstruction => return,
+            ProcessorAction::Fault(fault) => {
+                self.fault = Some(fault);
+                return;
+            }
         };
+        if next > LAST_PC {
+            self.fault = Some(Fault::PcOutOfRange(next as u16));
+        } else {
+            self.pc = next as u16;
+        }
+    }
+
+    // Describes the first broken invariant, if any. Every instruction keeps
+    // these, whatever the ROM does.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if self.pc as usize > LAST_PC {
+            return Err(format!("PC {:#06X} is past the end of RAM", self.pc));
+        }
+        if self.sp as usize > STACK_SZ {
+            return Err(format!("SP {} is deeper than the stack", self.sp));
+        }
+        if let Some(addr) = self.stack[..self.sp as usize].iter().find(|&&addr| addr as usize > LAST_PC) {
+            return Err(format!("return address {:#06X} is past the end of RAM", addr));
+        }
+        if let Some(key) = self.awaited_key.filter(|&key| key as usize >= KEYS_SZ) {
+            return Err(format!("awaited key {:#X} isn't on the keypad", key));
+        }
+        Ok(())
+    }
 
-        match action {
-            ProcessorAction::NextInstruction => self.pc += 2,
-            ProcessorAction::SkipInstruction => self.pc += 4,
-            ProcessorAction::JumpInstruction(j) => self.pc = j + 2 as u16,
+    // Every RAM write goes through here so cached decodes of the two
+    // instructions that overlap `addr` are dropped.
+    pub fn write_ram(&mut self, addr: usize, value: u8) {
+        self.ram[addr] = value;
+        self.cache.invalidate(addr);
+    }
+
+    // Instructions read and write RAM, V, I and the timers only through
+    // these, so watchpoints see every access. Debuggers and the 60 Hz timer
+    // tick go around them.
+    #[inline(always)]
+    fn watch(&mut self, location: Location, access: Access, old: u16, new: u16) {
+        if !self.watches.is_empty() {
+            self.watches.check(location, access, old, new, self.pc);
         }
     }
 
+    fn read_v(&mut self, x: usize) -> u8 {
+        let value = self.v[x];
+        self.watch(Location::V(x as u8), Access::Read, value as u16, value as u16);
+        value
+    }
+
+    fn write_v(&mut self, x: usize, value: u8) {
+        self.watch(Location::V(x as u8), Access::Write, self.v[x] as u16, value as u16);
+        self.v[x] = value;
+    }
+
+    fn read_i(&mut self) -> u16 {
+        self.watch(Location::I, Access::Read, self.i, self.i);
+        self.i
+    }
+
+    fn write_i(&mut self, value: u16) {
+        self.watch(Location::I, Access::Write, self.i, value);
+        self.i = value;
+    }
+
+    fn read_dt(&mut self) -> u8 {
+        let value = self.delay_timer;
+        self.watch(Location::Dt, Access::Read, value as u16, value as u16);
+        value
+    }
+
+    fn write_dt(&mut self, value: u8) {
+        self.watch(Location::Dt, Access::Write, self.delay_timer as u16, value as u16);
+        self.delay_timer = value;
+    }
+
+    fn write_st(&mut self, value: u8) {
+        self.watch(Location::St, Access::Write, self.sound_timer as u16, value as u16);
+        self.sound_timer = value;
+    }
+
+    fn read_mem(&mut self, addr: usize) -> u8 {
+        let value = self.ram[addr];
+        self.watch(Location::Ram(addr as u16), Access::Read, value as u16, value as u16);
+        value
+    }
+
+    fn write_mem(&mut self, addr: usize, value: u8) {
+        self.watch(Location::Ram(addr as u16), Access::Write, self.ram[addr] as u16, value as u16);
+        self.write_ram(addr, value);
+    }
+
     // Clear the display.
     pub fn op_00E0(&mut self) -> ProcessorAction {
-
-        self.cycle_buffer.clone_into(&mut self.pixels);
+        self.display.clear();
         ProcessorAction::NextInstruction
     }
 
     // The interpreter sets the program counter to the address at the top of the stack, then subtracts 1 from the stack pointer.
+    // The stack holds the address of the call itself, so execution resumes after it.
+    // Faults when the stack is empty.
     pub fn op_00EE(&mut self) -> ProcessorAction {
-        let addr = self.stack[self.sp as usize];
+        if self.sp == 0 {
+            return ProcessorAction::Fault(Fault::StackUnderflow);
+        }
         self.sp -= 1;
-        ProcessorAction::JumpInstruction(addr)
+        let addr = self.stack[self.sp as usize];
+        ProcessorAction::JumpInstruction(addr + 2)
     }
 
     // The interpreter sets the program counter to nnn.
@@ -161,18 +405,22 @@ impl Processor {
         ProcessorAction::JumpInstruction(nnn)
     }
 
-    // The interpreter increments the stack pointer, then puts the current PC on the top of the stack. The PC is then set to nnn.
+    // The interpreter puts the current PC on the top of the stack, then increments the stack pointer. The PC is then set to nnn.
+    // Faults when the stack is full.
     pub fn op_2nnn(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (nnn, ..) = process_nibbles(nibbles);
-        self.sp += 1;
+        if self.sp as usize == STACK_SZ {
+            return ProcessorAction::Fault(Fault::StackOverflow);
+        }
         self.stack[self.sp as usize] = self.pc;
+        self.sp += 1;
         ProcessorAction::JumpInstruction(nnn)
     }
 
     // The interpreter compares register Vx to kk, and if they are equal, increments the program counter by 2.
     pub fn op_3xkk(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, kk, x, ..) = process_nibbles(nibbles);
-        let vx = self.v[x];
+        let vx = self.read_v(x);
         if vx == kk { ProcessorAction::SkipInstruction } 
         else { ProcessorAction::NextInstruction }
     }
@@ -180,15 +428,15 @@ impl Processor {
     // The interpreter compares register Vx to kk, and if they are not equal, increments the program counter by 2.
     pub fn op_4xkk(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, kk, x, ..) = process_nibbles(nibbles);
-        let vx = self.v[x];
+        let vx = self.read_v(x);
         skip_if(vx != kk)
     }
 
     // The interpreter compares register Vx to register Vy, and if they are equal, increments the program counter by 2.
     pub fn op_5xy0(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, ..) = process_nibbles(nibbles);
-        let vx = self.v[x];
-        let vy = self.v[y];
+        let vx = self.read_v(x);
+        let vy = self.read_v(y);
         skip_if(vx == vy)
     }
 
@@ -196,138 +444,147 @@ impl Processor {
     pub fn op_6xkk(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, kk, ..) = process_nibbles(nibbles);
         let x = nibbles.1 as usize;
-        self.v[x] = kk;
+        self.write_v(x, kk);
         ProcessorAction::NextInstruction
     }
 
     // Adds the value kk to the value of register Vx, then stores the result in Vx.
     pub fn op_7xkk(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, kk, x, ..) = process_nibbles(nibbles);
-        let vx = self.v[x];
+        let vx = self.read_v(x);
         let result = vx.wrapping_add(kk);
-        self.v[x] = result as u8;
+        self.write_v(x, result);
         ProcessorAction::NextInstruction
     }
 
     // Stores the value of register Vy in register Vx.
     pub fn op_8xy0(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, _) = process_nibbles(nibbles);
-        let vy = self.v[y];
-        self.v[x] = vy;
+        let vy = self.read_v(y);
+        self.write_v(x, vy);
         ProcessorAction::NextInstruction
     }
 
     // Performs a bitwise OR on the values of Vx and Vy, then stores the result in Vx.
+    // With `quirks.vf_reset` VF is reset to 0.
     pub fn op_8xy1(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, _) = process_nibbles(nibbles);
-        let vx = self.v[x];
-        let vy = self.v[y];
-        self.v[x] = vy | vx;
+        let vx = self.read_v(x);
+        let vy = self.read_v(y);
+        self.write_v(x, vy | vx);
+        if self.quirks.vf_reset {
+            self.write_v(0xF, 0);
+        }
         ProcessorAction::NextInstruction
     }
 
-    // Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx. 
+    // Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx.
+    // With `quirks.vf_reset` VF is reset to 0.
     pub fn op_8xy2(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, _) = process_nibbles(nibbles);
-        let vx = self.v[x];
-        let vy = self.v[y];
-        self.v[x] = vy & vx;
+        let vx = self.read_v(x);
+        let vy = self.read_v(y);
+        self.write_v(x, vy & vx);
+        if self.quirks.vf_reset {
+            self.write_v(0xF, 0);
+        }
         ProcessorAction::NextInstruction
     }
 
     // Performs a bitwise exclusive OR on the values of Vx and Vy, then stores the result in Vx.
+    // With `quirks.vf_reset` VF is reset to 0.
     pub fn op_8xy3(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, _) = process_nibbles(nibbles);
-        let vx = self.v[x];
-        let vy = self.v[y];
-        self.v[x] = vy ^ vx;
+        let vx = self.read_v(x);
+        let vy = self.read_v(y);
+        self.write_v(x, vy ^ vx);
+        if self.quirks.vf_reset {
+            self.write_v(0xF, 0);
+        }
         ProcessorAction::NextInstruction
     }
 
     // The values of Vx and Vy are added together. 
     // If the result is greater than 8 bits (i.e., > 255,) VF is set to 1, otherwise 0. 
     // Only the lowest 8 bits of the result are kept, and stored in Vx.
+    // VF is written last, so the flag wins when x is F.
     pub fn op_8xy4(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, _) = process_nibbles(nibbles);
-        let vx = self.v[x] as u16;
-        let vy = self.v[y] as u16;
-        let result = vx + vy;
-        let carry = result > 0x100;
-        self.v[0xF] = if carry {1} else {0};
-        self.v[x] = (result & 0xFF) as u8;
+        let (result, carry) = self.read_v(x).overflowing_add(self.read_v(y));
+        self.write_v(x, result);
+        self.write_v(0xF, carry as u8);
         ProcessorAction::NextInstruction
     }
 
-    // If Vx > Vy, then VF is set to 1, otherwise 0.
+    // If Vx >= Vy, then VF is set to 1, otherwise 0.
     // Then Vy is subtracted from Vx, and the results stored in Vx.
     pub fn op_8xy5(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, _) = process_nibbles(nibbles);
-        let vx = self.v[x];
-        let vy = self.v[y];
-        let vf = vx > vy;
-        let result = vx.wrapping_sub(vy);
-        self.v[0xF] = if vf {1} else {0};
-        self.v[x] = result & 0xFF;
+        let (result, borrow) = self.read_v(x).overflowing_sub(self.read_v(y));
+        self.write_v(x, result);
+        self.write_v(0xF, !borrow as u8);
         ProcessorAction::NextInstruction
     }
 
-    // If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0. 
-    // Then Vx is divided by 2.
+    // If the least-significant bit of Vy is 1, then VF is set to 1, otherwise 0. 
+    // Then Vy divided by 2 is stored in Vx. With `quirks.shift_vx` Vx is shifted instead.
     pub fn op_8xy6(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
-        let (_, _, x, _, _) = process_nibbles(nibbles);
-        self.v[0xF] = self.v[x] & 0b1;
-        self.v[x] >>= 1;
+        let (_, _, x, y, _) = process_nibbles(nibbles);
+        let value = if self.quirks.shift_vx { self.read_v(x) } else { self.read_v(y) };
+        self.write_v(x, value >> 1);
+        self.write_v(0xF, value & 0b1);
         ProcessorAction::NextInstruction
     }
 
-    // If Vy > Vx, then VF is set to 1, otherwise 0. 
+    // If Vy >= Vx, then VF is set to 1, otherwise 0. 
     // Then Vx is subtracted from Vy, and the results stored in Vx.
     pub fn op_8xy7(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, _) = process_nibbles(nibbles);
-        let vx = self.v[x];
-        let vy = self.v[y];
-        self.v[0xF] = if vy > vx {1} else {0};
-        self.v[x] = (vy - vx) & 0xFF;
+        let (result, borrow) = self.read_v(y).overflowing_sub(self.read_v(x));
+        self.write_v(x, result);
+        self.write_v(0xF, !borrow as u8);
         ProcessorAction::NextInstruction
     }
 
-    // If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. 
-    // Then Vx is multiplied by 2.
+    // If the most-significant bit of Vy is 1, then VF is set to 1, otherwise to 0. 
+    // Then Vy multiplied by 2 is stored in Vx. With `quirks.shift_vx` Vx is shifted instead.
     pub fn op_8xyE(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
-        let (_, _, x, _, _) = process_nibbles(nibbles);
-        self.v[0xF] = self.v[x] >> 7;
-        self.v[x] <<= 1;
+        let (_, _, x, y, _) = process_nibbles(nibbles);
+        let value = if self.quirks.shift_vx { self.read_v(x) } else { self.read_v(y) };
+        self.write_v(x, value << 1);
+        self.write_v(0xF, value >> 7);
         ProcessorAction::NextInstruction
     }
 
     // Skip next instruction if Vx != Vy.
     pub fn op_9xy0(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, _) = process_nibbles(nibbles);
-        let vx = self.v[x];
-        let vy = self.v[y];
+        let vx = self.read_v(x);
+        let vy = self.read_v(y);
         skip_if(vx != vy)
     }
 
     // The value of register I is set to nnn.
     pub fn op_Annn(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (nnn, ..) = process_nibbles(nibbles);
-        self.i = nnn;
+        self.write_i(nnn);
         ProcessorAction::NextInstruction
     }
 
     // The program counter is set to nnn plus the value of V0.
+    // With `quirks.jump_vx` it's Vx instead, x being the top nibble of nnn.
     pub fn op_Bnnn(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
-        let (nnn, ..) = process_nibbles(nibbles);
-        self.i = nnn + (self.v[0] as u16);
-        ProcessorAction::NextInstruction
+        let (nnn, _, x, ..) = process_nibbles(nibbles);
+        let offset = if self.quirks.jump_vx { self.read_v(x) } else { self.read_v(0) };
+        ProcessorAction::JumpInstruction(nnn + offset as u16)
     }
 
     // The interpreter generates a random number from 0 to 255, 
     // which is then ANDed with the value kk. The results are stored in Vx.
     pub fn op_Cxkk(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, kk, x, ..) = process_nibbles(nibbles);
-        let rnd = rand::thread_rng().gen_range(0..256) as u8;
-        self.v[x] = kk & rnd;
+        let rnd: u8 = self.rng.gen();
+        self.write_v(x, kk & rnd);
         ProcessorAction::NextInstruction
     }
 
@@ -335,112 +592,135 @@ impl Processor {
     // These bytes are then displayed as sprites on screen at coordinates (Vx, Vy). 
     // Sprites are XORed onto the existing screen. If this causes any pixels to be erased, 
     // VF is set to 1, otherwise it is set to 0. If the sprite is positioned so part of it 
-    // is outside the coordinates of the display, it wraps around to the opposite side of the screen. 
+    // is outside the coordinates of the display, it wraps around to the opposite side of the screen,
+    // or with `quirks.clip` is cut off.
     pub fn op_Dxyn(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, y, n) = process_nibbles(nibbles);
-        let vx = self.v[x] as usize;
-        let vy = self.v[y] as usize;
-        let I = self.i as usize;
-
-        for byte in 0..n {
-            let jj = (byte + vy) % HEIGHT;
-            for bit in 0..8 {
-                let ii = (bit + vx) % WIDTH;
-                let color = (self.ram[I + byte] >> (7 - bit)) & 1;
-                self.v[0x0f] |= color & self.cycle_buffer[ii + jj * WIDTH] as u8;
-                self.cycle_buffer[ii + jj * WIDTH] ^= color == 1;
-            }
+        let vx = self.read_v(x) as usize;
+        let vy = self.read_v(y) as usize;
+        let i = self.read_i();
+        let mut sprite = [0; 15];
+        for (row, byte) in sprite.iter_mut().enumerate().take(n) {
+            *byte = self.read_mem(wrap(i, row));
         }
-        self.cycle_buffer.clone_into(&mut self.pixels);
+
+        let collision = self.display.draw_sprite(vx, vy, &sprite[..n], self.quirks.clip);
+        self.write_v(0xF, collision as u8);
         ProcessorAction::NextInstruction
     }
 
     // Skip next instruction if key with the value of Vx is pressed.
     pub fn op_Ex9E(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
-        // TODO
-        ProcessorAction::NextInstruction
+        let (_, _, x, ..) = process_nibbles(nibbles);
+        let key = (self.read_v(x) & 0xF) as usize;
+        skip_if(self.keypad[key])
     }
 
     // Skip next instruction if key with the value of Vx is not pressed.
     pub fn op_ExA1(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
-        // TODO
-        ProcessorAction::NextInstruction
+        let (_, _, x, ..) = process_nibbles(nibbles);
+        let key = (self.read_v(x) & 0xF) as usize;
+        skip_if(!self.keypad[key])
     }
 
     // Set Vx = delay timer value.
     pub fn op_Fx07(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, ..) = process_nibbles(nibbles);
-        self.v[x] = self.delay_timer;
+        let value = self.read_dt();
+        self.write_v(x, value);
         ProcessorAction::NextInstruction
     }
 
     // Wait for a key press, store the value of the key in Vx.
+    // Like the VIP, the key only counts once it has been released again.
     pub fn op_Fx0A(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
-        // TODO
-        ProcessorAction::NextInstruction
+        let (_, _, x, ..) = process_nibbles(nibbles);
+        match self.awaited_key {
+            Some(key) if !self.keypad[key as usize] => {
+                self.write_v(x, key);
+                self.awaited_key = None;
+                ProcessorAction::NextInstruction
+            }
+            Some(_) => ProcessorAction::RepeatInstruction,
+            None => {
+                self.awaited_key = self.keypad.iter().position(|&down| down).map(|key| key as u8);
+                ProcessorAction::RepeatInstruction
+            }
+        }
     }
 
     // Set delay timer = Vx.
     pub fn op_Fx15(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, ..) = process_nibbles(nibbles);
-        self.delay_timer = self.v[x];
+        let value = self.read_v(x);
+        self.write_dt(value);
         ProcessorAction::NextInstruction
     }
 
     // Set sound timer = Vx.
     pub fn op_Fx18(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, ..) = process_nibbles(nibbles);
-        self.sound_timer = self.v[x];
+        let value = self.read_v(x);
+        self.write_st(value);
         ProcessorAction::NextInstruction
     }
 
     // Set I = I + Vx.
     pub fn op_Fx1E(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, ..) = process_nibbles(nibbles);
-        self.i += self.v[x] as u16;
+        let value = self.read_i().wrapping_add(self.read_v(x) as u16);
+        self.write_i(value);
         ProcessorAction::NextInstruction
     }
 
     // Set I = location of sprite for digit Vx.
     pub fn op_Fx29(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, ..) = process_nibbles(nibbles);
-        let vx = self.v[x];
-
+        let vx = self.read_v(x) & 0xF;
+        self.write_i(self.font_base + vx as u16 * SMALL_GLYPH_SZ as u16);
         ProcessorAction::NextInstruction
     }
 
     // Store BCD representation of Vx in memory locations I, I+1, and I+2.
     pub fn op_Fx33(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, ..) = process_nibbles(nibbles);
-        let vx = self.v[x];
+        let vx = self.read_v(x);
+        let i = self.read_i();
         let hundreds = vx / 100;
         let tens = (vx / 10) % 10;
         let ones = vx % 10;
-        let index = self.i as usize;
-        self.ram[index] = hundreds;
-        self.ram[index + 1] = tens;
-        self.ram[index + 2] = ones;
+        self.write_mem(wrap(i, 0), hundreds);
+        self.write_mem(wrap(i, 1), tens);
+        self.write_mem(wrap(i, 2), ones);
         ProcessorAction::NextInstruction
     }
 
     // Store registers V0 through Vx in memory starting at location I.
+    // With `quirks.memory_increment` I ends up just past the last one.
     pub fn op_Fx55(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, ..) = process_nibbles(nibbles);
-        for i in 0..x {
-            let vi = self.v[i];
-            let index = self.i as usize + i;
-            self.ram[index] = vi;
+        let i = self.read_i();
+        for offset in 0..=x {
+            let value = self.read_v(offset);
+            self.write_mem(wrap(i, offset), value);
+        }
+        if self.quirks.memory_increment {
+            self.write_i(i.wrapping_add(x as u16 + 1));
         }
         ProcessorAction::NextInstruction
     }
 
     // Read registers V0 through Vx from memory starting at location I.
+    // With `quirks.memory_increment` I ends up just past the last one.
     pub fn op_Fx65(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         let (_, _, x, ..) = process_nibbles(nibbles);
-        for i in 0..x {
-            let index = i + self.i as usize;
-            let vi = self.ram[index];
-            self.v[i] = vi;
+        let i = self.read_i();
+        for offset in 0..=x {
+            let value = self.read_mem(wrap(i, offset));
+            self.write_v(offset, value);
+        }
+        if self.quirks.memory_increment {
+            self.write_i(i.wrapping_add(x as u16 + 1));
         }
         ProcessorAction::NextInstruction
     }
@@ -462,16 +742,24 @@ impl Processor {
     pub fn op_00FD(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         ProcessorAction::NextInstruction
     }
+    // Switch to the 64x32 low resolution mode, clearing the screen.
     pub fn op_00FE(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
+        self.display = Framebuffer::new(WIDTH, HEIGHT);
         ProcessorAction::NextInstruction
     }
+    // Switch to the 128x64 SUPER-CHIP high resolution mode, clearing the screen.
     pub fn op_00FF(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
+        self.display = Framebuffer::new(HIRES_WIDTH, HIRES_HEIGHT);
         ProcessorAction::NextInstruction
     }
     pub fn op_Dxy0(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         ProcessorAction::NextInstruction
     }
+    // Set I = location of the 10-byte SUPER-CHIP sprite for digit Vx.
     pub fn op_Fx30(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
+        let (_, _, x, ..) = process_nibbles(nibbles);
+        let vx = self.read_v(x) & 0xF;
+        self.write_i(self.big_font_base + vx as u16 * BIG_GLYPH_SZ as u16);
         ProcessorAction::NextInstruction
     }
     pub fn op_Fx75(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
@@ -480,6 +768,27 @@ impl Processor {
     pub fn op_Fx85(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
         ProcessorAction::NextInstruction
     }
+    // Anything `decode` doesn't know. Carrying on would only run data as code.
+    pub fn op_unknown(&mut self, nibbles: (u8, u8, u8, u8)) -> ProcessorAction {
+        let (a, b, c, d) = nibbles;
+        let op = (a as u16) << 12 | (b as u16) << 8 | (c as u16) << 4 | d as u16;
+        ProcessorAction::Fault(Fault::UnknownOpcode(op))
+    }
+}
+
+pub fn split_nibbles(op: u16) -> (u8, u8, u8, u8) {
+    (
+        ((op & 0xF000) >> 12) as u8,
+        ((op & 0x0F00) >> 8) as u8,
+        ((op & 0x00F0) >> 4) as u8,
+        (op & 0x000F) as u8,
+    )
+}
+
+
+// RAM address `offset` bytes past `i`. Addresses wrap around the 4 KB of RAM.
+fn wrap(i: u16, offset: usize) -> usize {
+    (i as usize + offset) % RAM_SZ
 }
 
 fn skip_if(v: bool) -> ProcessorAction {
@@ -503,25 +812,6 @@ fn process_nibbles(nibbles: (u8,u8,u8,u8)) -> (u16, u8, usize, usize, usize) {
         let nib3 = nibbles.3 as u16;
         nib1 | nib2 | nib3
     };
-    return (nnn, kk, x, y, n)
+    (nnn, kk, x, y, n)
 }
 
-
-const FONT_MEM: &'static [u8] = &[
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
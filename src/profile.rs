@@ -0,0 +1,285 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    ops::RangeInclusive,
+};
+
+use crate::chip_machine::{CHIPMachine, Status};
+use crate::debugger::read_labels;
+use crate::disasm::{classify, disassemble, Class};
+use crate::options::Options;
+use crate::processor::Processor;
+
+// Rows in the hot spot table.
+const HOT_SPOTS: usize = 20;
+// Where execution starts, the root of every call stack.
+const START: u16 = 0x200;
+
+// What happened during one 60 Hz frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    // Instructions run, not counting the ones spent waiting: Fx0A, a jump to
+    // itself or a loop polling the delay timer.
+    pub instructions: u64,
+    // Dxyn instructions run.
+    pub draws: u64,
+    // Whether the ROM got round to waiting, so its work fit in the frame.
+    pub waited: bool,
+}
+
+impl Frame {
+    // A frame where the ROM drew nothing, so the screen didn't move on.
+    pub fn lag(&self) -> bool {
+        self.draws == 0
+    }
+}
+
+// Counts what a machine executes, by address, by class of opcode and by call
+// stack. Subroutines are tracked by SP: when it goes up the new PC is the
+// entry of a subroutine, when it goes down the innermost one returned.
+pub struct Profiler {
+    labels: BTreeMap<String, u16>,
+    // Executions by address, one per byte of RAM.
+    counts: Vec<u64>,
+    classes: [u64; Class::ALL.len()],
+    // Entry addresses of the subroutines being run, outermost first.
+    stack: Vec<u16>,
+    // Executions by call stack.
+    stacks: BTreeMap<Vec<u16>, u64>,
+    frames: Vec<Frame>,
+    // The budget, instructions per frame.
+    budget: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            labels: BTreeMap::new(),
+            counts: vec![0; 4096],
+            classes: [0; Class::ALL.len()],
+            stack: vec![START],
+            stacks: BTreeMap::new(),
+            frames: Vec::new(),
+            budget: 0,
+        }
+    }
+}
+
+impl Profiler {
+    // Names subroutines in the reports by the labels at their entry.
+    pub fn with_labels(mut self, labels: BTreeMap<String, u16>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    // Runs the machine one instruction at a time to the end of the frame,
+    // or until it faults, counting everything it executes.
+    pub fn run_frame(&mut self, chip8: &mut CHIPMachine) {
+        self.budget = chip8.cycles_per_frame() as u64;
+        let mut frame = Frame::default();
+        loop {
+            let cpu = chip8.cpu();
+            let (pc, sp) = (cpu.pc, cpu.sp);
+            let Some(op) = cpu.get_instruction() else {
+                break;
+            };
+            let polling = polls_delay_timer(cpu, pc);
+            let done = chip8.step();
+            if let Status::Faulted(_) = chip8.status() {
+                break;
+            }
+            let cpu = chip8.cpu();
+            // Fx0A runs again until a key is released.
+            if polling || op == 0x1000 | pc || (op & 0xF0FF == 0xF00A && cpu.pc == pc) {
+                frame.waited = true;
+            } else {
+                frame.instructions += 1;
+            }
+            if op & 0xF000 == 0xD000 {
+                frame.draws += 1;
+            }
+            self.counts[pc as usize] += 1;
+            self.classes[classify(op) as usize] += 1;
+            *self.stacks.entry(self.stack.clone()).or_default() += 1;
+            if cpu.sp > sp {
+                self.stack.push(cpu.pc);
+            } else if cpu.sp < sp && self.stack.len() > 1 {
+                self.stack.pop();
+            }
+            if done {
+                break;
+            }
+        }
+        self.frames.push(frame);
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    // How often the instruction at `addr` ran.
+    pub fn count(&self, addr: u16) -> u64 {
+        self.counts.get(addr as usize).copied().unwrap_or_default()
+    }
+
+    // Every call stack and how many instructions ran in it, one per line as
+    // `0x200;0x20A;0x300 42`, which flamegraph.pl and inferno read.
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        for (stack, count) in &self.stacks {
+            let names: Vec<String> = stack.iter().map(|&entry| self.name(entry)).collect();
+            let _ = writeln!(out, "{} {}", names.join(";"), count);
+        }
+        out
+    }
+
+    pub fn report(&self, chip8: &CHIPMachine) -> String {
+        let total: u64 = self.counts.iter().sum();
+        let share = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+        let mut out = String::new();
+        let _ = writeln!(out, "{}: {} frames, {} instructions", chip8.rom_name(), self.frames.len(), total);
+
+        let busy: Vec<u64> = self.frames.iter().map(|frame| frame.instructions).collect();
+        let average = busy.iter().sum::<u64>() as f64 / busy.len().max(1) as f64;
+        let lag = frame_ranges(self.frames.iter().map(Frame::lag));
+        let over = frame_ranges(self.frames.iter().map(|frame| !frame.waited));
+        let _ = writeln!(out, "\nFrames");
+        let _ = writeln!(out, "  budget     {} instructions", self.budget);
+        let _ = writeln!(
+            out,
+            "  busy       min {}  avg {:.1}  max {}",
+            busy.iter().min().unwrap_or(&0),
+            average,
+            busy.iter().max().unwrap_or(&0)
+        );
+        let _ = writeln!(out, "  lag        {} without a sprite drawn{}", lag.0, lag.1);
+        let _ = writeln!(out, "  no wait    {} used the whole budget{}", over.0, over.1);
+
+        let mut hot: Vec<(u16, u64)> = (0..self.counts.len() as u16).map(|addr| (addr, self.count(addr))).filter(|&(_, count)| count > 0).collect();
+        hot.sort_by_key(|&(addr, count)| (std::cmp::Reverse(count), addr));
+        let _ = writeln!(out, "\nHot spots");
+        for &(addr, count) in hot.iter().take(HOT_SPOTS) {
+            let op = chip8.cpu().instruction_at(addr).unwrap_or_default();
+            let place = format!("{:#05X}{}", addr, self.label(addr));
+            let _ = writeln!(out, "  {:>10} {:>5.1}%  {:<20} {:04X}  {}", count, share(count), place, op, disassemble(op));
+        }
+
+        let _ = writeln!(out, "\nOpcode classes");
+        for (class, &count) in Class::ALL.iter().zip(&self.classes).filter(|(_, &count)| count > 0) {
+            let _ = writeln!(out, "  {:<8} {:>10} {:>5.1}%", class.name(), count, share(count));
+        }
+
+        // Self counts the instructions of the subroutine itself, total adds
+        // everything it called.
+        let mut subroutines: BTreeMap<u16, (u64, u64)> = BTreeMap::new();
+        for (stack, &count) in &self.stacks {
+            let mut seen = Vec::new();
+            for &entry in stack {
+                if !seen.contains(&entry) {
+                    seen.push(entry);
+                    subroutines.entry(entry).or_default().1 += count;
+                }
+            }
+            subroutines.entry(*stack.last().unwrap_or(&START)).or_default().0 += count;
+        }
+        let mut subroutines: Vec<(u16, (u64, u64))> = subroutines.into_iter().collect();
+        subroutines.sort_by_key(|&(entry, (_, all))| (std::cmp::Reverse(all), entry));
+        let _ = writeln!(out, "\nSubroutines");
+        let _ = writeln!(out, "  {:>10}  {:>17}", "self", "total");
+        for (entry, (own, all)) in subroutines {
+            let _ = writeln!(
+                out,
+                "  {:>10} {:>5.1}%  {:>10} {:>5.1}%  {}",
+                own,
+                share(own),
+                all,
+                share(all),
+                self.name(entry)
+            );
+        }
+        out
+    }
+
+    // ` <name>` for a label at `addr`.
+    fn label(&self, addr: u16) -> String {
+        self.labels
+            .iter()
+            .find(|(_, &at)| at == addr)
+            .map(|(name, _)| format!(" <{}>", name))
+            .unwrap_or_default()
+    }
+
+    // A subroutine's label, or its address.
+    fn name(&self, entry: u16) -> String {
+        match self.labels.iter().find(|(_, &at)| at == entry) {
+            Some((name, _)) => name.clone(),
+            None => format!("{:#05X}", entry),
+        }
+    }
+}
+
+// Whether `pc` is inside a loop that polls the delay timer: Fx07, then 3xkk
+// or 4xkk on the same Vx, then 1nnn back to the Fx07.
+fn polls_delay_timer(cpu: &Processor, pc: u16) -> bool {
+    (0..3).filter_map(|back| pc.checked_sub(2 * back)).any(|start| {
+        let op = |offset: u16| cpu.instruction_at(start + offset).unwrap_or_default();
+        let (read, test, jump) = (op(0), op(2), op(4));
+        read & 0xF0FF == 0xF007
+            && matches!(test >> 12, 0x3 | 0x4)
+            && test & 0x0F00 == read & 0x0F00
+            && jump == 0x1000 | start
+    })
+}
+
+// How many of the flags are set, and where, e.g. `: 0-3, 120`.
+fn frame_ranges(flags: impl Iterator<Item = bool>) -> (usize, String) {
+    let mut ranges: Vec<RangeInclusive<usize>> = Vec::new();
+    for (frame, _) in flags.enumerate().filter(|&(_, set)| set) {
+        match ranges.last_mut() {
+            Some(range) if *range.end() + 1 == frame => *range = *range.start()..=frame,
+            _ => ranges.push(frame..=frame),
+        }
+    }
+    let count = ranges.iter().map(|range| range.end() - range.start() + 1).sum();
+    let listed: Vec<String> = ranges
+        .iter()
+        .map(|range| {
+            if range.start() == range.end() {
+                range.start().to_string()
+            } else {
+                format!("{}-{}", range.start(), range.end())
+            }
+        })
+        .collect();
+    if listed.is_empty() {
+        (count, String::new())
+    } else {
+        (count, format!(": {}", listed.join(", ")))
+    }
+}
+
+// `chip8 profile`: runs the ROM headless for `--frames` frames with the
+// scripted key presses and returns the report. With `--folded` the call
+// stacks are saved for a flamegraph too.
+pub fn run(options: &Options) -> Result<String, String> {
+    let mut chip8 = options.machine()?;
+    let input = options.input_script()?;
+    let mut profiler = Profiler::default();
+    if let Some(path) = &options.labels {
+        profiler = profiler.with_labels(read_labels(path)?);
+    }
+    for frame in 0..options.frames {
+        if let Status::Faulted(_) = chip8.status() {
+            break;
+        }
+        for (key, pressed) in input.events(frame) {
+            chip8.set_key(key, pressed);
+        }
+        profiler.run_frame(&mut chip8);
+    }
+    if let Some(path) = &options.folded {
+        fs::write(path, profiler.folded()).map_err(|err| format!("{}: {}", path, err))?;
+    }
+    Ok(profiler.report(&chip8))
+}
@@ -0,0 +1,78 @@
+// Behaviours that differ between CHIP-8 interpreters. ROMs written for one
+// of them can misbehave on the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8xy1, 8xy2 and 8xy3 reset VF to 0 (COSMAC VIP).
+    pub vf_reset: bool,
+    // Fx55 and Fx65 leave I pointing past the last register (COSMAC VIP).
+    pub memory_increment: bool,
+    // 8xy6 and 8xyE shift Vx in place instead of shifting Vy into Vx (SUPER-CHIP).
+    pub shift_vx: bool,
+    // Bnnn jumps to nnn + Vx, x being the top nibble of nnn, instead of nnn + V0 (SUPER-CHIP).
+    pub jump_vx: bool,
+    // Sprites are cut off at the screen edges instead of wrapping around.
+    // Either way the starting position wraps.
+    pub clip: bool,
+}
+
+// (name, quirks) for every profile, the first being the default.
+pub const PROFILES: [(&str, Quirks); 3] = [
+    // What this emulator has always done.
+    (
+        "modern",
+        Quirks {
+            vf_reset: false,
+            memory_increment: false,
+            shift_vx: true,
+            jump_vx: false,
+            clip: false,
+        },
+    ),
+    (
+        "vip",
+        Quirks {
+            vf_reset: true,
+            memory_increment: true,
+            shift_vx: false,
+            jump_vx: false,
+            clip: true,
+        },
+    ),
+    (
+        "schip",
+        Quirks {
+            vf_reset: false,
+            memory_increment: false,
+            shift_vx: true,
+            jump_vx: true,
+            clip: true,
+        },
+    ),
+];
+
+impl Default for Quirks {
+    fn default() -> Self {
+        PROFILES[0].1
+    }
+}
+
+impl Quirks {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        PROFILES
+            .iter()
+            .find(|(profile, _)| *profile == name)
+            .map(|(_, quirks)| *quirks)
+            .ok_or_else(|| {
+                let names: Vec<&str> = PROFILES.iter().map(|(profile, _)| *profile).collect();
+                format!("unknown quirks '{}', expected one of: {}", name, names.join(", "))
+            })
+    }
+
+    // The profile's name, or "custom" for a mix that isn't one.
+    pub fn name(&self) -> &'static str {
+        PROFILES
+            .iter()
+            .find(|(_, quirks)| quirks == self)
+            .map_or("custom", |(profile, _)| profile)
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::disasm::{classify, Class};
+use crate::hash::fnv1a;
+use crate::options::Options;
+use crate::processor::{split_nibbles, LAST_PC};
+
+const START: u16 = 0x200;
+
+// How an instruction affects control flow, as far as splitting the ROM into
+// blocks goes. Anything that might not fall through ends a block.
+enum Flow {
+    Next,
+    // Execution continues at one of these addresses.
+    Branch(Vec<u16>),
+    // The target is only known at run time (00EE, Bnnn), so the interpreter
+    // carries on unless it lands on another block.
+    Dynamic,
+}
+
+fn flow(addr: u16, op: u16) -> Flow {
+    let next = addr + 2;
+    match split_nibbles(op) {
+        (0x0, 0x0, 0xE, 0xE) | (0xB, ..) => Flow::Dynamic,
+        (0x1, ..) => Flow::Branch(vec![op & 0x0FFF]),
+        (0x2, ..) => Flow::Branch(vec![op & 0x0FFF, next]),
+        (0x3, ..) | (0x4, ..) | (0x5, ..) | (0x9, ..) | (0xE, ..) => Flow::Branch(vec![next, next + 2]),
+        (0xF, _, 0x0, 0xA) => Flow::Branch(vec![addr, next]),
+        // Stores may rewrite the code that follows, ending the block makes
+        // the next one check its bytes first.
+        (0xF, _, 0x3, 0x3) | (0xF, _, 0x5, 0x5) => Flow::Branch(vec![next]),
+        // Unknown opcodes fault, which only `advance` records.
+        _ if classify(op) == Class::Unknown => Flow::Dynamic,
+        _ => Flow::Next,
+    }
+}
+
+// Follows every statically known path from 0x200 and returns the
+// instructions of each block by start address. Blocks may overlap when one
+// is entered part way through another.
+fn find_blocks(rom: &[u8]) -> BTreeMap<u16, Vec<u16>> {
+    let fetch = |addr: u16| {
+        let offset = addr.checked_sub(START)? as usize;
+        let bytes = rom.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let mut blocks = BTreeMap::new();
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![START];
+    while let Some(start) = pending.pop() {
+        if !seen.insert(start) {
+            continue;
+        }
+        let mut ops = Vec::new();
+        let mut addr = start;
+        while let Some(op) = fetch(addr) {
+            ops.push(op);
+            match flow(addr, op) {
+                Flow::Next => addr += 2,
+                Flow::Branch(targets) => {
+                    pending.extend(targets);
+                    break;
+                }
+                Flow::Dynamic => break,
+            }
+        }
+        if !ops.is_empty() {
+            blocks.insert(start, ops);
+        }
+    }
+    blocks
+}
+
+// Rust source for a module exposing `PROGRAM`, one function per block. Each
+// instruction goes through `Processor::execute` with a constant opcode, so
+// the compiler resolves the handler and no fetch or decode is left at run time.
+pub fn generate(rom: &[u8], name: &str) -> String {
+    let blocks = find_blocks(rom);
+    let mut out = String::new();
+    let _ = writeln!(out, "// Generated by `chip8 recompile` from {}, do not edit.", name);
+    let _ = writeln!(out, "use crate::aot::{{Block, Program}};");
+    let _ = writeln!(out, "use crate::processor::Processor;");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "pub static PROGRAM: Program = Program {{");
+    let _ = writeln!(out, "    rom_hash: {:#018x},", fnv1a(rom));
+    let _ = writeln!(out, "    blocks: &[");
+    for (start, ops) in &blocks {
+        let code: Vec<String> = ops.iter().flat_map(|op| op.to_be_bytes()).map(|b| format!("{:#04x}", b)).collect();
+        let _ = writeln!(
+            out,
+            "        Block {{ start: {:#05x}, code: &[{}], instructions: {}, run: block_{:03x} }},",
+            start,
+            code.join(", "),
+            ops.len(),
+            start
+        );
+    }
+    let _ = writeln!(out, "    ],");
+    let _ = writeln!(out, "}};");
+    for (&start, ops) in &blocks {
+        let end = start + 2 * ops.len() as u16;
+        let last = end - 2;
+        let _ = writeln!(out);
+        let _ = writeln!(out, "fn block_{:03x}(cpu: &mut Processor) {{", start);
+        for (&op, addr) in ops.iter().zip((start..).step_by(2)) {
+            match (addr == last, flow(addr, op)) {
+                // Falling off the end of RAM faults, which `advance` takes care of.
+                (true, Flow::Next) if end as usize <= LAST_PC => {
+                    let _ = writeln!(out, "    cpu.execute({:#06x});", op);
+                    let _ = writeln!(out, "    cpu.pc = {:#05x};", end);
+                }
+                (true, _) => {
+                    let _ = writeln!(out, "    cpu.pc = {:#05x};", addr);
+                    let _ = writeln!(out, "    let action = cpu.execute({:#06x});", op);
+                    let _ = writeln!(out, "    cpu.advance(action);");
+                }
+                (false, _) => {
+                    let _ = writeln!(out, "    cpu.execute({:#06x});", op);
+                }
+            }
+        }
+        let _ = writeln!(out, "}}");
+    }
+    out
+}
+
+// `chip8 recompile`: writes the ROM's blocks to `--out`, or `<rom>.rs`.
+pub fn run(options: &Options) -> Result<PathBuf, String> {
+    let rom = fs::read(&options.rom).map_err(|err| format!("{}: {}", options.rom, err))?;
+    let rom_path = Path::new(&options.rom);
+    let name = rom_path.file_name().and_then(|name| name.to_str()).unwrap_or("rom");
+    let path = match &options.out {
+        Some(out) => PathBuf::from(out),
+        None => PathBuf::from(rom_path.with_extension("rs").file_name().unwrap_or_default()),
+    };
+    fs::write(&path, generate(&rom, name)).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(path)
+}
@@ -0,0 +1,116 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use crate::chip_machine::CHIPMachine;
+use crate::palette::PALETTE_SZ;
+use crate::{HIRES_HEIGHT, HIRES_WIDTH};
+
+// GIF delays are in hundredths of a second, frames are emulated at 60 Hz.
+const CENTISECONDS_PER_SECOND: u64 = 100;
+const FRAMES_PER_SECOND: u64 = 60;
+// Browsers show shorter delays as 10 centiseconds, far too slow.
+const MIN_DELAY: u64 = 2;
+
+// Records emulated frames to an animated GIF. Runs of identical frames are
+// merged into one GIF frame with a longer delay, and a frame that changes
+// again before MIN_DELAY is up is dropped for the next one. The canvas is
+// always the SUPER-CHIP screen at `scale`, low resolution frames are drawn
+// twice as big so ROMs can switch between the two while recording.
+pub struct Recorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    path: PathBuf,
+    scale: usize,
+    width: u16,
+    height: u16,
+    // Last frame seen, not yet written because the next one might repeat it.
+    pending: Option<Vec<u8>>,
+    // Emulated frames seen so far and how many of them have been written.
+    frames: u64,
+    written_frames: u64,
+}
+
+impl Recorder {
+    // Starts `<rom>-<timestamp>.gif` inside `dir`.
+    pub fn start(chip8: &CHIPMachine, dir: &Path, scale: usize) -> io::Result<Self> {
+        let path = dir.join(crate::screenshot::file_name(chip8.rom_name(), "gif"));
+        Recorder::start_as(chip8, &path, scale)
+    }
+
+    // The palette active when recording starts is used for the whole file.
+    pub fn start_as(chip8: &CHIPMachine, path: &Path, scale: usize) -> io::Result<Self> {
+        assert!(scale > 0);
+        let (width, height) = (
+            u16::try_from(HIRES_WIDTH * scale).map_err(io::Error::other)?,
+            u16::try_from(HIRES_HEIGHT * scale).map_err(io::Error::other)?,
+        );
+        let palette: Vec<u8> = (0..PALETTE_SZ as u8)
+            .flat_map(|index| chip8.palette().color(index)[..3].to_vec())
+            .collect();
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, width, height, &palette).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+        Ok(Self {
+            encoder,
+            path: path.to_path_buf(),
+            scale,
+            width,
+            height,
+            pending: None,
+            frames: 0,
+            written_frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Adds the current screen as the next emulated frame.
+    pub fn push(&mut self, chip8: &CHIPMachine) -> io::Result<()> {
+        let frame = chip8.indexed_snapshot(self.scale * HIRES_WIDTH / chip8.size().0);
+        if self.pending.as_ref() != Some(&frame) {
+            if self.pending_delay() >= MIN_DELAY {
+                self.flush()?;
+            }
+            self.pending = Some(frame);
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<PathBuf> {
+        self.flush()?;
+        self.encoder.into_inner().map_err(io::Error::other)?;
+        Ok(self.path)
+    }
+
+    // Centiseconds from the last written frame until now. Rounded against
+    // the running total so 60 Hz does not drift.
+    fn pending_delay(&self) -> u64 {
+        let start = self.written_frames * CENTISECONDS_PER_SECOND / FRAMES_PER_SECOND;
+        let end = self.frames * CENTISECONDS_PER_SECOND / FRAMES_PER_SECOND;
+        end - start
+    }
+
+    // Writes the pending frame, lasting from the last written frame until now.
+    fn flush(&mut self) -> io::Result<()> {
+        let Some(buffer) = self.pending.take() else {
+            return Ok(());
+        };
+        // Only the last frame can be shorter, it's stretched a little.
+        let delay = self.pending_delay().max(MIN_DELAY);
+        self.written_frames = self.frames;
+        let frame = gif::Frame {
+            width: self.width,
+            height: self.height,
+            delay: delay.min(u16::MAX as u64) as u16,
+            buffer: Cow::Owned(buffer),
+            ..gif::Frame::default()
+        };
+        self.encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::chip_machine::CHIPMachine;
+
+// Saves the screen as `<rom>-<timestamp>.png` inside `dir` and returns the path.
+pub fn save(chip8: &CHIPMachine, dir: &Path, scale: usize) -> io::Result<PathBuf> {
+    let path = dir.join(file_name(chip8.rom_name(), "png"));
+    save_as(chip8, &path, scale)?;
+    Ok(path)
+}
+
+pub fn save_as(chip8: &CHIPMachine, path: &Path, scale: usize) -> io::Result<()> {
+    let (width, height) = chip8.size();
+    write_png(path, width * scale, height * scale, &chip8.snapshot(scale))
+}
+
+pub fn write_png(path: &Path, width: usize, height: usize, rgba: &[u8]) -> io::Result<()> {
+    encode_png(BufWriter::new(File::create(path)?), width, height, rgba)
+}
+
+pub fn encode_png(out: impl Write, width: usize, height: usize, rgba: &[u8]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(rgba).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+// `<rom>-YYYYMMDD-HHMMSS.<ext>` in UTC.
+pub fn file_name(rom_name: &str, ext: &str) -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let time = secs % 86400;
+    format!(
+        "{}-{:04}{:02}{:02}-{:02}{:02}{:02}.{}",
+        rom_name,
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        ext
+    )
+}
+
+// Days since 1970-01-01 to a (year, month, day) date, after Howard Hinnant's algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
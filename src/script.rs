@@ -0,0 +1,55 @@
+use std::fs;
+
+// Key presses to replay on a headless machine, one event per line:
+//
+//     # frame  key  down|up
+//     30       5    down
+//     36       5    up
+//
+// Keys are hex digits. An event applies just before its frame runs, frame 0
+// being the first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputScript {
+    // (frame, key, pressed), sorted by frame.
+    events: Vec<(usize, u8, bool)>,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || format!("line {}: expected '<frame> <key> down|up', got '{}'", number + 1, line);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [frame, key, action] = fields[..] else {
+                return Err(error());
+            };
+            let frame = frame.parse().map_err(|_| error())?;
+            let key = u8::from_str_radix(key, 16).ok().filter(|&key| key < 16).ok_or_else(error)?;
+            let pressed = match action {
+                "down" => true,
+                "up" => false,
+                _ => return Err(error()),
+            };
+            events.push((frame, key, pressed));
+        }
+        events.sort_by_key(|&(frame, ..)| frame);
+        Ok(Self { events })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Self::parse(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    // Key changes to make before running `frame`.
+    pub fn events(&self, frame: usize) -> impl Iterator<Item = (u8, bool)> + '_ {
+        self.events
+            .iter()
+            .filter(move |&&(at, ..)| at == frame)
+            .map(|&(_, key, pressed)| (key, pressed))
+    }
+}
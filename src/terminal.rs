@@ -0,0 +1,262 @@
+use std::{
+    io::{self, Write},
+    time::Instant,
+};
+
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    queue,
+    style::{self, Colors},
+    terminal,
+};
+
+use crate::chip_machine::{CHIPMachine, FRAME_DURATION};
+use crate::keypad;
+use crate::palette::Color;
+
+// Without key release events a key counts as held this many frames after
+// the last press or auto-repeat.
+const KEY_HOLD_FRAMES: u8 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Glyphs {
+    // One cell per 1x2 pixels using `▀`, with the foreground as the top pixel.
+    HalfBlock,
+    // One cell per 2x4 pixels using braille dots, in a single colour.
+    Braille,
+}
+
+impl Glyphs {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "half" => Ok(Glyphs::HalfBlock),
+            "braille" => Ok(Glyphs::Braille),
+            _ => Err(format!("unknown glyphs '{}', expected half or braille", text)),
+        }
+    }
+
+    // Pixels covered by one terminal cell.
+    fn cell_size(&self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cell {
+    glyph: char,
+    fg: Color,
+    bg: Color,
+}
+
+// Renders the screen as text, only writing the cells that changed since the
+// previous call so it stays usable over slow links.
+#[derive(Clone, Debug)]
+pub struct TerminalRenderer {
+    glyphs: Glyphs,
+    // The screen size in pixels this was made for.
+    width: usize,
+    height: usize,
+    columns: usize,
+    rows: usize,
+    // What the terminal currently shows, `None` when unknown.
+    cells: Vec<Option<Cell>>,
+}
+
+impl TerminalRenderer {
+    pub fn new(glyphs: Glyphs, width: usize, height: usize) -> Self {
+        let (cell_width, cell_height) = glyphs.cell_size();
+        let columns = width.div_ceil(cell_width);
+        let rows = height.div_ceil(cell_height);
+        Self {
+            glyphs,
+            width,
+            height,
+            columns,
+            rows,
+            cells: vec![None; columns * rows],
+        }
+    }
+
+    // Forces a full redraw, e.g. after the terminal was resized or cleared.
+    pub fn invalidate(&mut self) {
+        self.cells.fill(None);
+    }
+
+    pub fn render(&mut self, chip8: &CHIPMachine, out: &mut impl Write) -> io::Result<()> {
+        // 00FE and 00FF switch resolution, which starts over on a clear terminal.
+        let (width, height) = chip8.size();
+        if (width, height) != (self.width, self.height) {
+            *self = TerminalRenderer::new(self.glyphs, width, height);
+            queue!(out, terminal::Clear(terminal::ClearType::All))?;
+        }
+        let mut cursor_at = None;
+        let mut colors = None;
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let cell = self.cell(chip8, column, row);
+                let shown = &mut self.cells[row * self.columns + column];
+                if *shown == Some(cell) {
+                    continue;
+                }
+                *shown = Some(cell);
+                if cursor_at != Some((column, row)) {
+                    queue!(out, cursor::MoveTo(column as u16, row as u16))?;
+                }
+                if colors != Some((cell.fg, cell.bg)) {
+                    queue!(out, style::SetColors(Colors::new(rgb(cell.fg), rgb(cell.bg))))?;
+                    colors = Some((cell.fg, cell.bg));
+                }
+                queue!(out, style::Print(cell.glyph))?;
+                cursor_at = Some((column + 1, row));
+            }
+        }
+        if colors.is_some() {
+            queue!(out, style::ResetColor)?;
+        }
+        out.flush()
+    }
+
+    fn cell(&self, chip8: &CHIPMachine, column: usize, row: usize) -> Cell {
+        let (width, height) = chip8.size();
+        let (cell_width, cell_height) = self.glyphs.cell_size();
+        let (x, y) = (column * cell_width, row * cell_height);
+        let pixel = |dx: usize, dy: usize| x + dx < width && y + dy < height && chip8.pixel(x + dx, y + dy);
+        let palette = chip8.palette();
+        match self.glyphs {
+            Glyphs::HalfBlock => Cell {
+                glyph: '▀',
+                fg: palette.color(pixel(0, 0) as u8),
+                bg: palette.color(pixel(0, 1) as u8),
+            },
+            Glyphs::Braille => {
+                // Unicode braille dot numbering, column by column.
+                const DOTS: [(usize, usize, u32); 8] = [
+                    (0, 0, 0x01), (0, 1, 0x02), (0, 2, 0x04), (1, 0, 0x08),
+                    (1, 1, 0x10), (1, 2, 0x20), (0, 3, 0x40), (1, 3, 0x80),
+                ];
+                let bits = DOTS
+                    .iter()
+                    .filter(|(dx, dy, _)| pixel(*dx, *dy))
+                    .fold(0, |bits, (_, _, bit)| bits | bit);
+                Cell {
+                    glyph: char::from_u32(0x2800 + bits).unwrap_or(' '),
+                    fg: palette.color(1),
+                    bg: palette.color(0),
+                }
+            }
+        }
+    }
+}
+
+fn rgb(color: Color) -> style::Color {
+    style::Color::Rgb {
+        r: color[0],
+        g: color[1],
+        b: color[2],
+    }
+}
+
+// Puts the terminal into raw mode on the alternate screen and restores it on drop.
+struct RawTerminal {
+    release_events: bool,
+}
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut out = io::stdout();
+        queue!(out, terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(terminal::ClearType::All))?;
+        let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if release_events {
+            queue!(out, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+        out.flush()?;
+        Ok(Self { release_events })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        if self.release_events {
+            let _ = queue!(out, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(out, style::ResetColor, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// `--frontend terminal`: plays the machine in the terminal until Esc or Ctrl-C.
+pub fn run(mut chip8: CHIPMachine, glyphs: Glyphs) -> io::Result<()> {
+    let raw = RawTerminal::enter()?;
+    let mut out = io::stdout();
+    let (width, height) = chip8.size();
+    let mut renderer = TerminalRenderer::new(glyphs, width, height);
+    let mut held = [0u8; 16];
+    let mut next_frame = Instant::now();
+
+    loop {
+        while event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+            match event::read()? {
+                Event::Key(key) => {
+                    if is_quit(&key) {
+                        return Ok(());
+                    }
+                    if key.code == KeyCode::F(2) && key.kind == KeyEventKind::Press {
+                        chip8.cycle_palette();
+                        renderer.invalidate();
+                    }
+                    if let Some(hex) = key_char(&key).and_then(keypad::from_char) {
+                        let pressed = key.kind != KeyEventKind::Release;
+                        chip8.set_key(hex, pressed);
+                        if !raw.release_events {
+                            held[hex as usize] = if pressed { KEY_HOLD_FRAMES } else { 0 };
+                        }
+                    }
+                }
+                Event::Resize(..) => {
+                    queue!(out, terminal::Clear(terminal::ClearType::All))?;
+                    renderer.invalidate();
+                }
+                _ => (),
+            }
+        }
+
+        chip8.run_frame();
+        renderer.render(&chip8, &mut out)?;
+        for (hex, frames) in held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    chip8.set_key(hex as u8, false);
+                }
+            }
+        }
+
+        // Don't try to catch up after a long stall, e.g. a suspended process.
+        next_frame += FRAME_DURATION;
+        let now = Instant::now();
+        if next_frame + 4 * FRAME_DURATION < now {
+            next_frame = now;
+        }
+    }
+}
+
+fn is_quit(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Esc || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL))
+}
+
+fn key_char(key: &KeyEvent) -> Option<char> {
+    match key.code {
+        KeyCode::Char(c) => Some(c),
+        _ => None,
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use log::error;
+
+use crate::disasm::{classify, disassemble, Class};
+use crate::options::Options;
+use crate::processor::{Fault, Processor};
+
+// Starts every binary trace, followed by a version byte.
+const MAGIC: &[u8; 4] = b"C8TR";
+const VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    // One line per instruction, e.g.
+    //        12 0x20A 6105  LD V1, 0x05          V1=05
+    Text,
+    // A few bytes per instruction, for long runs. `chip8 trace FILE` turns it
+    // back into text.
+    Binary,
+}
+
+impl Format {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "text" => Ok(Format::Text),
+            "binary" => Ok(Format::Binary),
+            _ => Err(format!("unknown trace format '{}', expected text or binary", text)),
+        }
+    }
+}
+
+// Which instructions get traced: those inside any of `ranges` whose class is
+// one of `classes`. Empty lists let everything through.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub ranges: Vec<RangeInclusive<u16>>,
+    pub classes: Vec<Class>,
+}
+
+impl Filter {
+    // Comma separated addresses (`2A4`), address ranges (`200-2FF`) and
+    // classes (`draw`), addresses in hex.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut filter = Filter::default();
+        for item in text.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            if let Some(class) = Class::from_name(item) {
+                filter.classes.push(class);
+                continue;
+            }
+            let addr = |text: &str| {
+                let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+                u16::from_str_radix(digits, 16).map_err(|_| {
+                    let classes: Vec<&str> = Class::ALL.iter().map(|class| class.name()).collect();
+                    format!(
+                        "invalid trace filter '{}', expected ADDR, ADDR-ADDR or one of: {}",
+                        item,
+                        classes.join(", ")
+                    )
+                })
+            };
+            let range = match item.split_once('-') {
+                Some((start, end)) => addr(start)?..=addr(end)?,
+                None => addr(item)?..=addr(item)?,
+            };
+            filter.ranges.push(range);
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, pc: u16, op: u16) -> bool {
+        (self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&pc)))
+            && (self.classes.is_empty() || self.classes.contains(&classify(op)))
+    }
+}
+
+// A register an instruction changed, with its new value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    V(u8, u8),
+    I(u16),
+    Sp(u8),
+    Dt(u8),
+    St(u8),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::V(x, value) => write!(f, "V{:X}={:02X}", x, value),
+            Change::I(value) => write!(f, "I={:03X}", value),
+            Change::Sp(value) => write!(f, "SP={:X}", value),
+            Change::Dt(value) => write!(f, "DT={:02X}", value),
+            Change::St(value) => write!(f, "ST={:02X}", value),
+        }
+    }
+}
+
+// One executed instruction. PC is where it was, not where it went.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    // Instructions executed before this one.
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub changes: Vec<Change>,
+    pub fault: Option<Fault>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut line = format!("{:>10} {:#05X} {:04X}  {:<20}", self.cycle, self.pc, self.opcode, disassemble(self.opcode));
+        for change in &self.changes {
+            line += &format!(" {}", change);
+        }
+        if let Some(fault) = self.fault {
+            line += &format!(" fault: {}", fault);
+        }
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+// The registers an instruction can change, besides PC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Registers {
+    v: [u8; 16],
+    i: u16,
+    sp: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+}
+
+impl Registers {
+    fn of(cpu: &Processor) -> Self {
+        Self {
+            v: cpu.v,
+            i: cpu.i,
+            sp: cpu.sp,
+            delay_timer: cpu.delay_timer,
+            sound_timer: cpu.sound_timer,
+        }
+    }
+
+    fn changes(&self, after: &Registers) -> Vec<Change> {
+        let mut changes: Vec<Change> = (0..16)
+            .filter(|&x| self.v[x] != after.v[x])
+            .map(|x| Change::V(x as u8, after.v[x]))
+            .collect();
+        if self.i != after.i {
+            changes.push(Change::I(after.i));
+        }
+        if self.sp != after.sp {
+            changes.push(Change::Sp(after.sp as u8));
+        }
+        if self.delay_timer != after.delay_timer {
+            changes.push(Change::Dt(after.delay_timer));
+        }
+        if self.sound_timer != after.sound_timer {
+            changes.push(Change::St(after.sound_timer));
+        }
+        changes
+    }
+}
+
+enum Sink {
+    Stream {
+        out: Box<dyn Write + Send>,
+        format: Format,
+        // Cycle of the last binary entry, which later ones count from.
+        last_cycle: u64,
+    },
+    // The last `capacity` entries, written to `dump` when the tracer goes away.
+    Ring {
+        entries: VecDeque<Entry>,
+        capacity: usize,
+        dump: Option<(Box<dyn Write + Send>, Format)>,
+    },
+}
+
+// Records every instruction a machine runs. See `CHIPMachine::set_tracer`.
+pub struct Tracer {
+    filter: Filter,
+    sink: Sink,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tracer").field("filter", &self.filter).finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    // Writes each instruction to `out` as it runs.
+    pub fn stream(out: impl Write + Send + 'static, format: Format) -> io::Result<Self> {
+        let mut out: Box<dyn Write + Send> = Box::new(out);
+        if format == Format::Binary {
+            write_header(&mut out)?;
+        }
+        Ok(Self {
+            filter: Filter::default(),
+            sink: Sink::Stream {
+                out,
+                format,
+                last_cycle: 0,
+            },
+        })
+    }
+
+    // Keeps only the last `capacity` instructions in memory.
+    pub fn ring(capacity: usize) -> Self {
+        Self {
+            filter: Filter::default(),
+            sink: Sink::Ring {
+                entries: VecDeque::with_capacity(capacity),
+                capacity,
+                dump: None,
+            },
+        }
+    }
+
+    // A file for `--trace`: streamed to, or with `ring` written once at the end.
+    pub fn file(path: &str, format: Format, ring: Option<usize>) -> Result<Self, String> {
+        let out = BufWriter::new(File::create(path).map_err(|err| format!("{}: {}", path, err))?);
+        match ring {
+            Some(capacity) => Ok(Self::ring(capacity).dump_to(out, format)),
+            None => Self::stream(out, format).map_err(|err| format!("{}: {}", path, err)),
+        }
+    }
+
+    // For a ring buffer, where the entries go when the tracer is dropped.
+    pub fn dump_to(mut self, out: impl Write + Send + 'static, format: Format) -> Self {
+        if let Sink::Ring { dump, .. } = &mut self.sink {
+            *dump = Some((Box::new(out), format));
+        }
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    // What the ring buffer holds, oldest first. Empty when streaming.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        let entries = match &self.sink {
+            Sink::Ring { entries, .. } => Some(entries.iter()),
+            Sink::Stream { .. } => None,
+        };
+        entries.into_iter().flatten()
+    }
+
+    // Runs the instruction at PC, recording it when it passes the filter.
+    // `cycle` is how many instructions ran before it.
+    pub fn step(&mut self, cpu: &mut Processor, cycle: u64) {
+        let pc = cpu.pc;
+        let traced = match cpu.get_instruction() {
+            Some(op) if cpu.fault.is_none() && self.filter.matches(pc, op) => Some(op),
+            _ => None,
+        };
+        let Some(opcode) = traced else {
+            cpu.step();
+            return;
+        };
+        let before = Registers::of(cpu);
+        cpu.step();
+        self.record(Entry {
+            cycle,
+            pc,
+            opcode,
+            changes: before.changes(&Registers::of(cpu)),
+            fault: cpu.fault,
+        });
+    }
+
+    fn record(&mut self, entry: Entry) {
+        match &mut self.sink {
+            Sink::Stream {
+                out,
+                format,
+                last_cycle,
+            } => {
+                let written = match format {
+                    Format::Text => writeln!(out, "{}", entry),
+                    Format::Binary => write_binary(out, &entry, *last_cycle),
+                };
+                *last_cycle = entry.cycle;
+                if let Err(err) = written {
+                    // Don't keep failing on every instruction.
+                    error!("trace stopped: {}", err);
+                    self.sink = Sink::Ring {
+                        entries: VecDeque::new(),
+                        capacity: 0,
+                        dump: None,
+                    };
+                }
+            }
+            Sink::Ring { entries, capacity, .. } => {
+                if entries.len() == *capacity {
+                    entries.pop_front();
+                }
+                if *capacity > 0 {
+                    entries.push_back(entry);
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Stream { out, .. } => out.flush(),
+            Sink::Ring { entries, dump, .. } => {
+                let Some((out, format)) = dump else {
+                    return Ok(());
+                };
+                let mut last_cycle = 0;
+                if *format == Format::Binary {
+                    write_header(out)?;
+                }
+                for entry in entries.iter() {
+                    match format {
+                        Format::Text => writeln!(out, "{}", entry)?,
+                        Format::Binary => write_binary(out, entry, last_cycle)?,
+                    }
+                    last_cycle = entry.cycle;
+                }
+                out.flush()
+            }
+        }
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            error!("unable to finish trace: {}", err);
+        }
+    }
+}
+
+fn write_header(out: &mut impl Write) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_u8(VERSION)
+}
+
+// Each entry is
+//   the cycles since the previous entry, as a LEB128 varint
+//   PC and opcode, u16 each
+//   a u16 mask of the V registers that changed, bit x for Vx
+//   a u8 mask of the rest: I, SP, DT, ST, then a fault
+//   the new values in that order, u8 except I
+//   for a fault its kind, 1 to 4 as declared, then the address or opcode it carries
+// with everything little-endian.
+fn write_binary(out: &mut impl Write, entry: &Entry, last_cycle: u64) -> io::Result<()> {
+    let mut delta = entry.cycle - last_cycle;
+    loop {
+        let byte = (delta & 0x7F) as u8;
+        delta >>= 7;
+        if delta == 0 {
+            out.write_u8(byte)?;
+            break;
+        }
+        out.write_u8(byte | 0x80)?;
+    }
+    out.write_u16::<LittleEndian>(entry.pc)?;
+    out.write_u16::<LittleEndian>(entry.opcode)?;
+    let mut v_mask = 0u16;
+    let mut mask = 0u8;
+    for change in &entry.changes {
+        match change {
+            Change::V(x, _) => v_mask |= 1 << x,
+            Change::I(_) => mask |= 1,
+            Change::Sp(_) => mask |= 2,
+            Change::Dt(_) => mask |= 4,
+            Change::St(_) => mask |= 8,
+        }
+    }
+    if entry.fault.is_some() {
+        mask |= 16;
+    }
+    out.write_u16::<LittleEndian>(v_mask)?;
+    out.write_u8(mask)?;
+    for change in &entry.changes {
+        match *change {
+            Change::I(value) => out.write_u16::<LittleEndian>(value)?,
+            Change::V(_, value) | Change::Sp(value) | Change::Dt(value) | Change::St(value) => out.write_u8(value)?,
+        }
+    }
+    match entry.fault {
+        Some(Fault::StackOverflow) => out.write_u8(1),
+        Some(Fault::StackUnderflow) => out.write_u8(2),
+        Some(Fault::PcOutOfRange(addr)) => {
+            out.write_u8(3)?;
+            out.write_u16::<LittleEndian>(addr)
+        }
+        Some(Fault::UnknownOpcode(op)) => {
+            out.write_u8(4)?;
+            out.write_u16::<LittleEndian>(op)
+        }
+        None => Ok(()),
+    }
+}
+
+// Reads a binary trace back, one entry at a time.
+pub struct BinaryReader<R> {
+    input: R,
+    cycle: u64,
+}
+
+impl<R: Read> BinaryReader<R> {
+    pub fn new(mut input: R) -> Result<Self, String> {
+        let mut header = [0; 5];
+        input.read_exact(&mut header).map_err(|_| String::from("not a binary trace"))?;
+        if &header[..4] != MAGIC {
+            return Err(String::from("not a binary trace"));
+        }
+        if header[4] != VERSION {
+            return Err(format!("unsupported trace version {}", header[4]));
+        }
+        Ok(Self { input, cycle: 0 })
+    }
+
+    fn entry(&mut self, first: u8) -> io::Result<Entry> {
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        let (mut delta, mut shift, mut byte) = (0u64, 0, first);
+        loop {
+            delta |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift >= 64 {
+                return Err(invalid("cycle delta longer than 64 bits"));
+            }
+            byte = self.input.read_u8()?;
+        }
+        self.cycle = self.cycle.checked_add(delta).ok_or_else(|| invalid("cycle count past 2^64"))?;
+        let pc = self.input.read_u16::<LittleEndian>()?;
+        let opcode = self.input.read_u16::<LittleEndian>()?;
+        let v_mask = self.input.read_u16::<LittleEndian>()?;
+        let mask = self.input.read_u8()?;
+        let mut changes = Vec::new();
+        for x in (0..16).filter(|x| v_mask & 1 << x != 0) {
+            changes.push(Change::V(x, self.input.read_u8()?));
+        }
+        if mask & 1 != 0 {
+            changes.push(Change::I(self.input.read_u16::<LittleEndian>()?));
+        }
+        if mask & 2 != 0 {
+            changes.push(Change::Sp(self.input.read_u8()?));
+        }
+        if mask & 4 != 0 {
+            changes.push(Change::Dt(self.input.read_u8()?));
+        }
+        if mask & 8 != 0 {
+            changes.push(Change::St(self.input.read_u8()?));
+        }
+        let fault = match mask & 16 {
+            0 => None,
+            _ => match self.input.read_u8()? {
+                1 => Some(Fault::StackOverflow),
+                2 => Some(Fault::StackUnderflow),
+                3 => Some(Fault::PcOutOfRange(self.input.read_u16::<LittleEndian>()?)),
+                4 => Some(Fault::UnknownOpcode(self.input.read_u16::<LittleEndian>()?)),
+                kind => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown fault {}", kind))),
+            },
+        };
+        Ok(Entry {
+            cycle: self.cycle,
+            pc,
+            opcode,
+            changes,
+            fault,
+        })
+    }
+}
+
+impl<R: Read> Iterator for BinaryReader<R> {
+    type Item = Result<Entry, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A clean end of input can only come between entries.
+        let first = match self.input.read_u8() {
+            Ok(byte) => byte,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(err) => return Some(Err(err.to_string())),
+        };
+        Some(self.entry(first).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => format!("truncated trace: {}", err),
+            _ => format!("invalid trace: {}", err),
+        }))
+    }
+}
+
+// `chip8 trace FILE`: prints a binary trace as text.
+pub fn run(options: &Options) -> Result<(), String> {
+    let path = options.file.as_deref().ok_or("trace needs a binary trace file")?;
+    let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+    let reader = BinaryReader::new(io::BufReader::new(file)).map_err(|err| format!("{}: {}", path, err))?;
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for entry in reader {
+        let entry = entry.map_err(|err| format!("{}: {}", path, err))?;
+        writeln!(out, "{}", entry).map_err(|err| err.to_string())?;
+    }
+    out.flush().map_err(|err| err.to_string())
+}
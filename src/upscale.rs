@@ -0,0 +1,153 @@
+use crate::palette::Color;
+
+pub const DEFAULT_SCANLINE_SCALE: usize = 3;
+pub const DEFAULT_GRID_SCALE: usize = 4;
+// Largest upscaling factor anywhere, which keeps a SUPER-CHIP screen under
+// 2048 pixels wide.
+pub const MAX_SCALE: usize = 16;
+
+// How much of the original brightness darkened lines and grid cells keep, out of 256.
+const SCANLINE_LEVEL: u16 = 128;
+const GRID_LEVEL: u16 = 192;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    // Native resolution, stretched by the GPU.
+    None,
+    Nearest(usize),
+    Scale2x,
+    Scale3x,
+    Scanlines(usize),
+    Grid(usize),
+}
+
+impl Filter {
+    // `none`, `nearest:N`, `scale2x`, `scale3x`, `scanlines[:N]` or `grid[:N]`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (name, scale) = match text.split_once(':') {
+            Some((name, scale)) => match scale.parse() {
+                Ok(scale) if (2..=MAX_SCALE).contains(&scale) => (name, Some(scale)),
+                _ => return Err(format!("invalid filter scale '{}', expected 2 to {}", scale, MAX_SCALE)),
+            },
+            None => (text, None),
+        };
+        match (name, scale) {
+            ("none", None) => Ok(Filter::None),
+            ("nearest", Some(scale)) => Ok(Filter::Nearest(scale)),
+            ("scale2x", None) => Ok(Filter::Scale2x),
+            ("scale3x", None) => Ok(Filter::Scale3x),
+            ("scanlines", scale) => Ok(Filter::Scanlines(scale.unwrap_or(DEFAULT_SCANLINE_SCALE))),
+            ("grid", scale) => Ok(Filter::Grid(scale.unwrap_or(DEFAULT_GRID_SCALE))),
+            _ => Err(format!(
+                "unknown filter '{}', expected none, nearest:N, scale2x, scale3x, scanlines[:N] or grid[:N]",
+                text
+            )),
+        }
+    }
+
+    // Integer factor between the CHIP-8 screen and the output frame.
+    pub fn scale(&self) -> usize {
+        match *self {
+            Filter::None => 1,
+            Filter::Scale2x => 2,
+            Filter::Scale3x => 3,
+            Filter::Nearest(scale) | Filter::Scanlines(scale) | Filter::Grid(scale) => scale,
+        }
+    }
+
+    // Upscales the RGBA frame `src` of `width` x `height` pixels into `dst`,
+    // which must be `scale()` times larger in both directions.
+    pub fn apply(&self, src: &[u8], width: usize, height: usize, dst: &mut [u8]) {
+        let scale = self.scale();
+        debug_assert_eq!(src.len(), 4 * width * height);
+        debug_assert_eq!(dst.len(), src.len() * scale * scale);
+        let image = Image { src, width, height };
+        let out_width = width * scale;
+
+        for y in 0..height {
+            for x in 0..width {
+                image.block(*self, x, y, |sy, sx, color| {
+                    let index = 4 * ((y * scale + sy) * out_width + x * scale + sx);
+                    dst[index..index + 4].copy_from_slice(&color);
+                });
+            }
+        }
+    }
+}
+
+struct Image<'a> {
+    src: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+impl Image<'_> {
+    // Neighbours outside the frame repeat the edge pixel.
+    fn at(&self, x: isize, y: isize) -> Color {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        let index = 4 * (y * self.width + x);
+        [self.src[index], self.src[index + 1], self.src[index + 2], self.src[index + 3]]
+    }
+
+    // Passes every output pixel for the source pixel at (x, y) to `out` as
+    // (row, column, colour).
+    fn block(&self, filter: Filter, x: usize, y: usize, mut out: impl FnMut(usize, usize, Color)) {
+        let scale = filter.scale();
+        let (x, y) = (x as isize, y as isize);
+        let e = self.at(x, y);
+        match filter {
+            Filter::Scale2x => {
+                let (b, d, f, h) = (self.at(x, y - 1), self.at(x - 1, y), self.at(x + 1, y), self.at(x, y + 1));
+                let blocky = b == h || d == f;
+                let pick = |cond: bool, c: Color| if cond && !blocky { c } else { e };
+                out(0, 0, pick(d == b, d));
+                out(0, 1, pick(b == f, f));
+                out(1, 0, pick(d == h, d));
+                out(1, 1, pick(h == f, f));
+            }
+            Filter::Scale3x => {
+                let (a, b, c) = (self.at(x - 1, y - 1), self.at(x, y - 1), self.at(x + 1, y - 1));
+                let (d, f) = (self.at(x - 1, y), self.at(x + 1, y));
+                let (g, h, i) = (self.at(x - 1, y + 1), self.at(x, y + 1), self.at(x + 1, y + 1));
+                if b != h && d != f {
+                    let pick = |cond: bool, c: Color| if cond { c } else { e };
+                    out(0, 0, pick(d == b, d));
+                    out(0, 1, pick((d == b && e != c) || (b == f && e != a), b));
+                    out(0, 2, pick(b == f, f));
+                    out(1, 0, pick((d == b && e != g) || (d == h && e != a), d));
+                    out(1, 1, e);
+                    out(1, 2, pick((b == f && e != i) || (h == f && e != c), f));
+                    out(2, 0, pick(d == h, d));
+                    out(2, 1, pick((d == h && e != i) || (h == f && e != g), h));
+                    out(2, 2, pick(h == f, f));
+                } else {
+                    for sy in 0..3 {
+                        for sx in 0..3 {
+                            out(sy, sx, e);
+                        }
+                    }
+                }
+            }
+            _ => {
+                let last = scale - 1;
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        let color = match filter {
+                            Filter::Scanlines(_) if sy == last => darken(e, SCANLINE_LEVEL),
+                            Filter::Grid(_) if sy == last || sx == last => darken(e, GRID_LEVEL),
+                            _ => e,
+                        };
+                        out(sy, sx, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn darken(color: Color, level: u16) -> Color {
+    let [r, g, b, a] = color;
+    let scale = |c: u8| ((c as u16 * level) >> 8) as u8;
+    [scale(r), scale(g), scale(b), a]
+}
@@ -0,0 +1,293 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::processor::RAM_SZ;
+
+// One thing an instruction can read or write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    Ram(u16),
+    V(u8),
+    I,
+    Dt,
+    St,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Ram(addr) => write!(f, "{:#05X}", addr),
+            Location::V(x) => write!(f, "V{:X}", x),
+            Location::I => write!(f, "I"),
+            Location::Dt => write!(f, "DT"),
+            Location::St => write!(f, "ST"),
+        }
+    }
+}
+
+impl Location {
+    // I is 12 bits worth of address, everything else a byte.
+    fn value(&self, value: u16) -> String {
+        match self {
+            Location::I => format!("{:03X}", value),
+            _ => format!("{:02X}", value),
+        }
+    }
+}
+
+// What a watchpoint covers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Ram(RangeInclusive<u16>),
+    Register(Location),
+}
+
+impl Target {
+    // `V3`, `I`, `DT`, `ST`, an address or a range like `300-30F`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let register = match text.to_ascii_uppercase().as_str() {
+            "I" => Some(Location::I),
+            "DT" => Some(Location::Dt),
+            "ST" => Some(Location::St),
+            name => name
+                .strip_prefix('V')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .map(Location::V),
+        };
+        if let Some(register) = register {
+            return Ok(Target::Register(register));
+        }
+        let addr = |part: &str| match parse_hex(part) {
+            Ok(addr) if (addr as usize) < RAM_SZ => Ok(addr),
+            Ok(_) => Err(format!("watch target '{}' is past the end of RAM", text)),
+            Err(_) => Err(format!("invalid watch target '{}'", text)),
+        };
+        let (start, end) = match text.split_once('-') {
+            Some((start, end)) => (addr(start)?, addr(end)?),
+            None => (addr(text)?, addr(text)?),
+        };
+        if start > end {
+            return Err(format!("watch range '{}' ends before it starts", text));
+        }
+        Ok(Target::Ram(start..=end))
+    }
+
+    fn covers(&self, location: Location) -> bool {
+        match (self, location) {
+            (Target::Ram(range), Location::Ram(addr)) => range.contains(&addr),
+            (Target::Register(register), location) => *register == location,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Ram(range) if range.start() == range.end() => write!(f, "{:#05X}", range.start()),
+            Target::Ram(range) => write!(f, "{:#05X}-{:#05X}", range.start(), range.end()),
+            Target::Register(register) => write!(f, "{}", register),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// Which accesses a watchpoint fires on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    Read,
+    Write,
+    // Reads and writes.
+    Access,
+    // Writes that leave a different value.
+    Change,
+}
+
+impl Trigger {
+    fn name(&self) -> &'static str {
+        match self {
+            Trigger::Read => "read",
+            Trigger::Write => "write",
+            Trigger::Access => "access",
+            Trigger::Change => "change",
+        }
+    }
+
+    fn fires(&self, access: Access, old: u16, new: u16) -> bool {
+        match self {
+            Trigger::Read => access == Access::Read,
+            Trigger::Write => access == Access::Write,
+            Trigger::Access => true,
+            Trigger::Change => access == Access::Write && old != new,
+        }
+    }
+}
+
+// What to do on a hit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Break,
+    Log,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub target: Target,
+    pub trigger: Trigger,
+    // Only fire when the value was this before the access.
+    pub from: Option<u16>,
+    // Only fire when the value is this after the access.
+    pub to: Option<u16>,
+    pub action: Action,
+}
+
+impl Watchpoint {
+    // Breaks on `trigger` accesses to `target`, whatever the values.
+    pub fn new(target: Target, trigger: Trigger) -> Self {
+        Self {
+            target,
+            trigger,
+            from: None,
+            to: None,
+            action: Action::Break,
+        }
+    }
+
+    // `TARGET [read|write|access|change] [from VALUE] [to VALUE] [log]`,
+    // values in hex. Without a trigger it fires on changes.
+    pub fn parse(words: &[&str]) -> Result<Self, String> {
+        let [target, rest @ ..] = words else {
+            return Err(String::from("missing watch target"));
+        };
+        let mut watchpoint = Watchpoint::new(Target::parse(target)?, Trigger::Change);
+        let mut words = rest.iter();
+        while let Some(&word) = words.next() {
+            let mut value = || {
+                let text = words.next().ok_or_else(|| format!("missing value after '{}'", word))?;
+                parse_hex(text).map_err(|_| format!("invalid value '{}'", text))
+            };
+            match word {
+                "read" => watchpoint.trigger = Trigger::Read,
+                "write" => watchpoint.trigger = Trigger::Write,
+                "access" => watchpoint.trigger = Trigger::Access,
+                "change" => watchpoint.trigger = Trigger::Change,
+                "from" => watchpoint.from = Some(value()?),
+                "to" => watchpoint.to = Some(value()?),
+                "log" => watchpoint.action = Action::Log,
+                _ => return Err(format!("unexpected '{}' in watchpoint", word)),
+            }
+        }
+        Ok(watchpoint)
+    }
+
+    fn fires(&self, location: Location, access: Access, old: u16, new: u16) -> bool {
+        self.target.covers(location)
+            && self.trigger.fires(access, old, new)
+            && self.from.is_none_or(|from| from == old)
+            && self.to.is_none_or(|to| to == new)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.trigger.name(), self.target)?;
+        if let Some(from) = self.from {
+            write!(f, " from {:02X}", from)?;
+        }
+        if let Some(to) = self.to {
+            write!(f, " to {:02X}", to)?;
+        }
+        if self.action == Action::Log {
+            write!(f, ", log")?;
+        }
+        Ok(())
+    }
+}
+
+// A watchpoint firing, by the instruction at `pc`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hit {
+    pub id: u32,
+    pub action: Action,
+    pub location: Location,
+    pub access: Access,
+    pub old: u16,
+    pub new: u16,
+    pub pc: u16,
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let location = self.location;
+        write!(f, "Watchpoint {}: ", self.id)?;
+        match self.access {
+            Access::Read => write!(f, "{} read {}", location, location.value(self.new))?,
+            Access::Write => write!(f, "{} {} -> {}", location, location.value(self.old), location.value(self.new))?,
+        }
+        write!(f, " at {:#05X}", self.pc)
+    }
+}
+
+// The watchpoints of a processor, and the hits since they were last taken.
+#[derive(Clone, Debug, Default)]
+pub struct Watches {
+    points: Vec<(u32, Watchpoint)>,
+    next_id: u32,
+    hits: Vec<Hit>,
+}
+
+impl Watches {
+    // Returns the id to remove it by, counting from 1.
+    pub fn add(&mut self, watchpoint: Watchpoint) -> u32 {
+        self.next_id += 1;
+        self.points.push((self.next_id, watchpoint));
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<Watchpoint> {
+        let index = self.points.iter().position(|(at, _)| *at == id)?;
+        Some(self.points.remove(index).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(u32, Watchpoint)> {
+        self.points.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // Called by the processor for every access an instruction makes while
+    // there are watchpoints.
+    pub fn check(&mut self, location: Location, access: Access, old: u16, new: u16, pc: u16) {
+        for (id, watchpoint) in &self.points {
+            if watchpoint.fires(location, access, old, new) {
+                self.hits.push(Hit {
+                    id: *id,
+                    action: watchpoint.action,
+                    location,
+                    access,
+                    old,
+                    new,
+                    pc,
+                });
+            }
+        }
+    }
+
+    // The hits so far, oldest first, and forgets them.
+    pub fn take_hits(&mut self) -> Vec<Hit> {
+        std::mem::take(&mut self.hits)
+    }
+}
+
+// Hex, with or without 0x.
+fn parse_hex(text: &str) -> Result<u16, std::num::ParseIntError> {
+    u16::from_str_radix(text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text), 16)
+}
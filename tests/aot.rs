@@ -0,0 +1,37 @@
+// Needs the recompiled test ROM built in:
+//   cargo run --release -- recompile roms/test_opcode.ch8 --out /tmp/test_opcode.rs
+//   CHIP8_AOT_MODULE=/tmp/test_opcode.rs cargo test --features aot --test aot
+#![cfg(feature = "aot")]
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::{HEIGHT, WIDTH};
+
+const FRAMES: usize = 600;
+
+fn machine() -> CHIPMachine {
+    let mut chip8 = CHIPMachine::new(WIDTH, HEIGHT);
+    chip8.load_rom(String::from("roms/test_opcode.ch8")).unwrap();
+    chip8
+}
+
+#[test]
+fn recompiled_blocks_match_the_interpreter_every_frame() {
+    let mut recompiled = machine();
+    assert!(recompiled.aot(), "built from a module for another ROM");
+    let mut interpreted = machine();
+    interpreted.disable_aot();
+
+    for frame in 0..FRAMES {
+        recompiled.run_frame();
+        interpreted.run_frame();
+        let (a, b) = (recompiled.cpu(), interpreted.cpu());
+        assert_eq!(
+            (a.pc, a.i, a.v, a.sp, a.stack, a.delay_timer, a.sound_timer),
+            (b.pc, b.i, b.v, b.sp, b.stack, b.delay_timer, b.sound_timer),
+            "registers differ after frame {}",
+            frame
+        );
+        assert!(a.display == b.display, "screens differ after frame {}", frame);
+        assert!(a.ram == b.ram, "RAM differs after frame {}", frame);
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs;
+
+use chip8::batch::{self, Stop};
+use chip8::options::{Command, Options};
+
+// 0x200: V0 = 5, then jump to itself.
+const HALT_ROM: [u8; 4] = [0x60, 0x05, 0x12, 0x02];
+
+// 0x200: V0 = random, then loop forever.
+const RANDOM_ROM: [u8; 6] = [0xC0, 0xFF, 0x70, 0x00, 0x12, 0x02];
+
+// 0x200: wait for a key into V0, then jump to itself.
+const KEY_ROM: [u8; 4] = [0xF0, 0x0A, 0x12, 0x02];
+
+fn options(args: &[&str]) -> Result<Options, String> {
+    let args = ["chip8"].iter().chain(args).map(|arg| arg.to_string());
+    Options::parse(args)
+}
+
+// A directory holding the ROMs, the key script and `manifest`.
+fn manifest(test: &str, manifest: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("chip8-batch-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("halt.ch8"), HALT_ROM).unwrap();
+    fs::write(dir.join("random.ch8"), RANDOM_ROM).unwrap();
+    fs::write(dir.join("key.ch8"), KEY_ROM).unwrap();
+    fs::write(dir.join("key.keys"), "# frame key action\n2 5 down\n4 5 up\n").unwrap();
+    let path = dir.join("batch.toml");
+    fs::write(&path, manifest).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn batch_takes_a_manifest_and_a_thread_count() {
+    let parsed = options(&["batch", "runs.toml", "--threads", "4", "--out", "report.txt"]).unwrap();
+    assert_eq!(parsed.command, Command::Batch);
+    assert_eq!(parsed.file.as_deref(), Some("runs.toml"));
+    assert_eq!(parsed.threads, Some(4));
+    assert_eq!(parsed.out.as_deref(), Some("report.txt"));
+    assert!(options(&["batch", "runs.toml", "--threads", "0"]).is_err());
+}
+
+#[test]
+fn manifest_entries_fall_back_to_the_top_level_and_file_names() {
+    let path = manifest(
+        "defaults",
+        "frames = 30\n\n[[machine]]\nrom = \"halt.ch8\"\n\n[[machine]]\nname = \"short\"\nrom = \"random.ch8\"\nframes = 5\n",
+    );
+    let jobs = batch::load(&path, &options(&["batch"]).unwrap()).unwrap();
+    let names: Vec<(&str, usize)> = jobs.iter().map(|job| (job.name.as_str(), job.frames)).collect();
+    assert_eq!(names, [("halt.ch8", 30), ("short", 5)]);
+}
+
+#[test]
+fn missing_roms_are_reported() {
+    let path = manifest("missing", "[[machine]]\nrom = \"nowhere.ch8\"\n");
+    let err = batch::load(&path, &options(&["batch"]).unwrap()).err().unwrap();
+    assert!(err.ends_with("nowhere.ch8: no such ROM"), "{}", err);
+}
+
+#[test]
+fn outcomes_come_back_in_manifest_order() {
+    let mut text = String::from("frames = 10\n");
+    for seed in 0..8 {
+        text += &format!("\n[[machine]]\nname = \"random{}\"\nrom = \"random.ch8\"\nseed = {}\n", seed, seed % 2);
+        text += &format!("\n[[machine]]\nname = \"halt{}\"\nrom = \"halt.ch8\"\n", seed);
+    }
+    let path = manifest("order", &text);
+    let outcomes = batch::run(batch::load(&path, &options(&["batch"]).unwrap()).unwrap(), 4);
+    assert_eq!(outcomes.len(), 16);
+    for (seed, pair) in outcomes.chunks(2).enumerate() {
+        assert_eq!(pair[0].name, format!("random{}", seed));
+        assert_eq!(pair[0].stop, Stop::Finished);
+        assert_eq!(pair[0].frames, 10);
+        // The same seed draws the same number.
+        assert_eq!(pair[0].v[0], outcomes[seed % 2 * 2].v[0]);
+        assert_eq!(pair[1].name, format!("halt{}", seed));
+        assert_eq!(pair[1].stop, Stop::Halted(0x202));
+        assert_eq!((pair[1].frames, pair[1].v[0]), (1, 5));
+    }
+}
+
+#[test]
+fn input_scripts_press_keys() {
+    let path = manifest("input", "[[machine]]\nrom = \"key.ch8\"\ninput = \"key.keys\"\nframes = 10\n");
+    let outcomes = batch::run(batch::load(&path, &options(&["batch"]).unwrap()).unwrap(), 1);
+    assert_eq!(outcomes[0].v[0], 5);
+    assert_eq!(outcomes[0].stop, Stop::Halted(0x202));
+}
+
+#[test]
+fn report_has_a_line_per_machine_then_the_details() {
+    let path = manifest("report", "[[machine]]\nname = \"halt\"\nrom = \"halt.ch8\"\nframes = 3\n");
+    let report = batch::run_manifest(&options(&["batch", &path, "--threads", "2"]).unwrap()).unwrap();
+    let first = report.lines().next().unwrap();
+    assert!(first.starts_with("halt       1 frames  screen "), "{}", first);
+    assert!(first.ends_with("halted at 0x202"), "{}", first);
+    assert!(report.contains("\n== halt\nPC 0x202  I 0x000  SP 0  DT 00  ST 00\nV0 05  V1 00"), "{}", report);
+}
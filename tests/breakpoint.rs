@@ -0,0 +1,95 @@
+mod common;
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::debugger::Debugger;
+use chip8::expr::{Expr, Message};
+
+// 0x200: V0 = 0.
+// 0x202: I = the font glyph for V0, draw it twice at (V0, V0) so the second
+// one collides, V0 += 1, then back to 0x202.
+const ROM: [u8; 12] = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0x70, 0x01, 0x12, 0x02];
+
+fn machine() -> (CHIPMachine, Debugger) {
+    (common::machine(&ROM), Debugger::default())
+}
+
+fn run_until_stopped(debugger: &mut Debugger, chip8: &mut CHIPMachine) -> String {
+    debugger.command(chip8, "continue");
+    common::run_until_stopped(debugger, chip8)
+}
+
+#[test]
+fn expressions_bind_like_rust() {
+    let (mut chip8, _) = machine();
+    chip8.step();
+    chip8.step();
+    let eval = |text: &str| Expr::parse(text).and_then(|expr| expr.eval(chip8.cpu(), 4));
+    assert_eq!(eval("opcode & 0xF000 == 0xD000"), Ok(1));
+    assert_eq!(eval("1 + 2 * 3 == 7 && !(hit_count < 4)"), Ok(1));
+    assert_eq!(eval("i == 0x050 || ram[i] == 0xF0"), Ok(1));
+    assert_eq!(eval("-PC >> 4"), Ok(-0x21));
+    assert_eq!(eval("1 / v0"), Err(String::from("division by zero")));
+    assert_eq!(eval("5 % v0"), Err(String::from("division by zero")));
+    assert_eq!(eval("(-0x7FFFFFFFFFFFFFFF - 1) / -1 == -0x7FFFFFFFFFFFFFFF - 1"), Ok(1));
+    assert_eq!(eval("(-0x7FFFFFFFFFFFFFFF - 1) % -1"), Ok(0));
+    assert_eq!(eval("ram[0x1000]"), Err(String::from("0x1000 is outside RAM")));
+    assert_eq!(eval("vg"), Err(String::from("unknown name 'vg'")));
+    assert_eq!(eval("(v0"), Err(String::from("expected ')'")));
+
+    // Deep nesting is refused rather than overflowing the stack.
+    let nested = |open: &str, close: &str, count: usize| format!("{}1{}", open.repeat(count), close.repeat(count));
+    assert_eq!(eval(&nested("(", ")", 30)), Ok(1));
+    let too_deep = Err(String::from("expression nested too deeply"));
+    assert_eq!(eval(&nested("(", ")", 5000)), too_deep);
+    assert_eq!(eval(&nested("!", "", 5000)), too_deep);
+    assert_eq!(eval(&nested("", "+1", 5000)), too_deep);
+
+    let message = Message::parse("at {pc} drawing {opcode & 0xF}").unwrap();
+    assert_eq!(message.eval(chip8.cpu(), 0), Ok(String::from("at 0x204 drawing 0x5")));
+    assert!(Message::parse("{v0").is_err());
+}
+
+#[test]
+fn conditions_and_hit_counts() {
+    let (mut chip8, mut debugger) = machine();
+    assert_eq!(debugger.command(&mut chip8, "break op 7x01 if hit_count == 3"), "Breakpoint at op 7x01 if hit_count == 3");
+    assert_eq!(run_until_stopped(&mut debugger, &mut chip8), "Breakpoint at op 7x01\n0x208  7001");
+    assert_eq!(chip8.cpu().v[0], 2);
+    assert_eq!(debugger.command(&mut chip8, "breakpoints"), "op 7x01 if hit_count == 3  (3 hits)");
+
+    debugger.command(&mut chip8, "condition op 7x01 v0 == 5");
+    assert_eq!(run_until_stopped(&mut debugger, &mut chip8), "Breakpoint at op 7x01\n0x208  7001");
+    assert_eq!(debugger.command(&mut chip8, "print v0 + 0x10"), "0x15 (21)");
+    assert_eq!(debugger.command(&mut chip8, "delete op 7x01"), "Deleted breakpoint at op 7x01 if v0 == 5");
+}
+
+#[test]
+fn collisions_and_classes() {
+    let (mut chip8, mut debugger) = machine();
+    debugger.command(&mut chip8, "break collision");
+    assert_eq!(run_until_stopped(&mut debugger, &mut chip8), "Breakpoint at collision\n0x208  7001");
+    assert_eq!(chip8.cpu().v[0xF], 1);
+
+    debugger.command(&mut chip8, "delete collision");
+    debugger.command(&mut chip8, "break class draw");
+    assert_eq!(run_until_stopped(&mut debugger, &mut chip8), "Breakpoint at class draw\n0x204  D005");
+    assert_eq!(debugger.command(&mut chip8, "break op Fx0G"), "Error: invalid opcode pattern 'Fx0G', expected e.g. Fx0A or Dxyn");
+}
+
+#[test]
+fn log_points_print_and_carry_on() {
+    let (mut chip8, mut debugger) = machine();
+    debugger.command(&mut chip8, "log 208 V0 {v0} VF {vf}");
+    debugger.command(&mut chip8, "break 20A if v0 == 2");
+    assert_eq!(
+        run_until_stopped(&mut debugger, &mut chip8),
+        "V0 0x0 VF 0x1\nV0 0x1 VF 0x1\nBreakpoint at 0x20A\n0x20A  1202"
+    );
+
+    // A condition that can't be evaluated stops with the error.
+    debugger.command(&mut chip8, "break 204 if 1 / (v0 - 2)");
+    assert_eq!(
+        run_until_stopped(&mut debugger, &mut chip8),
+        "Error in breakpoint at 0x204: division by zero\nBreakpoint at 0x204\n0x204  D005"
+    );
+}
@@ -0,0 +1,25 @@
+use std::process::Command;
+
+fn chip8(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8")).args(args).output().unwrap();
+    (output.status.code(), String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+#[test]
+fn missing_roms_are_errors_not_panics() {
+    let rom = std::env::temp_dir().join(format!("chip8-missing-{}.ch8", std::process::id()));
+    let rom = rom.to_str().unwrap();
+    let commands: [&[&str]; 5] = [
+        &["test", rom, "--hash", "0"],
+        &["screenshot", rom, "--out", "/dev/null"],
+        &["record", rom, "--out", "/dev/null"],
+        &["bench", rom, "--frames", "1"],
+        &["profile", rom, "--frames", "1"],
+    ];
+    for args in commands {
+        let (code, stderr) = chip8(args);
+        assert_eq!(code, Some(2), "{:?}: {}", args, stderr);
+        assert!(stderr.starts_with(&format!("{}: ", rom)), "{:?}: {}", args, stderr);
+        assert!(!stderr.contains("panicked"), "{:?}: {}", args, stderr);
+    }
+}
@@ -0,0 +1,29 @@
+// Machines and ROMs shared by the debugging tests. Each test binary only
+// uses some of them.
+#![allow(dead_code)]
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::debugger::Debugger;
+use chip8::{HEIGHT, WIDTH};
+
+// 0x200: V0 = 1, call 0x20A, I = 0x300, store V0 at I, then loop forever.
+// 0x20A: V1 = 5, return.
+pub const SUBROUTINE_ROM: [u8; 14] = [
+    0x60, 0x01, 0x22, 0x0A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x08, 0x61, 0x05, 0x00, 0xEE,
+];
+
+pub fn machine(rom: &[u8]) -> CHIPMachine {
+    let mut chip8 = CHIPMachine::new(WIDTH, HEIGHT);
+    chip8.load_bytes(rom.to_vec());
+    chip8
+}
+
+// Runs frames the way the emulation thread does until the debugger stops.
+pub fn run_until_stopped(debugger: &mut Debugger, chip8: &mut CHIPMachine) -> String {
+    for _ in 0..10 {
+        if let Some(stopped) = debugger.run_frame(chip8) {
+            return stopped;
+        }
+    }
+    panic!("still running at {:#05X}", chip8.cpu().pc);
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use chip8::chip_machine::Status;
+use chip8::conformance;
+
+const CASES: &str = "tests/conformance/cases.json";
+
+// 0x200: jump to 0x204 over V0 = 1, V1 = 2, call 0x20C, V2 = 3, then loop
+// forever. 0x20C: V3 = 4, return.
+const JUMP_ROM: [u8; 16] = [
+    0x12, 0x04, 0x60, 0x01, 0x61, 0x02, 0x22, 0x0C, 0x62, 0x03, 0x12, 0x0A, 0x63, 0x04, 0x00, 0xEE,
+];
+
+#[test]
+fn every_conformance_case_passes() {
+    let cases = conformance::load(CASES).unwrap();
+    assert!(!cases.is_empty());
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|case| case.run().err().map(|err| format!("{} ({}):\n{}", case.name, case.opcode, err)))
+        .collect();
+    assert!(failures.is_empty(), "{} cases failed:\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn mismatches_are_reported() {
+    let mut case = conformance::load(CASES).unwrap().remove(0);
+    case.expected.pc = Some(0x400);
+    let err = case.run().unwrap_err();
+    assert!(err.contains("PC: expected 0x0400, got 0x0202"), "{}", err);
+}
+
+#[test]
+fn jumps_and_calls_run_the_instruction_they_land_on() {
+    let mut chip8 = common::machine(&JUMP_ROM);
+    chip8.run_frame();
+    assert_eq!(chip8.status(), Status::Halted(0x20A));
+    assert_eq!(chip8.cpu().v[..4], [0, 2, 3, 4]);
+}
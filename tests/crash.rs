@@ -0,0 +1,76 @@
+mod common;
+
+use std::fs;
+
+use chip8::chip_machine::{CHIPMachine, Status};
+use chip8::crash;
+use chip8::options::Options;
+use chip8::processor::Fault;
+use chip8::trace::Tracer;
+
+// 0x200: V0 = 1, call 0x206, then loop forever.
+// 0x206: I = 0x300, then an opcode that doesn't exist.
+const ROM: [u8; 10] = [0x60, 0x01, 0x22, 0x06, 0x12, 0x04, 0xA3, 0x00, 0x81, 0x28];
+
+fn crashed(ring: Option<usize>) -> CHIPMachine {
+    let mut chip8 = common::machine(&ROM);
+    if let Some(capacity) = ring {
+        chip8.set_tracer(Tracer::ring(capacity));
+    }
+    chip8.run_frame();
+    chip8
+}
+
+#[test]
+fn unknown_opcodes_fault() {
+    let chip8 = crashed(None);
+    assert_eq!(chip8.status(), Status::Faulted(Fault::UnknownOpcode(0x8128)));
+    // PC stays on the instruction that couldn't run.
+    assert_eq!(chip8.cpu().pc, 0x208);
+}
+
+#[test]
+fn report_has_the_call_stack_and_trace() {
+    let report = crash::report(&crashed(Some(3)));
+    let lines: Vec<&str> = report.lines().collect();
+    assert!(lines.contains(&"Fault   unknown opcode 8128"), "{}", report);
+    assert!(lines.contains(&"Quirks  modern"), "{}", report);
+
+    let stack = lines.iter().position(|&line| line == "Call stack").unwrap();
+    assert_eq!(lines[stack + 1..stack + 3], ["#0  0x208  8128  DW 0x8128", "#1  0x202  2206  CALL 0x206"]);
+
+    let trace = lines.iter().position(|&line| line == "Last 3 instructions").unwrap();
+    assert_eq!(lines[trace + 3], "         3 0x208 8128  DW 0x8128            fault: unknown opcode 8128");
+
+    // PC is marked in the dump around it.
+    let ram = lines.iter().position(|&line| line == "RAM around PC").unwrap();
+    assert!(lines[ram + 3].starts_with("200: 60 01 22 06 12 04 A3 00[81 28"), "{}", report);
+}
+
+#[test]
+fn report_without_a_ring_says_how_to_get_one() {
+    let report = crash::report(&crashed(None));
+    assert!(report.contains("run with --crash-ring N"), "{}", report);
+}
+
+#[test]
+fn crash_ring_is_refused_with_a_trace() {
+    let parse = |args: &str| Options::parse(args.split_whitespace().map(String::from));
+    assert!(parse("chip8 rom.ch8 --crash-ring 8").is_ok());
+    assert_eq!(
+        parse("chip8 rom.ch8 --trace out.txt --crash-ring 8").err(),
+        Some(String::from("--crash-ring can't be combined with --trace"))
+    );
+}
+
+#[test]
+fn save_writes_the_report_and_screen() {
+    let dir = std::env::temp_dir().join(format!("chip8-crash-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = crash::save(&crashed(None), &dir, 2).unwrap();
+    let report = fs::read_to_string(&path).unwrap();
+    let png = path.with_extension("png");
+    assert!(report.trim_end().ends_with(png.file_name().unwrap().to_str().unwrap()));
+    assert!(fs::read(&png).unwrap().starts_with(b"\x89PNG"));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use chip8::dap;
+use chip8::options::Options;
+use serde_json::{json, Value};
+
+// sub.ch8 is sub.8o assembled, sub.map maps it back to the source.
+const ROM: &str = "tests/dap/sub.ch8";
+const SOURCE: &str = "tests/dap/sub.8o";
+const MAP: &str = "tests/dap/sub.map";
+const LABELS: &str = "tests/dap/sub.labels";
+
+// A scripted stand-in for the editor.
+struct Client {
+    output: TcpStream,
+    input: BufReader<TcpStream>,
+    seq: u64,
+    // Events that arrived while waiting for something else.
+    events: VecDeque<Value>,
+    server: JoinHandle<()>,
+}
+
+impl Client {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let output = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        output.set_nodelay(true).unwrap();
+        let server = thread::spawn(move || {
+            dap::serve(stream.try_clone().unwrap(), stream, &Options::default()).unwrap();
+        });
+        let input = BufReader::new(output.try_clone().unwrap());
+        Self {
+            output,
+            input,
+            seq: 0,
+            events: VecDeque::new(),
+            server,
+        }
+    }
+
+    fn read(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.input.read_line(&mut line).unwrap();
+            match line.trim_end().strip_prefix("Content-Length: ") {
+                Some(value) => length = value.parse().unwrap(),
+                None if line.trim_end().is_empty() => break,
+                None => panic!("unexpected header {:?}", line),
+            }
+        }
+        let mut body = vec![0; length];
+        self.input.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    // Sends a request and returns the response.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let body = json!({"seq": self.seq, "type": "request", "command": command, "arguments": arguments}).to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        loop {
+            let message = self.read();
+            if message["type"] == "response" {
+                assert_eq!(message["request_seq"], self.seq);
+                return message;
+            }
+            self.events.push_back(message);
+        }
+    }
+
+    // Like `request`, for requests that must succeed, returning the body.
+    fn ok(&mut self, command: &str, arguments: Value) -> Value {
+        let response = self.request(command, arguments);
+        assert_eq!(response["success"], true, "{}", response);
+        response["body"].clone()
+    }
+
+    fn event(&mut self, name: &str) -> Value {
+        let event = match self.events.pop_front() {
+            Some(event) => event,
+            None => self.read(),
+        };
+        assert_eq!(event["event"], name, "{}", event);
+        event["body"].clone()
+    }
+
+    fn stopped(&mut self) -> String {
+        self.event("stopped")["reason"].as_str().unwrap().to_string()
+    }
+
+    fn launch(&mut self, stop_on_entry: bool) {
+        let capabilities = self.ok("initialize", json!({"adapterID": "chip8"}));
+        assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+        self.ok(
+            "launch",
+            json!({"program": ROM, "sourceMap": MAP, "labels": LABELS, "stopOnEntry": stop_on_entry}),
+        );
+        self.event("initialized");
+    }
+
+    // (name, line) of every frame.
+    fn stack(&mut self) -> Vec<(String, u64)> {
+        let body = self.ok("stackTrace", json!({"threadId": 1}));
+        let frames = body["stackFrames"].as_array().unwrap();
+        frames
+            .iter()
+            .map(|frame| (frame["name"].as_str().unwrap().to_string(), frame["line"].as_u64().unwrap()))
+            .collect()
+    }
+
+    fn variable(&mut self, reference: u64, name: &str) -> String {
+        let body = self.ok("variables", json!({"variablesReference": reference}));
+        let variables = body["variables"].as_array().unwrap();
+        let variable = variables.iter().find(|variable| variable["name"] == name).unwrap();
+        variable["value"].as_str().unwrap().to_string()
+    }
+
+    fn disconnect(mut self) {
+        self.ok("disconnect", json!({}));
+        self.server.join().unwrap();
+    }
+}
+
+#[test]
+fn breakpoints_map_through_the_source_map() {
+    let mut client = Client::start();
+    client.launch(false);
+    // Line 4 is blank, so its breakpoint moves down to line 5.
+    let body = client.ok(
+        "setBreakpoints",
+        json!({"source": {"path": SOURCE}, "breakpoints": [{"line": 4}, {"line": 11}, {"line": 30}]}),
+    );
+    let breakpoints = body["breakpoints"].as_array().unwrap();
+    let lines: Vec<(bool, u64)> = breakpoints
+        .iter()
+        .map(|breakpoint| (breakpoint["verified"].as_bool().unwrap(), breakpoint["line"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(lines, [(true, 5), (true, 11), (false, 30)]);
+
+    client.ok("configurationDone", json!({}));
+    assert_eq!(client.stopped(), "breakpoint");
+    assert_eq!(client.stack(), [(String::from("sub"), 11), (String::from("main"), 3)]);
+    let body = client.ok("stackTrace", json!({"threadId": 1}));
+    let source = &body["stackFrames"][0]["source"];
+    assert_eq!(source["path"], fs::canonicalize(SOURCE).unwrap().to_str().unwrap());
+    assert_eq!(body["stackFrames"][1]["instructionPointerReference"], "0x202");
+
+    assert_eq!(client.variable(1, "V0"), "0x01");
+    assert_eq!(client.variable(1, "PC"), "0x20A");
+    assert_eq!(client.variable(1, "SP"), "1");
+
+    client.ok("continue", json!({"threadId": 1}));
+    assert_eq!(client.stopped(), "breakpoint");
+    assert_eq!(client.stack(), [(String::from("main"), 5)]);
+    assert_eq!(client.variable(1, "V1"), "0x05");
+    client.disconnect();
+}
+
+#[test]
+fn stepping_by_line_and_instruction() {
+    let mut client = Client::start();
+    client.launch(true);
+    client.ok("configurationDone", json!({}));
+    assert_eq!(client.stopped(), "entry");
+    assert_eq!(client.stack(), [(String::from("main"), 2)]);
+
+    client.ok("next", json!({"threadId": 1}));
+    assert_eq!(client.stopped(), "step");
+    assert_eq!(client.stack(), [(String::from("main"), 3)]);
+    client.ok("next", json!({"threadId": 1}));
+    assert_eq!(client.stopped(), "step");
+    assert_eq!(client.stack(), [(String::from("main"), 5)]);
+    assert_eq!(client.variable(1, "V1"), "0x05");
+
+    client.ok("stepIn", json!({"threadId": 1, "granularity": "instruction"}));
+    assert_eq!(client.stopped(), "step");
+    assert_eq!(client.variable(1, "PC"), "0x206");
+    client.ok("stepIn", json!({"threadId": 1}));
+    assert_eq!(client.stopped(), "step");
+    assert_eq!(client.stack(), [(String::from("loop"), 8)]);
+    assert!(client.variable(2, "0x300").starts_with("01 00"));
+    client.disconnect();
+}
+
+#[test]
+fn step_in_and_out_of_a_subroutine() {
+    let mut client = Client::start();
+    client.launch(true);
+    client.ok("configurationDone", json!({}));
+    assert_eq!(client.stopped(), "entry");
+    client.ok("stepIn", json!({"threadId": 1}));
+    assert_eq!(client.stopped(), "step");
+    client.ok("stepIn", json!({"threadId": 1}));
+    assert_eq!(client.stopped(), "step");
+    assert_eq!(client.stack(), [(String::from("sub"), 11), (String::from("main"), 3)]);
+
+    client.ok("stepOut", json!({"threadId": 1}));
+    assert_eq!(client.stopped(), "step");
+    assert_eq!(client.stack(), [(String::from("main"), 5)]);
+    let response = client.request("stepOut", json!({"threadId": 1}));
+    assert_eq!((response["success"].as_bool(), response["message"].as_str()), (Some(false), Some("not in a subroutine")));
+    client.disconnect();
+}
+
+#[test]
+fn oversized_messages_end_the_session() {
+    let mut client = Client::start();
+    write!(client.output, "Content-Length: 99999999999\r\n\r\n").unwrap();
+    client.server.join().unwrap();
+}
+
+#[test]
+fn pause_captures_the_screen() {
+    let mut client = Client::start();
+    client.launch(false);
+    client.ok("configurationDone", json!({}));
+    client.ok("pause", json!({"threadId": 1}));
+    assert_eq!(client.stopped(), "pause");
+
+    let scopes = client.ok("scopes", json!({"frameId": 0}));
+    let names: Vec<&str> = scopes["scopes"].as_array().unwrap().iter().map(|scope| scope["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["Registers", "Memory", "Screen"]);
+    // A PNG starts with 0x89 "PNG".
+    assert!(client.variable(3, "screen").starts_with("data:image/png;base64,iVBORw0KGgo"));
+    client.disconnect();
+}
+
+#[test]
+fn requests_fail_before_launch() {
+    let mut client = Client::start();
+    let response = client.request("continue", json!({"threadId": 1}));
+    assert_eq!(response["success"], false);
+    assert_eq!(response["message"], "no program launched");
+    let response = client.request("launch", json!({"program": "tests/dap/missing.ch8"}));
+    assert_eq!(response["success"], false);
+    client.disconnect();
+}
+
+#[test]
+fn conditions_log_points_and_function_breakpoints() {
+    let mut client = Client::start();
+    client.launch(false);
+    let body = client.ok(
+        "setBreakpoints",
+        json!({"source": {"path": SOURCE}, "breakpoints": [
+            {"line": 11, "logMessage": "sub with V0 {v0}"},
+            {"line": 8, "condition": "ram[0x300] == 1", "hitCondition": "3"},
+            {"line": 2, "condition": "v0 =="},
+        ]}),
+    );
+    let verified: Vec<bool> = body["breakpoints"].as_array().unwrap().iter().map(|b| b["verified"].as_bool().unwrap()).collect();
+    assert_eq!(verified, [true, true, false]);
+    let body = client.ok("setFunctionBreakpoints", json!({"breakpoints": [{"name": "op Fx55"}, {"name": "nowhere"}]}));
+    assert_eq!(body["breakpoints"][0]["verified"], true);
+    assert_eq!(body["breakpoints"][1]["verified"], false);
+
+    client.ok("configurationDone", json!({}));
+    assert_eq!(client.event("output")["output"], "sub with V0 0x1\n");
+    let stopped = client.event("stopped");
+    assert_eq!((stopped["reason"].as_str(), stopped["text"].as_str()), (Some("function breakpoint"), Some("op Fx55")));
+    assert_eq!(client.stack(), [(String::from("main"), 6)]);
+
+    client.ok("continue", json!({"threadId": 1}));
+    assert_eq!(client.stopped(), "breakpoint");
+    let body = client.ok("evaluate", json!({"expression": "ram[i] + v1", "context": "repl"}));
+    assert_eq!(body["result"], "0x6 (6)");
+
+    // A function breakpoint on a label is still a function breakpoint.
+    client.ok("setBreakpoints", json!({"source": {"path": SOURCE}, "breakpoints": []}));
+    client.ok("setFunctionBreakpoints", json!({"breakpoints": [{"name": "loop"}]}));
+    client.ok("continue", json!({"threadId": 1}));
+    let stopped = client.event("stopped");
+    assert_eq!((stopped["reason"].as_str(), stopped["text"].as_str()), (Some("function breakpoint"), Some("0x208")));
+    client.disconnect();
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::debugger::Debugger;
+use common::run_until_stopped;
+
+// 0x200: V0 = 1, call 0x208, V0 += 2, then loop forever.
+// 0x208: V1 = 5, return.
+const ROM: [u8; 12] = [0x60, 0x01, 0x22, 0x08, 0x70, 0x02, 0x12, 0x06, 0x61, 0x05, 0x00, 0xEE];
+
+fn machine() -> (CHIPMachine, Debugger) {
+    (common::machine(&ROM), Debugger::default())
+}
+
+#[test]
+fn continue_stops_at_a_label_breakpoint() {
+    let (mut chip8, mut debugger) = machine();
+    debugger.command(&mut chip8, "label sub 208");
+    assert_eq!(debugger.command(&mut chip8, "break sub"), "Breakpoint at 0x208 <sub>");
+    debugger.command(&mut chip8, "continue");
+    assert_eq!(run_until_stopped(&mut debugger, &mut chip8), "Breakpoint at 0x208 <sub>\n0x208 <sub>  6105");
+    assert_eq!(debugger.command(&mut chip8, "stack"), "#0  called from 0x202");
+}
+
+#[test]
+fn next_steps_over_calls_and_finish_returns() {
+    let (mut chip8, mut debugger) = machine();
+    assert_eq!(debugger.command(&mut chip8, "step"), "0x202  2208");
+    debugger.command(&mut chip8, "next");
+    assert_eq!(run_until_stopped(&mut debugger, &mut chip8), "Stopped\n0x204  7002");
+    assert_eq!(chip8.cpu().v[1], 5);
+
+    debugger.command(&mut chip8, "set pc 202");
+    assert_eq!(debugger.command(&mut chip8, "step"), "0x208  6105");
+    debugger.command(&mut chip8, "finish");
+    assert_eq!(run_until_stopped(&mut debugger, &mut chip8), "Stopped\n0x204  7002");
+}
+
+#[test]
+fn registers_and_memory_can_be_set() {
+    let (mut chip8, mut debugger) = machine();
+    debugger.command(&mut chip8, "set vA 42");
+    debugger.command(&mut chip8, "set i 300");
+    debugger.command(&mut chip8, "poke 300 de ad");
+    assert_eq!(chip8.cpu().v[0xA], 0x42);
+    assert_eq!(debugger.command(&mut chip8, "mem 300 4"), "300:[DE AD 00 00");
+    assert_eq!(debugger.command(&mut chip8, "set v0 100"), "Error: 0x100 doesn't fit in a byte");
+}
+
+#[test]
+fn setting_pc_recovers_from_a_fault() {
+    // 0x200: V0 = 1, an unknown opcode, V0 += 2, then loop forever.
+    let mut chip8 = common::machine(&[0x60, 0x01, 0x01, 0x23, 0x70, 0x02, 0x12, 0x06]);
+    let mut debugger = Debugger::default();
+    debugger.command(&mut chip8, "continue");
+    assert_eq!(run_until_stopped(&mut debugger, &mut chip8), "Fault: unknown opcode 0123\n0x202  0123");
+    assert_eq!(debugger.command(&mut chip8, "set pc 204"), "PC = 0x204");
+    debugger.command(&mut chip8, "break 206");
+    debugger.command(&mut chip8, "continue");
+    assert_eq!(run_until_stopped(&mut debugger, &mut chip8), "Breakpoint at 0x206\n0x206  1206");
+    assert_eq!(chip8.cpu().v[0], 3);
+}
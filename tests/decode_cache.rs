@@ -0,0 +1,87 @@
+mod common;
+
+use chip8::processor::Processor;
+
+// 0x200: call 0x20E, which sets V0 = 7, then store V0 = 0x60 and V1 = 0x2A
+// over it with Fx55 so it reads 602A, and call it again.
+const FX55_ROM: [u8; 18] = [
+    0x22, 0x0E, 0x60, 0x60, 0x61, 0x2A, 0xA2, 0x0E, 0xF1, 0x55, 0x22, 0x0E, 0x12, 0x0C, 0x60, 0x07, 0x00, 0xEE,
+];
+
+// 0x200: V0 = 1, call 0x20E, which skips V1 += 1 only when V0 == 5, then
+// write the digits of 123 from its low byte on with Fx33, so it skips when
+// V0 == 1, and call it again.
+const FX33_ROM: [u8; 20] = [
+    0x60, 0x01, 0x22, 0x0E, 0x62, 0x7B, 0xA2, 0x0F, 0xF2, 0x33, 0x22, 0x0E, 0x12, 0x0C, 0x30, 0x05, 0x71, 0x01,
+    0x00, 0xEE,
+];
+
+// 0x200: V0 += 1, then loop forever.
+const LOOP_ROM: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+fn run(cpu: &mut Processor, steps: usize) {
+    for _ in 0..steps {
+        cpu.step();
+    }
+    assert!(cpu.fault.is_none(), "{:?}", cpu.fault);
+}
+
+#[test]
+fn fx55_over_a_cached_block_runs_the_new_code() {
+    let mut chip8 = common::machine(&FX55_ROM);
+    let cpu = chip8.cpu_mut();
+    run(cpu, 3);
+    assert_eq!(cpu.v[0], 7);
+    run(cpu, 8);
+    assert_eq!(cpu.v[0], 0x2A);
+}
+
+#[test]
+fn fx33_over_a_cached_block_runs_the_new_code() {
+    let mut chip8 = common::machine(&FX33_ROM);
+    let cpu = chip8.cpu_mut();
+    run(cpu, 5);
+    assert_eq!(cpu.v[1], 1);
+    run(cpu, 7);
+    assert_eq!(cpu.pc, 0x20C);
+    assert_eq!(cpu.v[1], 1);
+}
+
+#[test]
+fn host_writes_invalidate_cached_decodes() {
+    let mut chip8 = common::machine(&LOOP_ROM);
+    let cpu = chip8.cpu_mut();
+    run(cpu, 4);
+    assert_eq!(cpu.v[0], 2);
+    // V0 += 5
+    cpu.write_ram(0x201, 0x05);
+    run(cpu, 2);
+    assert_eq!(cpu.v[0], 7);
+}
+
+#[test]
+fn decode_cache_matches_the_interpreter() {
+    for rom in [&FX55_ROM[..], &FX33_ROM[..]] {
+        let mut cached = common::machine(rom).cpu().clone();
+        let mut interpreted = cached.clone();
+        for _ in 0..20 {
+            cached.step();
+            let op = interpreted.get_instruction().unwrap_or_default();
+            interpreted.tick(op);
+            assert_eq!((cached.pc, cached.v, cached.i), (interpreted.pc, interpreted.v, interpreted.i));
+        }
+        assert_eq!(cached.ram, interpreted.ram);
+    }
+}
+
+#[test]
+fn runs_stop_at_jumps_and_within_the_budget() {
+    let mut chip8 = common::machine(&LOOP_ROM);
+    let cpu = chip8.cpu_mut();
+    assert_eq!(cpu.run(10), 2);
+    assert_eq!((cpu.pc, cpu.v[0]), (0x200, 1));
+    assert_eq!(cpu.run(1), 1);
+    assert_eq!((cpu.pc, cpu.v[0]), (0x202, 2));
+    assert_eq!(cpu.run(10), 1);
+    assert_eq!(cpu.pc, 0x200);
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use chip8::emulation::{Captures, Emulation, Frame, Input};
+use winit::event::VirtualKeyCode;
+
+// 0x200: I = 0x200, draw its first byte, then jump to itself.
+const DRAW_ROM: [u8; 6] = [0xA2, 0x00, 0xD0, 0x01, 0x12, 0x04];
+
+// 0x200: wait for a key into V0, draw the pixel at 0x208 in column V0, then
+// jump to itself.
+const KEY_ROM: [u8; 9] = [0xF0, 0x0A, 0xA2, 0x08, 0xD0, 0x11, 0x12, 0x06, 0x80];
+
+// Far longer than a frame, so a slow machine doesn't make the tests flaky.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn captures(test: &str) -> Captures {
+    let dir = std::env::temp_dir().join(format!("chip8-emulation-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    Captures {
+        dir,
+        screenshot_scale: 1,
+        record_scale: 1,
+        crash_reports: false,
+    }
+}
+
+// Runs `rom`, with a message on the returned receiver for every frame published.
+fn spawn(rom: &[u8], captures: Captures, every_frame: bool) -> (Emulation, Receiver<()>) {
+    let (wake, woken) = mpsc::channel();
+    let emulation = Emulation::spawn(common::machine(rom), captures, every_frame, move || {
+        let _ = wake.send(());
+    });
+    (emulation, woken)
+}
+
+// Whether the pixel at (x, y) of the 64x32 screen is lit, going by the
+// colour of the dark pixel at (0, 31).
+fn lit(frame: &Frame, x: usize, y: usize) -> bool {
+    let scale = frame.width / 64;
+    let pixel = |x: usize, y: usize| &frame.rgba[4 * (y * scale * frame.width + x * scale)..][..4];
+    pixel(x, y) != pixel(0, 31)
+}
+
+#[test]
+fn frames_are_published_when_the_picture_changes() {
+    let (mut emulation, woken) = spawn(&DRAW_ROM, captures("changes"), false);
+    woken.recv_timeout(TIMEOUT).unwrap();
+    assert!(lit(emulation.frame(), 0, 0));
+    // The ROM halts after drawing, nothing changes from here on.
+    assert_eq!(woken.recv_timeout(Duration::from_millis(250)), Err(RecvTimeoutError::Timeout));
+    emulation.stop();
+}
+
+#[test]
+fn every_frame_is_published_when_asked_for() {
+    let (mut emulation, woken) = spawn(&DRAW_ROM, captures("every"), true);
+    for _ in 0..10 {
+        woken.recv_timeout(TIMEOUT).unwrap();
+    }
+    assert!(lit(emulation.frame(), 0, 0));
+    emulation.stop();
+}
+
+#[test]
+fn keys_reach_the_machine() {
+    let (mut emulation, woken) = spawn(&KEY_ROM, captures("keys"), false);
+    woken.recv_timeout(TIMEOUT).unwrap();
+    assert!(!lit(emulation.frame(), 5, 0));
+
+    // W is key 5. Fx0A takes it once it's released, a few frames later.
+    emulation.send(Input::Key(VirtualKeyCode::W, true));
+    thread::sleep(Duration::from_millis(100));
+    emulation.send(Input::Key(VirtualKeyCode::W, false));
+    woken.recv_timeout(TIMEOUT).unwrap();
+    assert!(lit(emulation.frame(), 5, 0));
+    emulation.stop();
+}
+
+#[test]
+fn stopping_joins_the_thread_and_saves_the_recording() {
+    let captures = captures("stop");
+    let dir = captures.dir.clone();
+    let (mut emulation, woken) = spawn(&DRAW_ROM, captures, true);
+    emulation.send(Input::ToggleRecording);
+    for _ in 0..10 {
+        woken.recv_timeout(TIMEOUT).unwrap();
+    }
+    emulation.stop();
+    // The thread dropped `wake` on its way out.
+    while woken.try_recv().is_ok() {}
+    assert_eq!(woken.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+    assert_eq!(emulation.messages().collect::<Vec<_>>(), ["Recording"]);
+
+    let gifs: Vec<PathBuf> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    assert_eq!(gifs.len(), 1, "{:?}", gifs);
+    assert_eq!(gifs[0].extension().unwrap(), "gif");
+    // Only a finished file has every frame and the trailer.
+    let mut decoder = gif::DecodeOptions::new().read_info(File::open(&gifs[0]).unwrap()).unwrap();
+    let mut frames = 0;
+    while decoder.read_next_frame().unwrap().is_some() {
+        frames += 1;
+    }
+    assert!(frames > 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
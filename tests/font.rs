@@ -0,0 +1,80 @@
+mod common;
+
+use chip8::font::{Font, FontSet, BIG_FONT_SZ, BIG_GLYPH_SZ, SMALL_FONT_SZ, SMALL_GLYPH_SZ};
+use chip8::options::Options;
+
+// 0x200: VA = 0x1B, point I at the small glyph for VA, then at the big one.
+const GLYPH_ROM: [u8; 6] = [0x6A, 0x1B, 0xFA, 0x29, 0xFA, 0x30];
+
+fn options(args: &[&str]) -> Result<Options, String> {
+    let args = ["chip8", "run", "rom.ch8"].iter().chain(args).map(|arg| arg.to_string());
+    Options::parse(args)
+}
+
+#[test]
+fn font_sets_are_picked_by_name() {
+    for set in FontSet::ALL {
+        assert_eq!(FontSet::from_name(set.name()), Some(set));
+        assert_eq!(options(&["--font", set.name()]).unwrap().font().unwrap().small, *set.glyphs());
+    }
+    assert_eq!(FontSet::from_name("VIP"), Some(FontSet::CosmacVip));
+    assert_eq!(FontSet::from_name("chip48"), None);
+    let err = options(&["--font", "chip48"]).err().unwrap();
+    assert!(err.starts_with("unknown font 'chip48', expected one of: vip, "), "{}", err);
+    assert_eq!(options(&[]).unwrap().font().unwrap().small, *FontSet::Octo.glyphs());
+}
+
+#[test]
+fn font_files_hold_small_glyphs_and_maybe_big_ones() {
+    let small = [0x11; SMALL_FONT_SZ];
+    let font = Font::from_bytes(&small).unwrap();
+    assert_eq!(font.small, small);
+    assert_eq!(font.big, Font::default().big);
+
+    let both: Vec<u8> = small.iter().copied().chain([0x22; BIG_FONT_SZ]).collect();
+    let font = Font::from_bytes(&both).unwrap();
+    assert_eq!((font.small, font.big), (small, [0x22; BIG_FONT_SZ]));
+
+    for len in [0, SMALL_FONT_SZ - 1, SMALL_FONT_SZ + 1, SMALL_FONT_SZ + BIG_FONT_SZ - 1, SMALL_FONT_SZ + BIG_FONT_SZ + 1] {
+        assert!(Font::from_bytes(&vec![0; len]).is_none(), "{} bytes", len);
+    }
+
+    let path = std::env::temp_dir().join(format!("chip8-font-{}.bin", std::process::id()));
+    std::fs::write(&path, [0; 100]).unwrap();
+    let err = Font::from_file(path.to_str().unwrap()).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(err.to_string(), "font file must be 80 or 240 bytes, got 100");
+}
+
+#[test]
+fn both_fonts_must_fit_below_the_program() {
+    let last = (0x200 - SMALL_FONT_SZ - BIG_FONT_SZ) as u16;
+    let font = Font::default().with_base(last);
+    assert!(font.fits());
+    assert_eq!(font.big_base(), last + SMALL_FONT_SZ as u16);
+    assert!(!Font::default().with_base(last + 1).fits());
+
+    assert_eq!(options(&["--font-base", "0x50"]).unwrap().font().unwrap().base, 0x50);
+    assert_eq!(options(&["--font-base", &last.to_string()]).unwrap().font().unwrap().base, last);
+    let err = options(&["--font-base", "0x111"]).unwrap().font().unwrap_err();
+    assert_eq!(err, "font base 0x111 leaves no room below 0x200");
+}
+
+#[test]
+fn fx29_and_fx30_point_at_glyphs_from_the_font_base() {
+    let font = Font::builtin(FontSet::CosmacVip).with_base(0x50);
+    let mut chip8 = common::machine(&GLYPH_ROM);
+    chip8.load_font(&font);
+
+    // Fx29 used to leave I alone. Only the low nibble of Vx picks the glyph.
+    chip8.step();
+    chip8.step();
+    let small = 0x50 + 0xB * SMALL_GLYPH_SZ;
+    assert_eq!(chip8.cpu().i as usize, small);
+    assert_eq!(chip8.cpu().ram[small..small + SMALL_GLYPH_SZ], font.small[0xB * SMALL_GLYPH_SZ..][..SMALL_GLYPH_SZ]);
+
+    chip8.step();
+    let big = 0x50 + SMALL_FONT_SZ + 0xB * BIG_GLYPH_SZ;
+    assert_eq!(chip8.cpu().i as usize, big);
+    assert_eq!(chip8.cpu().ram[big..big + BIG_GLYPH_SZ], font.big[0xB * BIG_GLYPH_SZ..][..BIG_GLYPH_SZ]);
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use chip8::framebuffer::Framebuffer;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// 0x200: VF = 1, draw the top row of the "0" glyph, copy VF to VE, draw it
+// again, then draw two rows from I = 0xFFF, which wraps around RAM.
+const VF_ROM: [u8; 16] = [0x6F, 0x01, 0xA0, 0x00, 0xD0, 0x11, 0x8E, 0xF0, 0xD0, 0x11, 0xAF, 0xFF, 0xD0, 0x12, 0x12, 0x0E];
+
+// One bool per pixel, the way the screen was kept before it was packed.
+struct Reference {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Reference {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], clip: bool) -> bool {
+        let (x, y) = (x % self.width, y % self.height);
+        let mut collision = false;
+        for (row, byte) in sprite.iter().enumerate() {
+            for bit in 0..8 {
+                if byte >> (7 - bit) & 1 == 0 {
+                    continue;
+                }
+                let (px, py) = (x + bit, y + row);
+                if clip && (px >= self.width || py >= self.height) {
+                    continue;
+                }
+                let pixel = &mut self.pixels[py % self.height * self.width + px % self.width];
+                collision |= *pixel;
+                *pixel = !*pixel;
+            }
+        }
+        collision
+    }
+}
+
+fn lit(screen: &Framebuffer) -> Vec<(usize, usize)> {
+    let width = screen.width();
+    screen.pixels().enumerate().filter(|(_, on)| *on).map(|(i, _)| (i % width, i / width)).collect()
+}
+
+#[test]
+fn sprites_wrap_around_both_edges() {
+    let mut screen = Framebuffer::new(64, 32);
+    screen.draw_sprite(62, 31, &[0b1100_0011, 0b1000_0001], false);
+    assert_eq!(lit(&screen), [(5, 0), (62, 0), (4, 31), (5, 31), (62, 31), (63, 31)]);
+}
+
+#[test]
+fn clipped_sprites_are_cut_off_at_both_edges() {
+    let mut screen = Framebuffer::new(64, 32);
+    screen.draw_sprite(62, 31, &[0b1100_0011, 0b1000_0001], true);
+    assert_eq!(lit(&screen), [(62, 31), (63, 31)]);
+}
+
+#[test]
+fn start_position_wraps_even_when_clipping() {
+    let mut screen = Framebuffer::new(64, 32);
+    screen.draw_sprite(64 + 3, 32 + 2, &[0x80], true);
+    assert_eq!(lit(&screen), [(3, 2)]);
+}
+
+#[test]
+fn collision_only_when_a_lit_pixel_goes_dark() {
+    let mut screen = Framebuffer::new(64, 32);
+    assert!(!screen.draw_sprite(0, 0, &[0xF0], false));
+    assert!(!screen.draw_sprite(4, 0, &[0xF0], false));
+    assert!(screen.draw_sprite(7, 0, &[0x80], false));
+}
+
+#[test]
+fn wrapped_pixels_collide_but_clipped_ones_dont() {
+    let mut screen = Framebuffer::new(64, 32);
+    screen.draw_sprite(0, 0, &[0x80], false);
+    assert!(!screen.draw_sprite(60, 0, &[0x0F], true));
+    assert!(screen.draw_sprite(60, 0, &[0x0F], false));
+}
+
+#[test]
+fn packed_rows_match_one_bool_per_pixel() {
+    let mut rng = StdRng::seed_from_u64(34);
+    for (width, height) in [(64, 32), (128, 64)] {
+        for clip in [false, true] {
+            let mut screen = Framebuffer::new(width, height);
+            let mut reference = Reference::new(width, height);
+            for _ in 0..2000 {
+                let (x, y) = (rng.gen_range(0..256), rng.gen_range(0..256));
+                let sprite: Vec<u8> = (0..rng.gen_range(1..16)).map(|_| rng.gen()).collect();
+                let collision = screen.draw_sprite(x, y, &sprite, clip);
+                assert_eq!(collision, reference.draw_sprite(x, y, &sprite, clip), "VF at ({}, {})", x, y);
+            }
+            assert!(screen.pixels().eq(reference.pixels.iter().copied()), "{}x{} clip {}", width, height, clip);
+        }
+    }
+}
+
+#[test]
+fn dirty_until_taken() {
+    let mut screen = Framebuffer::new(64, 32);
+    assert!(screen.take_dirty());
+    assert!(!screen.take_dirty());
+    screen.draw_sprite(0, 0, &[], false);
+    assert!(!screen.take_dirty());
+    screen.draw_sprite(0, 0, &[0x80], false);
+    assert!(screen.take_dirty());
+    screen.clear();
+    assert!(screen.take_dirty());
+}
+
+#[test]
+fn dxyn_sets_vf_rather_than_or_ing_into_it() {
+    let mut chip8 = common::machine(&VF_ROM);
+    for _ in 0..7 {
+        chip8.step();
+    }
+    let cpu = chip8.cpu();
+    assert!(cpu.fault.is_none(), "{:?}", cpu.fault);
+    assert_eq!(cpu.pc, 0x20E);
+    assert_eq!(cpu.v[0xE], 0);
+}
+
+#[test]
+fn equality_ignores_whether_the_change_was_taken() {
+    let mut a = Framebuffer::new(64, 32);
+    let b = Framebuffer::new(64, 32);
+    a.take_dirty();
+    assert_eq!(a, b);
+    a.draw_sprite(0, 0, &[0x80], false);
+    assert_ne!(a, b);
+    a.draw_sprite(0, 0, &[0x80], false);
+    assert_eq!(a, b);
+    assert_ne!(Framebuffer::new(64, 32), Framebuffer::new(128, 64));
+}
@@ -0,0 +1,206 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread::{self, JoinHandle};
+
+mod common;
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::gdb::GdbServer;
+use common::SUBROUTINE_ROM;
+
+// A scripted stand-in for gdb.
+struct Client {
+    stream: TcpStream,
+    ack: bool,
+}
+
+impl Client {
+    fn connect() -> (Self, JoinHandle<CHIPMachine>) {
+        let server = GdbServer::bind(common::machine(&SUBROUTINE_ROM), 0).unwrap();
+        let addr = server.local_addr().unwrap();
+        let thread = thread::spawn(move || server.serve().unwrap());
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        (Self { stream, ack: true }, thread)
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    // Sends a packet and returns the reply.
+    fn send(&mut self, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+        if self.ack {
+            assert_eq!(self.byte(), b'+', "{} was not acked", data);
+        }
+        self.reply()
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(self.byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(checksum, data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)));
+        if self.ack {
+            self.stream.write_all(b"+").unwrap();
+        }
+        String::from_utf8(data).unwrap()
+    }
+
+    fn detach(mut self, thread: JoinHandle<CHIPMachine>) -> CHIPMachine {
+        assert_eq!(self.send("D"), "OK");
+        thread.join().unwrap()
+    }
+}
+
+#[test]
+fn describes_the_target() {
+    let (mut gdb, thread) = Client::connect();
+    assert!(gdb.send("qSupported:multiprocess+;swbreak+").contains("qXfer:features:read+"));
+    assert_eq!(gdb.send("?"), "S05");
+
+    // Read the description in small pieces, the way gdb does for large ones.
+    let mut xml = String::new();
+    loop {
+        let chunk = gdb.send(&format!("qXfer:features:read:target.xml:{:x},40", xml.len()));
+        xml.push_str(&chunk[1..]);
+        if chunk.starts_with('l') {
+            break;
+        }
+        assert!(chunk.starts_with('m'));
+    }
+    assert!(xml.contains(r#"<feature name="org.chip8.core">"#));
+    assert_eq!(xml.matches("<reg ").count(), 21);
+    assert!(xml.contains(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#));
+
+    // V0-VF, then I, PC, SP, DT and ST.
+    assert_eq!(gdb.send("g"), format!("{}{}{}{}", "00".repeat(16), "0000", "0002", "000000"));
+    assert_eq!(gdb.send("p11"), "0002");
+    assert_eq!(gdb.send("qXfer:features:read:other.xml:0,40"), "");
+    gdb.detach(thread);
+}
+
+#[test]
+fn breakpoints_steps_and_interrupts() {
+    let (mut gdb, thread) = Client::connect();
+    assert_eq!(gdb.send("Z0,20a,2"), "OK");
+    assert_eq!(gdb.send("c"), "T05swbreak:;");
+    assert_eq!(gdb.send("p11"), "0a02");
+    assert_eq!(gdb.send("p12"), "01");
+    assert_eq!(gdb.send("z0,20a,2"), "OK");
+
+    assert_eq!(gdb.send("vCont?"), "vCont;c;s");
+    assert_eq!(gdb.send("vCont;s:1"), "S05");
+    assert_eq!(gdb.send("s"), "S05");
+    assert_eq!(gdb.send("p11"), "0402");
+    assert_eq!(gdb.send("p1"), "05");
+
+    // The ROM ends in a loop that never stops by itself.
+    write!(gdb.stream, "$c#63").unwrap();
+    assert_eq!(gdb.byte(), b'+');
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(gdb.reply(), "S02");
+    assert_eq!(gdb.send("p11"), "0802");
+    gdb.detach(thread);
+}
+
+#[test]
+fn watchpoints_stop_after_the_access() {
+    let (mut gdb, thread) = Client::connect();
+    assert_eq!(gdb.send("Z3,300,1"), "OK");
+    assert_eq!(gdb.send("Z2,301,1"), "OK");
+    assert_eq!(gdb.send("Z2,300,1"), "OK");
+    assert_eq!(gdb.send("c"), "T05watch:300;");
+    assert_eq!(gdb.send("p11"), "0802");
+    assert_eq!(gdb.send("m300,1"), "01");
+
+    assert_eq!(gdb.send("z2,300,1"), "OK");
+    assert_eq!(gdb.send("P11=0602"), "OK");
+    assert_eq!(gdb.send("Z4,2ff,2"), "OK");
+    assert_eq!(gdb.send("vCont;c"), "T05awatch:300;");
+    assert_eq!(gdb.send("Z1,1234,2"), "OK");
+    gdb.detach(thread);
+}
+
+#[test]
+fn watchpoints_hit_by_a_faulting_instruction_are_printed() {
+    let (mut gdb, thread) = Client::connect();
+    // A store in the last instruction slot, so PC runs off the end of RAM.
+    assert_eq!(gdb.send("Mffe,2:f055"), "OK");
+    assert_eq!(gdb.send("P11=fe0f"), "OK");
+    assert_eq!(gdb.send("P10=0003"), "OK");
+    assert_eq!(gdb.send("P0=2a"), "OK");
+    assert_eq!(gdb.send("Z2,300,1"), "OK");
+    let output = gdb.send("s");
+    let text: Vec<u8> = (1..output.len()).step_by(2).map(|at| u8::from_str_radix(&output[at..at + 2], 16).unwrap()).collect();
+    assert_eq!(String::from_utf8(text).unwrap(), "Watchpoint 1: 0x300 00 -> 2A at 0xFFE\n");
+    assert_eq!(gdb.reply(), "S0b");
+    gdb.detach(thread);
+}
+
+#[test]
+fn packets_that_arent_ascii_are_unsupported() {
+    let (mut gdb, thread) = Client::connect();
+    gdb.stream.write_all(b"$\xff#ff").unwrap();
+    assert_eq!(gdb.byte(), b'+');
+    assert_eq!(gdb.reply(), "");
+    assert_eq!(gdb.send("p11"), "0002");
+    gdb.detach(thread);
+}
+
+#[test]
+fn registers_and_memory_can_be_written() {
+    let (mut gdb, thread) = Client::connect();
+    assert_eq!(gdb.send("QStartNoAckMode"), "OK");
+    gdb.ack = false;
+
+    assert_eq!(gdb.send("M300,3:abcdef"), "OK");
+    assert_eq!(gdb.send("m2ff,5"), "00abcdef00");
+    assert_eq!(gdb.send("mfff,2"), "E0e");
+    assert_eq!(gdb.send("M300,2:ab"), "E0e");
+
+    assert_eq!(gdb.send("P0=7f"), "OK");
+    assert_eq!(gdb.send("P10=3412"), "OK");
+    assert_eq!(gdb.send("P11=ff0f"), "E01");
+    assert_eq!(gdb.send("P12=11"), "E01");
+    let registers = format!("{}{}{}{}", "01".repeat(16), "0003", "0402", "000a0b");
+    assert_eq!(gdb.send(&format!("G{}", registers)), "OK");
+    assert_eq!(gdb.send("g"), registers);
+
+    let chip8 = gdb.detach(thread);
+    assert_eq!(chip8.cpu().ram[0x300..0x303], [0xAB, 0xCD, 0xEF]);
+    assert_eq!((chip8.cpu().i, chip8.cpu().pc), (0x300, 0x204));
+    assert_eq!((chip8.cpu().delay_timer, chip8.cpu().sound_timer), (0x0A, 0x0B));
+}
+
+#[test]
+fn monitor_breakpoints_have_conditions_and_logs() {
+    let (mut gdb, thread) = Client::connect();
+    let monitor = |command: &str| format!("qRcmd,{}", command.bytes().map(|b| format!("{:02x}", b)).collect::<String>());
+    let text = |hex: &str| String::from_utf8((0..hex.len()).step_by(2).map(|at| u8::from_str_radix(&hex[at..at + 2], 16).unwrap()).collect()).unwrap();
+    let reply = gdb.send(&monitor("break op Fx55 if v0 == 1 && sp == 0"));
+    assert_eq!(text(&reply), "Breakpoint at op Fx55 if v0 == 1 && sp == 0\n");
+    let reply = gdb.send(&monitor("log 20c V1 is {v1}"));
+    assert_eq!(text(&reply), "Breakpoint at 0x20C, log \"V1 is {v1}\"\n");
+    assert_eq!(text(&gdb.send(&monitor("print v0 +"))), "Error: unexpected end of expression\n");
+
+    // Log output comes as console output before the stop reply.
+    let output = gdb.send("c");
+    assert_eq!(text(output.strip_prefix('O').unwrap()), "V1 is 0x5\n");
+    assert_eq!(gdb.reply(), "S05");
+    assert_eq!(gdb.send("p11"), "0602");
+    // Stopped before the store.
+    assert_eq!(text(&gdb.send(&monitor("print ram[i] | v1 << 4"))), "0x50 (80)\n");
+    gdb.detach(thread);
+}
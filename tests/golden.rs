@@ -0,0 +1,67 @@
+use std::fs;
+
+use chip8::golden::{self, Expected, RomTest};
+use chip8::options::Options;
+use chip8::script::InputScript;
+
+const ROM: &str = "roms/test_opcode.ch8";
+const GOLDEN: &str = "tests/golden/test_opcode.txt";
+
+fn opcode_test(expected: Expected) -> RomTest {
+    RomTest {
+        frames: 60,
+        input: InputScript::default(),
+        expected,
+    }
+}
+
+fn run(test: &RomTest) -> golden::Outcome {
+    let options = Options {
+        rom: String::from(ROM),
+        seed: Some(0),
+        ..Options::default()
+    };
+    test.run(&mut options.machine().unwrap())
+}
+
+#[test]
+fn test_opcode_matches_golden_screen() {
+    let golden = fs::read_to_string(GOLDEN).unwrap();
+    let outcome = run(&opcode_test(Expected::Screen(golden)));
+    assert!(outcome.passed, "{}", outcome.report);
+}
+
+// Where the ROM prints its results and the "OK" it prints for a pass.
+const RESULT_ROWS: [usize; 6] = [1, 6, 11, 16, 21, 26];
+const RESULT_COLUMNS: [usize; 3] = [10, 32, 52];
+const OK: [&str; 4] = ["###.#.#", "#.#.##.", "#.#.#.#", "###.#.#"];
+
+// A golden screen is only worth matching if the emulator passed every test on it.
+#[test]
+fn golden_screen_shows_every_opcode_passing() {
+    let golden = fs::read_to_string(GOLDEN).unwrap();
+    let lines: Vec<&str> = golden.lines().collect();
+    for y in RESULT_ROWS {
+        for x in RESULT_COLUMNS {
+            let result: Vec<&str> = lines[y..y + OK.len()].iter().map(|line| &line[x..x + OK[0].len()]).collect();
+            assert_eq!(result, OK, "result at ({}, {}) isn't OK", x, y);
+        }
+    }
+}
+
+#[test]
+fn test_opcode_matches_golden_hash() {
+    let outcome = run(&opcode_test(Expected::Hash(0x56fc_410c_7842_93f3)));
+    assert!(outcome.passed, "{}", outcome.report);
+}
+
+#[test]
+fn mismatch_marks_differing_pixels() {
+    let golden = fs::read_to_string(GOLDEN).unwrap();
+    let blank = golden.replace('#', ".");
+    let outcome = run(&opcode_test(Expected::Screen(blank)));
+    assert!(!outcome.passed);
+    // Every lit pixel should have been dark, nothing is missing.
+    let map: String = outcome.report.lines().skip(1).collect();
+    assert!(map.contains('+') && !map.contains('-'), "{}", outcome.report);
+}
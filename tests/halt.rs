@@ -0,0 +1,55 @@
+mod common;
+
+use chip8::chip_machine::{CHIPMachine, Status};
+use chip8::headless;
+use chip8::script::InputScript;
+
+// 0x200: V0 = 5, then jump to itself.
+const HALT_ROM: [u8; 4] = [0x60, 0x05, 0x12, 0x02];
+
+// 0x200: DT = 0x10, then poll DT into V1 until it reads 0, V2 += 1 and start over.
+const SKIP_3XKK_ROM: [u8; 14] = [0x60, 0x10, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04, 0x72, 0x01, 0x12, 0x00];
+
+// 0x200: DT = 0x10, then poll DT into V1 while it reads 1, V2 += 1 and start over.
+const SKIP_4XKK_ROM: [u8; 14] = [0x60, 0x10, 0xF0, 0x15, 0xF1, 0x07, 0x41, 0x01, 0x12, 0x04, 0x72, 0x01, 0x12, 0x00];
+
+// Runs a frame one instruction at a time, with nothing skipped.
+fn step_frame(chip8: &mut CHIPMachine) {
+    while !chip8.step() {}
+}
+
+#[test]
+fn self_jump_halts() {
+    let mut chip8 = common::machine(&HALT_ROM);
+    chip8.run_frame();
+    assert_eq!(chip8.status(), Status::Halted(0x202));
+    assert_eq!(chip8.cpu().v[0], 5);
+}
+
+#[test]
+fn headless_runs_stop_once_halted() {
+    let mut chip8 = common::machine(&HALT_ROM);
+    assert_eq!(headless::run_frames(&mut chip8, 600, &InputScript::default()), 1);
+    assert_eq!(chip8.frame_count, 1);
+}
+
+#[test]
+fn timer_polling_skips_to_the_same_state() {
+    for rom in [&SKIP_3XKK_ROM[..], &SKIP_4XKK_ROM[..]] {
+        let mut skipped = common::machine(rom);
+        let mut stepped = common::machine(rom);
+        for frame in 0..120 {
+            skipped.run_frame();
+            step_frame(&mut stepped);
+            assert_eq!(skipped.status(), Status::Running);
+            let (a, b) = (skipped.cpu(), stepped.cpu());
+            assert_eq!(
+                (a.pc, a.v, a.i, a.delay_timer, skipped.cycle_count),
+                (b.pc, b.v, b.i, b.delay_timer, stepped.cycle_count),
+                "after frame {}",
+                frame
+            );
+        }
+        assert!(skipped.cpu().v[2] > 0);
+    }
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use chip8::terminal::{Glyphs, TerminalRenderer};
+use chip8::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+
+// 0x200: 00FF, then loop forever.
+const HIRES_ROM: [u8; 4] = [0x00, 0xFF, 0x12, 0x02];
+
+// 0x200: 00FF, 00FE, then loop forever.
+const LORES_ROM: [u8; 6] = [0x00, 0xFF, 0x00, 0xFE, 0x12, 0x04];
+
+#[test]
+fn hires_rom_draws_a_128x64_frame() {
+    let mut chip8 = common::machine(&HIRES_ROM);
+    chip8.run_frame();
+    assert_eq!(chip8.size(), (HIRES_WIDTH, HIRES_HEIGHT));
+    let (width, height) = chip8.output_size();
+    assert_eq!((width, height), (HIRES_WIDTH, HIRES_HEIGHT));
+    let mut screen = vec![0; width * height * 4];
+    chip8.draw(&mut screen);
+    assert_eq!(chip8.indexed_snapshot(1).len(), HIRES_WIDTH * HIRES_HEIGHT);
+}
+
+#[test]
+fn lores_switches_back_to_64x32() {
+    let mut chip8 = common::machine(&LORES_ROM);
+    chip8.run_frame();
+    assert_eq!(chip8.size(), (WIDTH, HEIGHT));
+    let mut screen = vec![0; WIDTH * HEIGHT * 4];
+    chip8.draw(&mut screen);
+}
+
+#[test]
+fn terminal_follows_the_resolution() {
+    let mut chip8 = common::machine(&HIRES_ROM);
+    let mut renderer = TerminalRenderer::new(Glyphs::HalfBlock, WIDTH, HEIGHT);
+    chip8.run_frame();
+    let mut out = Vec::new();
+    renderer.render(&chip8, &mut out).unwrap();
+    // A half block cell holds two rows, so the bottom row is at 32.
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.contains("\x1b[32;1H"), "{:?}", text);
+}
@@ -0,0 +1,83 @@
+use chip8::options::Options;
+use chip8::osd::{Osd, MIN_WIDTH};
+
+const BACKGROUND: [u8; 4] = [0x40, 0x80, 0xC0, 0xFF];
+const WHITE: [u8; 4] = [0xFF; 4];
+
+fn options(args: &[&str]) -> Options {
+    let args = ["chip8"].iter().chain(args).map(|arg| arg.to_string());
+    Options::parse(args).unwrap()
+}
+
+fn screen(width: usize, height: usize) -> Vec<u8> {
+    BACKGROUND.repeat(width * height)
+}
+
+// Rows of the frame with any text drawn in them.
+fn text_rows(screen: &[u8], width: usize) -> Vec<usize> {
+    let rows = screen.chunks_exact(4 * width).enumerate();
+    rows.filter(|(_, row)| row.chunks_exact(4).any(|pixel| pixel == WHITE)).map(|(y, _)| y).collect()
+}
+
+#[test]
+fn osd_upscales_small_frames_until_text_fits() {
+    let plain = options(&["roms/test_opcode.ch8"]).machine().unwrap();
+    assert_eq!(plain.output_size(), (64, 32));
+    let osd = options(&["--osd", "roms/test_opcode.ch8"]).machine().unwrap();
+    assert!(osd.output_size().0 >= MIN_WIDTH, "{:?}", osd.output_size());
+}
+
+#[test]
+fn osd_keeps_a_filter_that_is_already_wide_enough() {
+    let chip8 = options(&["--osd", "--filter", "scale3x", "roms/test_opcode.ch8"]).machine().unwrap();
+    assert_eq!(chip8.output_size(), (192, 96));
+}
+
+#[test]
+fn nothing_to_show_leaves_the_frame_alone() {
+    let (width, height) = (256, 128);
+    let mut frame = screen(width, height);
+    Osd::default().draw(&mut frame, width, height);
+    assert_eq!(frame, screen(width, height));
+}
+
+#[test]
+fn messages_are_drawn_at_the_bottom() {
+    let (width, height) = (256, 128);
+    let mut frame = screen(width, height);
+    let mut osd = Osd::default();
+    osd.message("STATE 3 SAVED");
+    osd.draw(&mut frame, width, height);
+    let rows = text_rows(&frame, width);
+    assert!(!rows.is_empty());
+    assert!(rows.iter().all(|&y| y >= height - 7), "{:?}", rows);
+    // The box behind the text is darkened.
+    assert_eq!(&frame[4 * (width * (height - 7))..][..4], [0x10, 0x20, 0x30, 0xFF]);
+}
+
+#[test]
+fn info_is_drawn_at_the_top_only_when_shown() {
+    let (width, height) = (256, 128);
+    let mut osd = Osd::default();
+    osd.set_info("TEST_OPCODE MODERN");
+    let mut frame = screen(width, height);
+    osd.draw(&mut frame, width, height);
+    assert!(text_rows(&frame, width).is_empty());
+
+    osd.show_info = true;
+    osd.draw(&mut frame, width, height);
+    let rows = text_rows(&frame, width);
+    assert!(!rows.is_empty() && rows.iter().all(|&y| y < 7), "{:?}", rows);
+}
+
+#[test]
+fn text_grows_with_the_frame() {
+    let (width, height) = (512, 256);
+    let mut frame = screen(width, height);
+    let mut osd = Osd::default();
+    osd.message("SPEED 200%");
+    osd.draw(&mut frame, width, height);
+    let rows = text_rows(&frame, width);
+    assert!(rows.iter().all(|&y| y >= height - 14), "{:?}", rows);
+    assert!(rows.len() > 5, "{:?}", rows);
+}
@@ -0,0 +1,19 @@
+use chip8::palette::{parse_color, Palette};
+
+#[test]
+fn colours_are_six_hex_digits() {
+    assert_eq!(parse_color("#e69f00"), Ok([0xE6, 0x9F, 0x00, 0xFF]));
+    assert_eq!(parse_color("56B4E9"), Ok([0x56, 0xB4, 0xE9, 0xFF]));
+    for text in ["+12345", "#+12345", "-12345", "#12345", "#1234567", "#12 345", "#gg0000", "#é2345", ""] {
+        assert_eq!(parse_color(text), Err(format!("invalid colour '{}', expected #rrggbb", text)));
+    }
+}
+
+#[test]
+fn two_colour_palettes_draw_both_planes_in_the_foreground() {
+    let palette = Palette::from_hex("mono", &["#000000", "#ffffff"]).unwrap();
+    assert_eq!(palette.color(0b00), [0, 0, 0, 0xFF]);
+    assert_eq!(palette.color(0b11), [0xFF, 0xFF, 0xFF, 0xFF]);
+    assert!(Palette::from_hex("three", &["000000", "111111", "222222"]).is_err());
+    assert!(Palette::from_hex("bad", &["000000", "+fffff"]).is_err());
+}
@@ -0,0 +1,49 @@
+use chip8::framebuffer::Framebuffer;
+use chip8::palette::Palette;
+use chip8::phosphor::{Persistence, Phosphor};
+
+// The red channel of the top left pixel after drawing `frame`.
+fn red(phosphor: &mut Phosphor, display: &Framebuffer, frame: u64) -> u8 {
+    let palette = Palette::new("grey", [[0, 0, 0, 0xFF], [0xFF, 0xFF, 0xFF, 0xFF], [0; 4], [0; 4]]);
+    let mut screen = vec![0; 4 * display.width() * display.height()];
+    phosphor.apply(display, &palette, &mut screen, frame);
+    screen[0]
+}
+
+#[test]
+fn fade_follows_frames_not_draws() {
+    let mut display = Framebuffer::new(8, 1);
+    let mut phosphor = Phosphor::new(Persistence::Fade(4), 8);
+    display.draw_sprite(0, 0, &[0x80], false);
+    assert_eq!(red(&mut phosphor, &display, 10), 0xFF);
+
+    // 255 / 5 off per frame, however often the same frame is drawn.
+    display.clear();
+    assert_eq!(red(&mut phosphor, &display, 11), 204);
+    assert_eq!(red(&mut phosphor, &display, 11), 204);
+    assert!(phosphor.is_fading());
+    // Skipped frames still count.
+    assert_eq!(red(&mut phosphor, &display, 13), 102);
+    assert_eq!(red(&mut phosphor, &display, 20), 0);
+    assert!(!phosphor.is_fading());
+}
+
+#[test]
+fn deflicker_keeps_pixels_for_one_frame() {
+    let mut display = Framebuffer::new(8, 1);
+    let mut phosphor = Phosphor::new(Persistence::Deflicker, 8);
+    display.draw_sprite(0, 0, &[0x80], false);
+    assert_eq!(red(&mut phosphor, &display, 0), 0xFF);
+    display.clear();
+    assert_eq!(red(&mut phosphor, &display, 1), 0xFF);
+    assert_eq!(red(&mut phosphor, &display, 1), 0xFF);
+    assert_eq!(red(&mut phosphor, &display, 2), 0);
+}
+
+#[test]
+fn modes_parse() {
+    assert_eq!(Persistence::parse("fade:2"), Ok(Persistence::Fade(2)));
+    assert_eq!(Persistence::parse("fade"), Ok(Persistence::Fade(4)));
+    assert!(Persistence::parse("fade:0").is_err());
+    assert!(Persistence::parse("glow").is_err());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::profile::Profiler;
+
+// 0x200: call the drawing subroutine, set the delay timer to 3 and poll it
+// until it runs out, then start over.
+// 0x20E: draw the 0 glyph and return.
+const ROM: [u8; 20] = [
+    0x22, 0x0E, 0x60, 0x03, 0xF0, 0x15, 0xF0, 0x07, 0x30, 0x00, 0x12, 0x06, 0x12, 0x00, 0xA0, 0x00, 0xD0, 0x15,
+    0x00, 0xEE,
+];
+
+fn profiled(frames: usize) -> (CHIPMachine, Profiler) {
+    let mut chip8 = common::machine(&ROM);
+    let mut profiler = Profiler::default();
+    for _ in 0..frames {
+        profiler.run_frame(&mut chip8);
+    }
+    (chip8, profiler)
+}
+
+#[test]
+fn counts_work_and_lag_per_frame() {
+    let (_, profiler) = profiled(6);
+    let frames: Vec<(u64, u64)> = profiler.frames().iter().map(|frame| (frame.instructions, frame.draws)).collect();
+    // Polling the delay timer isn't work, and the frames spent on it are lag.
+    assert_eq!(frames, [(6, 1), (0, 0), (0, 0), (7, 1), (0, 0), (0, 0)]);
+    assert!(profiler.frames().iter().all(|frame| frame.waited));
+    assert_eq!(profiler.frames().iter().filter(|frame| frame.lag()).count(), 4);
+}
+
+#[test]
+fn attributes_instructions_to_subroutines() {
+    let (chip8, profiler) = profiled(12);
+    assert_eq!(profiler.count(0x210), 4);
+    assert_eq!(profiler.folded(), "0x200 984\n0x200;0x20E 12\n");
+
+    let report = profiler.report(&chip8);
+    assert!(report.contains("  lag        8 without a sprite drawn: 1-2, 4-5, 7-8, 10-11\n"), "{}", report);
+    assert!(report.contains("         324  32.5%  0x206                F007  LD V0, DT\n"), "{}", report);
+    assert!(report.contains("          12   1.2%          12   1.2%  0x20E\n"), "{}", report);
+}
+
+#[test]
+fn labels_name_subroutines() {
+    let (_, profiler) = profiled(3);
+    let labels = [(String::from("main"), 0x200), (String::from("draw"), 0x20E)].into_iter().collect();
+    assert_eq!(profiler.with_labels(labels).folded(), "main 246\nmain;draw 3\n");
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use std::fs::{self, File};
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::recorder::Recorder;
+
+// 0x200: I = 0x200, flip the top left pixels, then wait a frame on the
+// delay timer and flip them again.
+const ROM: [u8; 16] = [
+    0xA2, 0x00, 0xD0, 0x01, 0x61, 0x01, 0xF1, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x08, 0x12, 0x02,
+];
+
+// 0x200: I = 0x200, draw its first byte, switch to 128x64, draw it again,
+// switch back to 64x32, then loop forever.
+const SWITCH_ROM: [u8; 12] = [0xA2, 0x00, 0xD0, 0x01, 0x00, 0xFF, 0xD0, 0x01, 0x00, 0xFE, 0x12, 0x0A];
+
+fn temp_gif(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("chip8-record-{}-{}.gif", std::process::id(), name))
+}
+
+// The delays of the GIF recorded over `frames` frames.
+fn delays(frames: usize) -> Vec<u16> {
+    let mut chip8 = common::machine(&ROM);
+    let path = temp_gif(&frames.to_string());
+    let mut recorder = Recorder::start_as(&chip8, &path, 1).unwrap();
+    for _ in 0..frames {
+        chip8.run_frame();
+        recorder.push(&chip8).unwrap();
+    }
+    recorder.finish().unwrap();
+    let mut decoder = gif::DecodeOptions::new().read_info(File::open(&path).unwrap()).unwrap();
+    let mut delays = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+    }
+    fs::remove_file(&path).unwrap();
+    delays
+}
+
+#[test]
+fn delays_are_never_below_two_centiseconds() {
+    // The screen changes every frame, faster than a GIF can show.
+    let delays = delays(60);
+    assert!(delays.iter().all(|&delay| delay >= 2), "{:?}", delays);
+    assert_eq!(delays.iter().map(|&delay| delay as u32).sum::<u32>(), 100);
+    assert!(delays.len() > 20, "{:?}", delays);
+
+    // A single frame still lasts long enough.
+    assert_eq!(self::delays(1), [2]);
+}
+
+#[test]
+fn switching_resolution_keeps_recording() {
+    let mut chip8 = common::machine(&SWITCH_ROM);
+    let path = temp_gif("switch");
+    let mut recorder = Recorder::start_as(&chip8, &path, 1).unwrap();
+    let mut run = |chip8: &mut CHIPMachine, steps| {
+        for _ in 0..steps {
+            chip8.step();
+        }
+        // Long enough for every picture to get a GIF frame of its own.
+        for _ in 0..10 {
+            recorder.push(chip8).unwrap();
+        }
+    };
+    run(&mut chip8, 2);
+    run(&mut chip8, 2);
+    run(&mut chip8, 1);
+    recorder.finish().unwrap();
+
+    let mut decoder = gif::DecodeOptions::new().read_info(File::open(&path).unwrap()).unwrap();
+    assert_eq!((decoder.width(), decoder.height()), (128, 64));
+    let mut lit = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!((frame.width, frame.height), (128, 64));
+        let pixels = frame.buffer.iter().enumerate().filter(|(_, &index)| index != 0);
+        lit.push(pixels.map(|(i, _)| (i % 128, i / 128)).collect::<Vec<_>>());
+    }
+    fs::remove_file(&path).unwrap();
+    // 0xA2 is 1010_0010, drawn two by two at 64x32 and as is at 128x64.
+    let lowres: Vec<_> = [0, 1].iter().flat_map(|&y| [0, 1, 4, 5, 12, 13].map(|x| (x, y))).collect();
+    assert_eq!(lit, [lowres, vec![(0, 0), (2, 0), (6, 0)], vec![]]);
+}
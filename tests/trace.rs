@@ -0,0 +1,178 @@
+mod common;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::disasm::{classify, disassemble, Class};
+use chip8::processor::Fault;
+use chip8::trace::{BinaryReader, Change, Entry, Filter, Format, Tracer};
+use common::SUBROUTINE_ROM;
+
+// A trace destination the test can still read after handing it over.
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn bytes(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+// Runs the first `steps` instructions of SUBROUTINE_ROM under `tracer` and drops it.
+fn run(tracer: Tracer, steps: usize) -> CHIPMachine {
+    let mut chip8 = common::machine(&SUBROUTINE_ROM);
+    chip8.set_tracer(tracer);
+    for _ in 0..steps {
+        chip8.cycle();
+    }
+    drop(chip8.take_tracer());
+    chip8
+}
+
+#[test]
+fn disassembles_every_class() {
+    let cases = [
+        (0x00E0, "CLS", Class::Draw),
+        (0x00EE, "RET", Class::Flow),
+        (0x00C4, "SCD 4", Class::Draw),
+        (0x1208, "JP 0x208", Class::Flow),
+        (0x3A7F, "SE VA, 0x7F", Class::Skip),
+        (0x6105, "LD V1, 0x05", Class::Load),
+        (0x8126, "SHR V1, V2", Class::Alu),
+        (0xA300, "LD I, 0x300", Class::Load),
+        (0xB400, "JP V0, 0x400", Class::Flow),
+        (0xC0FF, "RND V0, 0xFF", Class::Alu),
+        (0xD015, "DRW V0, V1, 5", Class::Draw),
+        (0xE29E, "SKP V2", Class::Key),
+        (0xF30A, "LD V3, K", Class::Key),
+        (0xF415, "LD DT, V4", Class::Timer),
+        (0xF033, "LD B, V0", Class::Memory),
+        (0xF565, "LD V5, [I]", Class::Memory),
+        (0xF630, "LD HF, V6", Class::Load),
+        (0x0123, "DW 0x0123", Class::Unknown),
+        (0x8AB9, "DW 0x8AB9", Class::Unknown),
+    ];
+    for (op, text, class) in cases {
+        assert_eq!((disassemble(op).as_str(), classify(op)), (text, class), "{:04X}", op);
+    }
+}
+
+#[test]
+fn text_trace_lists_changed_registers() {
+    let out = Shared::default();
+    run(Tracer::stream(out.clone(), Format::Text).unwrap(), 6);
+    let text = String::from_utf8(out.bytes()).unwrap();
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    assert_eq!(
+        lines,
+        [
+            "         0 0x200 6001  LD V0, 0x01          V0=01",
+            "         1 0x202 220A  CALL 0x20A           SP=1",
+            "         2 0x20A 6105  LD V1, 0x05          V1=05",
+            "         3 0x20C 00EE  RET                  SP=0",
+            "         4 0x204 A300  LD I, 0x300          I=300",
+            "         5 0x206 F055  LD [I], V0",
+        ]
+    );
+}
+
+#[test]
+fn filters_by_address_and_class() {
+    let tracer = Tracer::ring(100).with_filter(Filter::parse("200-209,load").unwrap());
+    let mut chip8 = common::machine(&SUBROUTINE_ROM);
+    chip8.set_tracer(tracer);
+    for _ in 0..8 {
+        chip8.cycle();
+    }
+    let pcs: Vec<u16> = chip8.tracer().unwrap().entries().map(|entry| entry.pc).collect();
+    // 0x20A is outside the range and 0x202 isn't a load.
+    assert_eq!(pcs, [0x200, 0x204]);
+
+    assert!(Filter::parse("2A4,draw,flow").is_ok());
+    assert!(Filter::parse("sprites").is_err());
+}
+
+#[test]
+fn ring_keeps_the_last_entries() {
+    let mut chip8 = common::machine(&SUBROUTINE_ROM);
+    chip8.set_tracer(Tracer::ring(3));
+    for _ in 0..10 {
+        chip8.cycle();
+    }
+    let entries: Vec<(u64, u16)> = chip8.tracer().unwrap().entries().map(|entry| (entry.cycle, entry.pc)).collect();
+    assert_eq!(entries, [(7, 0x208), (8, 0x208), (9, 0x208)]);
+}
+
+#[test]
+fn binary_trace_reads_back() {
+    let out = Shared::default();
+    let filter = Filter::parse("200-209").unwrap();
+    run(Tracer::stream(out.clone(), Format::Binary).unwrap().with_filter(filter), 300);
+    let bytes = out.bytes();
+    let entries: Vec<Entry> = BinaryReader::new(bytes.as_slice()).unwrap().map(Result::unwrap).collect();
+    assert_eq!(entries.len(), 298);
+    assert_eq!(
+        entries[1],
+        Entry {
+            cycle: 1,
+            pc: 0x202,
+            opcode: 0x220A,
+            changes: vec![Change::Sp(1)],
+            fault: None,
+        }
+    );
+    // Skipped entries still count.
+    assert_eq!((entries[2].cycle, entries[2].pc), (4, 0x204));
+    assert_eq!(entries[297].cycle, 299);
+    // Far smaller than the text.
+    assert!(bytes.len() < 298 * 10, "{} bytes", bytes.len());
+
+    assert!(BinaryReader::new(&b"C8TR"[..]).is_err());
+    let truncated = &bytes[..bytes.len() - 2];
+    assert!(BinaryReader::new(truncated).unwrap().any(|entry| entry.is_err()));
+}
+
+#[test]
+fn malformed_binary_traces_are_errors() {
+    let header = b"C8TR\x01";
+    // A cycle delta that goes on past 64 bits.
+    let long = [&header[..], &[0xFF; 10], &[0x01]].concat();
+    let err = BinaryReader::new(long.as_slice()).unwrap().next().unwrap().unwrap_err();
+    assert_eq!(err, "invalid trace: cycle delta longer than 64 bits");
+
+    // Two deltas adding up to more than 64 bits of cycles.
+    let entry = |delta: &[u8]| [delta, &[0x00, 0x02, 0xE0, 0x00, 0x00, 0x00, 0x00]].concat();
+    let max = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+    let overflow = [&header[..], &entry(&max), &entry(&[0x01])].concat();
+    let entries: Vec<_> = BinaryReader::new(overflow.as_slice()).unwrap().collect();
+    assert_eq!(entries[0].as_ref().map(|entry| entry.cycle), Ok(u64::MAX));
+    assert_eq!(entries[1], Err(String::from("invalid trace: cycle count past 2^64")));
+}
+
+#[test]
+fn ring_dumps_faults_in_binary() {
+    // 00EE with nothing to return to.
+    let out = Shared::default();
+    let mut chip8 = common::machine(&[0x60, 0x01, 0x00, 0xEE]);
+    chip8.set_tracer(Tracer::ring(8).dump_to(out.clone(), Format::Binary));
+    for _ in 0..4 {
+        chip8.cycle();
+    }
+    drop(chip8.take_tracer());
+    let bytes = out.bytes();
+    let entries: Vec<Entry> = BinaryReader::new(bytes.as_slice()).unwrap().map(Result::unwrap).collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].fault, Some(Fault::StackUnderflow));
+    assert_eq!(entries[1].to_string().trim_end(), "         1 0x202 00EE  RET                  fault: stack underflow");
+}
@@ -0,0 +1,81 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::options::Options;
+use chip8::screenshot;
+use chip8::upscale::Filter;
+
+const ROM: &str = "roms/test_opcode.ch8";
+
+// The test_opcode screen after a second.
+fn machine(filter: Filter) -> CHIPMachine {
+    let options = Options {
+        rom: String::from(ROM),
+        filter,
+        ..Options::default()
+    };
+    let mut chip8 = options.machine().unwrap();
+    for _ in 0..60 {
+        chip8.run_frame();
+    }
+    chip8
+}
+
+// The screen through `filter`, as the window shows it.
+fn check(filter: &str, name: &str) {
+    let mut chip8 = machine(Filter::parse(filter).unwrap());
+    let (width, height) = chip8.output_size();
+    let mut rgba = vec![0; 4 * width * height];
+    assert!(chip8.draw(&mut rgba));
+
+    compare(name, width, height, &rgba);
+}
+
+// Compares an image with tests/golden/<name>.png, which UPDATE_GOLDEN=1 rewrites.
+fn compare(name: &str, width: usize, height: usize, rgba: &[u8]) {
+    let path = Path::new("tests/golden").join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        screenshot::write_png(&path, width, height, rgba).unwrap();
+    }
+    let mut reader = png::Decoder::new(BufReader::new(File::open(&path).unwrap())).read_info().unwrap();
+    let mut golden = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut golden).unwrap();
+    assert_eq!((info.width as usize, info.height as usize), (width, height), "{}", name);
+    assert!(golden == rgba, "{} differs from {}", name, path.display());
+}
+
+#[test]
+fn filters_match_golden_images() {
+    check("none", "upscale-none");
+    check("nearest:2", "upscale-nearest");
+    check("scale2x", "upscale-scale2x");
+    check("scale3x", "upscale-scale3x");
+    check("scanlines", "upscale-scanlines");
+    check("grid", "upscale-grid");
+}
+
+#[test]
+fn screenshots_match_golden_image() {
+    let chip8 = machine(Filter::None);
+    let path = env::temp_dir().join(format!("chip8-screenshot-{}.png", std::process::id()));
+    screenshot::save_as(&chip8, &path, 3).unwrap();
+    let mut reader = png::Decoder::new(BufReader::new(File::open(&path).unwrap())).read_info().unwrap();
+    let mut rgba = vec![0; reader.output_buffer_size().unwrap()];
+    reader.next_frame(&mut rgba).unwrap();
+    fs::remove_file(&path).unwrap();
+    compare("screenshot-3x", 192, 96, &rgba);
+}
+
+#[test]
+fn scales_are_bounded() {
+    assert_eq!(Filter::parse("nearest:16"), Ok(Filter::Nearest(16)));
+    assert!(Filter::parse("nearest:17").is_err());
+    assert!(Filter::parse("grid:1").is_err());
+    let parse = |args: &str| Options::parse(args.split_whitespace().map(String::from));
+    assert_eq!(parse("chip8 rom.ch8 --screenshot-scale 16").unwrap().screenshot_scale, 16);
+    assert_eq!(parse("chip8 rom.ch8 --record-scale 100000").err(), Some(String::from("invalid scale '100000', expected 1 to 16")));
+    assert!(parse("chip8 rom.ch8 --screenshot-scale 0").is_err());
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use chip8::chip_machine::CHIPMachine;
+use chip8::debugger::Debugger;
+use chip8::watch::{Access, Hit, Location, Target, Trigger, Watchpoint};
+
+// 0x200: I = 0x300, V0 = 5, V1 = 7, store V0-V1, load them back, draw a
+// row from I, V0 += 1, then loop forever.
+const ROM: [u8; 16] = [
+    0xA3, 0x00, 0x60, 0x05, 0x61, 0x07, 0xF1, 0x55, 0xF1, 0x65, 0xD0, 0x11, 0x70, 0x01, 0x12, 0x0E,
+];
+
+fn machine() -> CHIPMachine {
+    common::machine(&ROM)
+}
+
+// Runs to the loop at the end and returns the hits of `watchpoint`.
+fn hits(watchpoint: &str) -> Vec<Hit> {
+    let mut chip8 = machine();
+    let words: Vec<&str> = watchpoint.split_whitespace().collect();
+    chip8.cpu_mut().watches.add(Watchpoint::parse(&words).unwrap());
+    while chip8.cpu().pc != 0x20E {
+        chip8.step();
+    }
+    chip8.cpu_mut().watches.take_hits()
+}
+
+#[test]
+fn parses_targets_and_conditions() {
+    let watchpoint = Watchpoint::parse(&["300-30f", "write", "from", "0", "to", "0x7", "log"]).unwrap();
+    assert_eq!(watchpoint.target, Target::Ram(0x300..=0x30F));
+    assert_eq!(watchpoint.trigger, Trigger::Write);
+    assert_eq!(watchpoint.to_string(), "write 0x300-0x30F from 00 to 07, log");
+    assert_eq!(Target::parse("vb"), Ok(Target::Register(Location::V(0xB))));
+    assert!(Watchpoint::parse(&["v3", "to"]).is_err());
+    assert!(Watchpoint::parse(&["vx"]).is_err());
+}
+
+#[test]
+fn rejects_ranges_that_never_fire() {
+    assert_eq!(Target::parse("fff"), Ok(Target::Ram(0xFFF..=0xFFF)));
+    assert_eq!(Target::parse("30F-300"), Err(String::from("watch range '30F-300' ends before it starts")));
+    assert_eq!(Target::parse("1000"), Err(String::from("watch target '1000' is past the end of RAM")));
+    assert_eq!(Target::parse("F00-1000"), Err(String::from("watch target 'F00-1000' is past the end of RAM")));
+}
+
+#[test]
+fn stores_and_register_writes_are_seen() {
+    let stored = hits("301");
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].to_string(), "Watchpoint 1: 0x301 00 -> 07 at 0x206");
+
+    // Loading V0 back writes the same value, which isn't a change.
+    let changed: Vec<u16> = hits("v0").iter().map(|hit| hit.pc).collect();
+    assert_eq!(changed, [0x202, 0x20C]);
+    let incremented = hits("v0 from 5 to 6");
+    assert_eq!(incremented[0].to_string(), "Watchpoint 1: V0 05 -> 06 at 0x20C");
+}
+
+#[test]
+fn loads_and_draws_read_memory() {
+    let read: Vec<(u16, Access)> = hits("300 read").iter().map(|hit| (hit.pc, hit.access)).collect();
+    assert_eq!(read, [(0x208, Access::Read), (0x20A, Access::Read)]);
+    assert_eq!(hits("i access").len(), 4);
+}
+
+#[test]
+fn debugger_breaks_and_logs() {
+    let mut chip8 = machine();
+    let mut debugger = Debugger::default();
+    assert_eq!(debugger.command(&mut chip8, "watch 300-301 log"), "Watchpoint 1: change 0x300-0x301, log");
+    assert_eq!(debugger.command(&mut chip8, "watch v0 to 6"), "Watchpoint 2: change V0 to 06");
+    debugger.command(&mut chip8, "continue");
+    assert_eq!(
+        debugger.run_frame(&mut chip8).unwrap(),
+        "Watchpoint 1: 0x300 00 -> 05 at 0x206\n\
+         Watchpoint 1: 0x301 00 -> 07 at 0x206\n\
+         Watchpoint 2: V0 05 -> 06 at 0x20C\n\
+         0x20E  120E"
+    );
+    assert_eq!(debugger.command(&mut chip8, "unwatch 1"), "Deleted watchpoint 1: change 0x300-0x301, log");
+    assert_eq!(debugger.command(&mut chip8, "watches"), "2  change V0 to 06");
+}